@@ -0,0 +1,353 @@
+//! A dynamic RSA accumulator: a single group element that commits to a set of elements, with
+//! short witnesses that an element is or isn't in the set.
+//!
+//! Elements are hashed to 256-bit primes, and the value of the set S is g^(∏ S) mod n. The
+//! manager keeps the set and the factorization of n, the verifiers only need the `Accumulator`.
+use crate::common::{mod_inv, os2ip};
+use crate::primes::baillie_psw;
+use crate::rsa::{gen_rsa_key_with, KeyGenOptions, PrimeKind, SecretRsaKey};
+use digest::Digest;
+use num::{bigint::RandBigInt, BigInt, Integer, One, Signed};
+use rand::prelude::*;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::collections::HashSet;
+
+/// The bit length of the primes the elements are hashed to.
+const PRIME_BITS: u64 = 256;
+
+/// Hashes an element to a prime: the first SHA-256 hash of the element and a counter that has
+/// the top bit set and passes Baillie-PSW.
+pub fn hash_to_prime(element: &[u8]) -> BigInt {
+    (0u64..)
+        .map(|counter| {
+            let h = Sha256::new()
+                .chain_update(element)
+                .chain_update(counter.to_be_bytes())
+                .finalize();
+            os2ip(&h) | (BigInt::one() << (PRIME_BITS - 1))
+        })
+        .find(baillie_psw)
+        .unwrap()
+}
+
+/// The public state: the modulus, the generator and the current value.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Accumulator {
+    n: BigInt,
+    g: BigInt,
+    value: BigInt,
+}
+
+/// w with w^x = A, where x is the prime of the element.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MembershipWitness(BigInt);
+
+/// (a, d) with A^a = d^y g, where y is the prime of the element. It exists iff y is coprime to
+/// the product of the set: a u + b y = 1 and d = g^-b.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NonMembershipWitness(BigInt, BigInt);
+
+/// A batch of additions or deletions, with the product of their primes and the new value.
+/// Publishing it lets the holders update their witnesses without knowing the set.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Update {
+    Add { product: BigInt, value: BigInt },
+    Delete { product: BigInt, value: BigInt },
+}
+
+impl Accumulator {
+    /// The accumulator of the empty set with the generator g.
+    pub fn new(n: BigInt, g: BigInt) -> Self {
+        Self {
+            value: g.clone(),
+            n,
+            g,
+        }
+    }
+
+    pub fn n(&self) -> &BigInt {
+        &self.n
+    }
+
+    pub fn g(&self) -> &BigInt {
+        &self.g
+    }
+
+    pub fn value(&self) -> &BigInt {
+        &self.value
+    }
+
+    /// x^e mod n, a negative e inverts x.
+    fn pow(&self, x: &BigInt, e: &BigInt) -> BigInt {
+        if e.is_negative() {
+            mod_inv(x, &self.n).modpow(&-e, &self.n)
+        } else {
+            x.modpow(e, &self.n)
+        }
+    }
+
+    fn is_unit(&self, x: &BigInt) -> bool {
+        x.is_positive() && *x < self.n && x.gcd(&self.n).is_one()
+    }
+
+    /// Checks w^x = A.
+    pub fn verify_member(&self, element: &[u8], w: &MembershipWitness) -> bool {
+        self.is_unit(&w.0) && w.0.modpow(&hash_to_prime(element), &self.n) == self.value
+    }
+
+    /// Checks A^a = d^y g.
+    pub fn verify_non_member(&self, element: &[u8], w: &NonMembershipWitness) -> bool {
+        let NonMembershipWitness(a, d) = w;
+        let y = hash_to_prime(element);
+        self.is_unit(d) && self.pow(&self.value, a) == (d.modpow(&y, &self.n) * &self.g) % &self.n
+    }
+
+    /// Applies an update published by the manager.
+    pub fn apply(&mut self, update: &Update) {
+        match update {
+            Update::Add { value, .. } | Update::Delete { value, .. } => self.value = value.clone(),
+        }
+    }
+}
+
+impl MembershipWitness {
+    /// Updates the witness of the element after a batch, from the accumulator before it.
+    /// Returns `false` if the element was deleted.
+    pub fn update(&mut self, acc: &Accumulator, element: &[u8], update: &Update) -> bool {
+        match update {
+            // (w^P)^x = A^P
+            Update::Add { product, .. } => self.0 = acc.pow(&self.0, product),
+            // With a x + b P = 1, w' = w^b A'^a, since A'^(1 / x) = A'^(a + b P / x) = A'^a w^b.
+            Update::Delete { product, value } => {
+                let e = hash_to_prime(element).extended_gcd(product);
+                if !e.gcd.is_one() {
+                    return false;
+                }
+                self.0 = (acc.pow(&self.0, &e.y) * acc.pow(value, &e.x)) % &acc.n;
+            }
+        }
+        true
+    }
+}
+
+impl NonMembershipWitness {
+    /// Updates the witness of the element after a batch, from the accumulator before it.
+    /// Returns `false` if the element was added.
+    pub fn update(&mut self, acc: &Accumulator, element: &[u8], update: &Update) -> bool {
+        let y = hash_to_prime(element);
+        let NonMembershipWitness(a, d) = self;
+        let (a, value) = match update {
+            // With α P + β y = 1, A^(a α P) = A^(a - a β y) = (d A^(-a β))^y g.
+            Update::Add { product, value } => {
+                let e = product.extended_gcd(&y);
+                if !e.gcd.is_one() {
+                    return false;
+                }
+                *d = (&*d * acc.pow(&acc.value, &(-&*a * e.y))) % &acc.n;
+                (&*a * e.x, value)
+            }
+            // A = A'^P, so A'^(a P) = d^y g.
+            Update::Delete { product, value } => (&*a * product, value),
+        };
+        // Keeps a in [0, y): A'^(a - k y) = (d A'^-k)^y g.
+        let (k, a) = a.div_mod_floor(&y);
+        *d = (&*d * acc.pow(value, &-k)) % &acc.n;
+        self.0 = a;
+        true
+    }
+}
+
+/// The manager of an accumulator, it keeps the set and the trapdoor.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct AccumulatorManager {
+    acc: Accumulator,
+    key: SecretRsaKey,
+    members: HashSet<BigInt>,
+}
+
+impl AccumulatorManager {
+    /// An empty accumulator over a fresh `bits` bit RSA modulus and a random square generator.
+    pub fn setup<R: RandBigInt, CR: RandBigInt + CryptoRng>(
+        bits: u64,
+        rng: &mut R,
+        crng: &mut CR,
+    ) -> Self {
+        let opts = KeyGenOptions {
+            bits,
+            primes: PrimeKind::Probable,
+        };
+        let key = gen_rsa_key_with(&opts, rng, crng);
+        let n = key.pub_key().n().clone();
+        let g = loop {
+            let r = crng.gen_bigint_range(&2.into(), &n);
+            if r.gcd(&n).is_one() {
+                break (&r * &r) % &n;
+            }
+        };
+        Self {
+            acc: Accumulator::new(n, g),
+            key,
+            members: HashSet::new(),
+        }
+    }
+
+    pub fn accumulator(&self) -> &Accumulator {
+        &self.acc
+    }
+
+    pub fn contains(&self, element: &[u8]) -> bool {
+        self.members.contains(&hash_to_prime(element))
+    }
+
+    fn phi(&self) -> BigInt {
+        let (p, q) = self.key.primes();
+        (p - 1u8) * (q - 1u8)
+    }
+
+    /// The root A^(1 / e), with the trapdoor. None if e isn't invertible mod φ(n).
+    fn root(&self, e: &BigInt) -> Option<BigInt> {
+        let phi = self.phi();
+        if !e.gcd(&phi).is_one() {
+            return None;
+        }
+        Some(self.acc.value.modpow(&mod_inv(e, &phi), &self.acc.n))
+    }
+
+    /// Adds the elements. Returns `None` and leaves the set as is if one of them is already
+    /// in it.
+    pub fn add(&mut self, elements: &[&[u8]]) -> Option<Update> {
+        let primes = elements
+            .iter()
+            .map(|e| hash_to_prime(e))
+            .collect::<HashSet<_>>();
+        if primes.len() != elements.len() || primes.iter().any(|x| self.members.contains(x)) {
+            return None;
+        }
+        let product = primes.iter().product::<BigInt>();
+        self.acc.value = self.acc.value.modpow(&product, &self.acc.n);
+        self.members.extend(primes);
+        Some(Update::Add {
+            product,
+            value: self.acc.value.clone(),
+        })
+    }
+
+    /// Deletes the elements with the trapdoor. Returns `None` and leaves the set as is if one
+    /// of them isn't in it.
+    pub fn delete(&mut self, elements: &[&[u8]]) -> Option<Update> {
+        let primes = elements
+            .iter()
+            .map(|e| hash_to_prime(e))
+            .collect::<HashSet<_>>();
+        if primes.len() != elements.len() || !primes.iter().all(|x| self.members.contains(x)) {
+            return None;
+        }
+        let product = primes.iter().product::<BigInt>();
+        self.acc.value = self.root(&product)?;
+        self.members.retain(|x| !primes.contains(x));
+        Some(Update::Delete {
+            product,
+            value: self.acc.value.clone(),
+        })
+    }
+
+    /// The membership witness A^(1 / x) of an element in the set.
+    pub fn membership_witness(&self, element: &[u8]) -> Option<MembershipWitness> {
+        let x = hash_to_prime(element);
+        if !self.members.contains(&x) {
+            return None;
+        }
+        self.root(&x).map(MembershipWitness)
+    }
+
+    /// The non-membership witness of an element that isn't in the set, from the Bezout
+    /// coefficients of y and the product u of the set: a = u^-1 mod y and d = g^((a u - 1) / y).
+    pub fn non_membership_witness(&self, element: &[u8]) -> Option<NonMembershipWitness> {
+        let y = hash_to_prime(element);
+        if self.members.contains(&y) {
+            return None;
+        }
+        let u = self.members.iter().product::<BigInt>();
+        let a = mod_inv(&(&u % &y), &y);
+        let b = (&a * u - 1u8) / &y;
+        let d = self.acc.g.modpow(&b.mod_floor(&self.phi()), &self.acc.n);
+        Some(NonMembershipWitness(a, d))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_manager() -> AccumulatorManager {
+        let key = SecretRsaKey::from_pem(include_str!("../testdata/rsa-2048.pem")).unwrap();
+        let n = key.pub_key().n().clone();
+        AccumulatorManager {
+            acc: Accumulator::new(n, 4.into()),
+            key,
+            members: HashSet::new(),
+        }
+    }
+
+    #[test]
+    fn witnesses() {
+        let mut manager = test_manager();
+        assert!(manager.add(&[b"a", b"b", b"c"]).is_some());
+        assert!(manager.add(&[b"d", b"a"]).is_none());
+        assert!(manager.add(&[b"d", b"d"]).is_none());
+        assert!(!manager.contains(b"d"));
+        let acc = manager.accumulator();
+
+        for element in [b"a", b"b", b"c"] {
+            let w = manager.membership_witness(element).unwrap();
+            assert!(acc.verify_member(element, &w));
+            assert!(!acc.verify_member(b"d", &w));
+            assert!(manager.non_membership_witness(element).is_none());
+        }
+        let w = manager.non_membership_witness(b"d").unwrap();
+        assert!(acc.verify_non_member(b"d", &w));
+        assert!(!acc.verify_non_member(b"a", &w));
+        assert!(manager.membership_witness(b"d").is_none());
+
+        // forged witnesses
+        let MembershipWitness(w) = manager.membership_witness(b"a").unwrap();
+        assert!(!acc.verify_member(b"a", &MembershipWitness((&w * 2) % acc.n())));
+        assert!(!acc.verify_member(b"a", &MembershipWitness(&w + acc.n())));
+        let NonMembershipWitness(a, d) = manager.non_membership_witness(b"d").unwrap();
+        assert!(!acc.verify_non_member(b"d", &NonMembershipWitness(&a + 1, d.clone())));
+        assert!(!acc.verify_non_member(b"d", &NonMembershipWitness(a, 0.into())));
+    }
+
+    #[test]
+    fn witness_updates() {
+        let mut manager = test_manager();
+        manager.add(&[b"a", b"b"]).unwrap();
+        let mut acc = manager.accumulator().clone();
+        let mut member_a = manager.membership_witness(b"a").unwrap();
+        let mut member_b = manager.membership_witness(b"b").unwrap();
+        let mut non_member_c = manager.non_membership_witness(b"c").unwrap();
+        let mut non_member_d = manager.non_membership_witness(b"d").unwrap();
+
+        let update = manager.add(&[b"c", b"e"]).unwrap();
+        assert!(member_a.update(&acc, b"a", &update));
+        assert!(non_member_d.update(&acc, b"d", &update));
+        assert!(!non_member_c.update(&acc, b"c", &update));
+        acc.apply(&update);
+        assert_eq!(acc, *manager.accumulator());
+        assert!(acc.verify_member(b"a", &member_a));
+        assert!(acc.verify_non_member(b"d", &non_member_d));
+
+        let update = manager.delete(&[b"b", b"c"]).unwrap();
+        assert!(manager.delete(&[b"b"]).is_none());
+        assert!(member_a.update(&acc, b"a", &update));
+        assert!(!member_b.update(&acc, b"b", &update));
+        assert!(non_member_d.update(&acc, b"d", &update));
+        acc.apply(&update);
+        assert_eq!(acc, *manager.accumulator());
+        assert!(acc.verify_member(b"a", &member_a));
+        assert!(acc.verify_non_member(b"d", &non_member_d));
+        assert!(acc.verify_non_member(b"b", &manager.non_membership_witness(b"b").unwrap()));
+        assert!(!manager.contains(b"b") && manager.contains(b"e"));
+    }
+}
@@ -1,3 +1,5 @@
+use rand::{CryptoRng, RngCore, SeedableRng};
+
 #[inline]
 fn qr(a: &mut u32, b: &mut u32, c: &mut u32, d: &mut u32) {
     *a = a.wrapping_add(*b);
@@ -17,9 +19,9 @@ fn qr(a: &mut u32, b: &mut u32, c: &mut u32, d: &mut u32) {
 unsafe fn qr_diag(s: &mut [u32; 16], i: usize) {
     qr(
         &mut *(s.get_unchecked_mut(i) as *mut _),
-        &mut *(s.get_unchecked_mut(4 | i + 1 & 3) as *mut _),
-        &mut *(s.get_unchecked_mut(8 | i + 2 & 3) as *mut _),
-        &mut *(s.get_unchecked_mut(12 | i + 3 & 3) as *mut _),
+        &mut *(s.get_unchecked_mut(4 | (i + 1) & 3) as *mut _),
+        &mut *(s.get_unchecked_mut(8 | (i + 2) & 3) as *mut _),
+        &mut *(s.get_unchecked_mut(12 | (i + 3) & 3) as *mut _),
     );
 }
 
@@ -64,11 +66,8 @@ const CHACHA_CONST: [u32; 4] = [
 pub fn chacha(k: &[u32; 8], pos: u64, nonce: &[u32; 2], out: &mut [u32; 16], rounds: usize) {
     // let state = mem::transmute((CHACHA_CONST, *k, pos, nonce));
 
-    let pos = pos.to_ne_bytes();
-    let pos = [
-        u32::from_ne_bytes([pos[0], pos[1], pos[2], pos[3]]),
-        u32::from_ne_bytes([pos[4], pos[5], pos[6], pos[7]]),
-    ];
+    // The low word of the block counter comes first, on every platform.
+    let pos = [pos as u32, (pos >> 32) as u32];
 
     #[rustfmt::skip]
     let state: [u32; 16] = [
@@ -147,24 +146,58 @@ pub type ChaCha8 = ChaCha<8>;
 pub type ChaCha12 = ChaCha<12>;
 pub type ChaCha20 = ChaCha<20>;
 
+/// The keystream as an RNG, its words are output in little endian.
+/// `fill_bytes` discards the unused bytes of its last word.
+impl<const N: usize> RngCore for ChaCha<N> {
+    fn next_u32(&mut self) -> u32 {
+        self.get32()
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.get32() as u64 | (self.get32() as u64) << 32
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        for chunk in dest.chunks_mut(4) {
+            chunk.copy_from_slice(&self.get32().to_le_bytes()[..chunk.len()]);
+        }
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
+
+/// The seed is the key in little endian, the nonce is zero.
+impl<const N: usize> SeedableRng for ChaCha<N> {
+    type Seed = [u8; 32];
+
+    fn from_seed(seed: [u8; 32]) -> Self {
+        Self::new(key_words(&seed), [0; 2])
+    }
+}
+
+impl CryptoRng for ChaCha20 {}
+
 impl<const N: usize> ChaCha<N> {
     pub fn new(key: [u32; 8], nonce: [u32; 2]) -> Self {
         Self {
             key,
             nonce,
             pos: 0,
-            out_pos: 0,
+            out_pos: 16,
             out: [0; 16],
         }
     }
     pub fn get32(&mut self) -> u32 {
-        if let Some(&x) = self.out.get(self.out_pos as usize) {
-            x
-        } else {
+        if self.out_pos == 16 {
             chacha(&self.key, self.pos, &self.nonce, &mut self.out, N);
-            self.out_pos = 1;
-            self.out[0]
+            self.pos += 1;
+            self.out_pos = 0;
         }
+        self.out_pos += 1;
+        self.out[self.out_pos as usize - 1]
     }
 }
 
@@ -186,17 +219,424 @@ impl<const N: usize> XChaCha<N> {
             key,
             nonce,
             pos: 0,
-            out_pos: 0,
+            out_pos: 16,
             out: [0; 16],
         }
     }
     pub fn get32(&mut self) -> u32 {
-        if let Some(&x) = self.out.get(self.out_pos as usize) {
-            x
-        } else {
+        if self.out_pos == 16 {
             xchacha(&self.key, self.pos, &self.nonce, &mut self.out, N);
-            self.out_pos = 1;
-            self.out[0]
+            self.pos += 1;
+            self.out_pos = 0;
+        }
+        self.out_pos += 1;
+        self.out[self.out_pos as usize - 1]
+    }
+}
+
+/// The Poly1305 one-time authenticator (RFC 8439 2.5), with 26-bit limbs.
+pub struct Poly1305 {
+    r: [u32; 5],
+    h: [u32; 5],
+    pad: [u32; 4],
+    buf: [u8; 16],
+    buf_len: usize,
+}
+
+#[inline]
+fn le32(b: &[u8]) -> u32 {
+    u32::from_le_bytes([b[0], b[1], b[2], b[3]])
+}
+
+impl Poly1305 {
+    const MASK: u32 = 0x3ffffff;
+
+    /// The key is r followed by s, it must never be reused.
+    pub fn new(key: &[u8; 32]) -> Self {
+        Self {
+            r: [
+                le32(&key[0..]) & 0x3ffffff,
+                (le32(&key[3..]) >> 2) & 0x3ffff03,
+                (le32(&key[6..]) >> 4) & 0x3ffc0ff,
+                (le32(&key[9..]) >> 6) & 0x3f03fff,
+                (le32(&key[12..]) >> 8) & 0x00fffff,
+            ],
+            h: [0; 5],
+            pad: [
+                le32(&key[16..]),
+                le32(&key[20..]),
+                le32(&key[24..]),
+                le32(&key[28..]),
+            ],
+            buf: [0; 16],
+            buf_len: 0,
+        }
+    }
+
+    /// Adds a 16 byte block to the accumulator and multiplies it by r, `hibit` is the 2^128 bit
+    /// of the block.
+    fn block(&mut self, m: &[u8; 16], hibit: u32) {
+        let [r0, r1, r2, r3, r4] = self.r.map(u64::from);
+        let (s1, s2, s3, s4) = (r1 * 5, r2 * 5, r3 * 5, r4 * 5);
+        let h = &mut self.h;
+        let h0 = (h[0] + (le32(&m[0..]) & Self::MASK)) as u64;
+        let h1 = (h[1] + ((le32(&m[3..]) >> 2) & Self::MASK)) as u64;
+        let h2 = (h[2] + ((le32(&m[6..]) >> 4) & Self::MASK)) as u64;
+        let h3 = (h[3] + ((le32(&m[9..]) >> 6) & Self::MASK)) as u64;
+        let h4 = (h[4] + ((le32(&m[12..]) >> 8) | hibit)) as u64;
+
+        let d0 = h0 * r0 + h1 * s4 + h2 * s3 + h3 * s2 + h4 * s1;
+        let mut d1 = h0 * r1 + h1 * r0 + h2 * s4 + h3 * s3 + h4 * s2;
+        let mut d2 = h0 * r2 + h1 * r1 + h2 * r0 + h3 * s4 + h4 * s3;
+        let mut d3 = h0 * r3 + h1 * r2 + h2 * r1 + h3 * r0 + h4 * s4;
+        let mut d4 = h0 * r4 + h1 * r3 + h2 * r2 + h3 * r1 + h4 * r0;
+
+        d1 += d0 >> 26;
+        d2 += d1 >> 26;
+        d3 += d2 >> 26;
+        d4 += d3 >> 26;
+        let h0 = (d0 & Self::MASK as u64) + (d4 >> 26) * 5;
+        h[0] = (h0 & Self::MASK as u64) as u32;
+        h[1] = (d1 & Self::MASK as u64) as u32 + (h0 >> 26) as u32;
+        h[2] = (d2 & Self::MASK as u64) as u32;
+        h[3] = (d3 & Self::MASK as u64) as u32;
+        h[4] = (d4 & Self::MASK as u64) as u32;
+    }
+
+    pub fn update(&mut self, mut data: &[u8]) {
+        if self.buf_len != 0 {
+            let n = data.len().min(16 - self.buf_len);
+            self.buf[self.buf_len..self.buf_len + n].copy_from_slice(&data[..n]);
+            self.buf_len += n;
+            data = &data[n..];
+            if self.buf_len < 16 {
+                return;
+            }
+            let buf = self.buf;
+            self.block(&buf, 1 << 24);
+            self.buf_len = 0;
+        }
+        let mut blocks = data.chunks_exact(16);
+        for m in &mut blocks {
+            self.block(m.try_into().unwrap(), 1 << 24);
+        }
+        let rest = blocks.remainder();
+        self.buf[..rest.len()].copy_from_slice(rest);
+        self.buf_len = rest.len();
+    }
+
+    /// The 16 byte tag.
+    pub fn finalize(mut self) -> [u8; 16] {
+        if self.buf_len != 0 {
+            let mut m = [0; 16];
+            m[..self.buf_len].copy_from_slice(&self.buf[..self.buf_len]);
+            m[self.buf_len] = 1;
+            self.block(&m, 0);
+        }
+
+        // Fully carries h, then subtracts p = 2^130 - 5 if h >= p.
+        let mut h = self.h;
+        for i in 1..5 {
+            h[i] += h[i - 1] >> 26;
+            h[i - 1] &= Self::MASK;
+        }
+        h[0] += (h[4] >> 26) * 5;
+        h[4] &= Self::MASK;
+        h[1] += h[0] >> 26;
+        h[0] &= Self::MASK;
+
+        let mut g = [0; 5];
+        let mut c = 5;
+        for i in 0..4 {
+            g[i] = h[i] + c;
+            c = g[i] >> 26;
+            g[i] &= Self::MASK;
+        }
+        g[4] = (h[4] + c).wrapping_sub(1 << 26);
+        // All ones if h - p didn't borrow.
+        let mask = (g[4] >> 31).wrapping_sub(1);
+        for i in 0..5 {
+            h[i] = (h[i] & !mask) | (g[i] & mask);
+        }
+
+        let h = [
+            h[0] | (h[1] << 26),
+            (h[1] >> 6) | (h[2] << 20),
+            (h[2] >> 12) | (h[3] << 14),
+            (h[3] >> 18) | (h[4] << 8),
+        ];
+        let mut tag = [0; 16];
+        let mut f = 0u64;
+        for i in 0..4 {
+            f = h[i] as u64 + self.pad[i] as u64 + (f >> 32);
+            tag[4 * i..4 * i + 4].copy_from_slice(&(f as u32).to_le_bytes());
+        }
+        tag
+    }
+}
+
+/// XORs the ChaCha20 keystream of the RFC 8439 layout, a 32-bit block counter and a 96-bit
+/// nonce, into the data.
+fn chacha20_ietf_xor(key: &[u32; 8], counter: u32, nonce: &[u8; 12], data: &mut [u8]) {
+    let pos = counter as u64 | (le32(&nonce[0..]) as u64) << 32;
+    let nonce = [le32(&nonce[4..]), le32(&nonce[8..])];
+    let mut out = [0; 16];
+    for (i, chunk) in data.chunks_mut(64).enumerate() {
+        chacha20(key, pos + i as u64, &nonce, &mut out);
+        let stream = out.iter().flat_map(|w| w.to_le_bytes());
+        for (b, s) in chunk.iter_mut().zip(stream) {
+            *b ^= s;
+        }
+    }
+}
+
+/// The Poly1305 tag of the AEAD construction over the associated data and the cipher text.
+fn aead_tag(key: &[u32; 8], nonce: &[u8; 12], aad: &[u8], c: &[u8]) -> [u8; 16] {
+    let mut poly_key = [0; 32];
+    chacha20_ietf_xor(key, 0, nonce, &mut poly_key);
+    let mut mac = Poly1305::new(&poly_key);
+    let pad = [0; 16];
+    mac.update(aad);
+    mac.update(&pad[..aad.len().wrapping_neg() % 16]);
+    mac.update(c);
+    mac.update(&pad[..c.len().wrapping_neg() % 16]);
+    mac.update(&(aad.len() as u64).to_le_bytes());
+    mac.update(&(c.len() as u64).to_le_bytes());
+    mac.finalize()
+}
+
+fn key_words(key: &[u8; 32]) -> [u32; 8] {
+    let mut words = [0; 8];
+    for (w, bytes) in words.iter_mut().zip(key.chunks(4)) {
+        *w = le32(bytes);
+    }
+    words
+}
+
+/// ChaCha20-Poly1305 encryption (RFC 8439 2.8), returns the cipher text followed by the tag.
+pub fn chacha20_poly1305_seal(key: &[u8; 32], nonce: &[u8; 12], aad: &[u8], m: &[u8]) -> Vec<u8> {
+    let key = key_words(key);
+    let mut c = m.to_vec();
+    chacha20_ietf_xor(&key, 1, nonce, &mut c);
+    let tag = aead_tag(&key, nonce, aad, &c);
+    c.extend_from_slice(&tag);
+    c
+}
+
+/// ChaCha20-Poly1305 decryption (RFC 8439 2.8) of the cipher text followed by the tag.
+/// Returns `None` if the tag doesn't match.
+pub fn chacha20_poly1305_open(
+    key: &[u8; 32],
+    nonce: &[u8; 12],
+    aad: &[u8],
+    c: &[u8],
+) -> Option<Vec<u8>> {
+    let (c, tag) = c.split_at(c.len().checked_sub(16)?);
+    let key = key_words(key);
+    let expected = aead_tag(&key, nonce, aad, c);
+    // Compares the tags in constant time.
+    if expected
+        .iter()
+        .zip(tag)
+        .fold(0, |acc, (a, b)| acc | (a ^ b))
+        != 0
+    {
+        return None;
+    }
+    let mut m = c.to_vec();
+    chacha20_ietf_xor(&key, 1, nonce, &mut m);
+    Some(m)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::hex;
+
+    #[test]
+    fn rng_keystream() {
+        // RFC 8439 A.1 test vector #1: the all zero key and nonce
+        let mut rng = ChaCha20::from_seed([0; 32]);
+        let mut out = [0; 64];
+        rng.fill_bytes(&mut out);
+        assert_eq!(
+            out.to_vec(),
+            hex(
+                "76b8e0ada0f13d90405d6ae55386bd28bdd219b8a08ded1aa836efcc8b770dc7\
+                 da41597c5157488d7724e03fb8d84a376a43b8f41518a11cc387b669b2ee6586"
+            )
+        );
+
+        let seed: [u8; 32] = core::array::from_fn(|i| i as u8);
+        let keystream = hex(
+            "39fd2b7dd9c5196a8dbd0377b8dc4a498a35d86fbcde6accb2cc7d4cd8ea2492\
+             2b23cce7a26023ab3f0eef693ac87f64258235eab1f7a32dc22762a0485b410c",
+        );
+        let mut rng = ChaCha20::from_seed(seed);
+        assert_eq!(rng.next_u32().to_le_bytes(), keystream[..4]);
+        assert_eq!(rng.next_u64().to_le_bytes(), keystream[4..12]);
+        // the unused byte of the last word is discarded
+        let mut out = [0; 3];
+        rng.fill_bytes(&mut out);
+        assert_eq!(out, keystream[12..15]);
+        let mut out = [0; 48];
+        rng.fill_bytes(&mut out);
+        assert_eq!(out, keystream[16..]);
+    }
+
+    fn poly1305(key: &[u8], m: &[u8]) -> Vec<u8> {
+        let mut mac = Poly1305::new(key.try_into().unwrap());
+        mac.update(m);
+        mac.finalize().to_vec()
+    }
+
+    const IETF_CONTRIBUTION: &[u8] = b"Any submission to the IETF intended by the Contributor for \
+        publication as all or part of an IETF Internet-Draft or RFC and any statement made within \
+        the context of an IETF activity is considered an \"IETF Contribution\". Such statements \
+        include oral statements in IETF sessions, as well as written and electronic communications \
+        made at any time or place, which are addressed to";
+
+    #[test]
+    fn poly1305_vectors() {
+        // RFC 8439 2.5.2
+        let key = hex("85d6be7857556d337f4452fe42d506a80103808afb0db2fd4abff6af4149f51b");
+        let tag = hex("a8061dc1305136c6c22b8baf0c0127a9");
+        assert_eq!(poly1305(&key, b"Cryptographic Forum Research Group"), tag);
+        let mut mac = Poly1305::new(key[..].try_into().unwrap());
+        for chunk in b"Cryptographic Forum Research Group".chunks(5) {
+            mac.update(chunk);
+        }
+        assert_eq!(mac.finalize().to_vec(), tag);
+
+        // RFC 8439 A.3 #2 to #4
+        let s = "36e5f6b5c5e06070f0efca96227a863e";
+        assert_eq!(
+            poly1305(&hex(&format!("{:032}{}", 0, s)), IETF_CONTRIBUTION),
+            hex(s)
+        );
+        assert_eq!(
+            poly1305(&hex(&format!("{}{:032}", s, 0)), IETF_CONTRIBUTION),
+            hex("f3477e7cd95417af89a6b8794c310cf0")
+        );
+        assert_eq!(
+            poly1305(
+                &hex("1c9240a5eb55d38af333888604f6b5f0473917c1402b80099dca5cbc207075c0"),
+                b"'Twas brillig, and the slithy toves\nDid gyre and gimble in the wabe:\n\
+                  All mimsy were the borogoves,\nAnd the mome raths outgrabe."
+            ),
+            hex("4541669a7eaaee61e708dc7cbcc5eb62")
+        );
+
+        // RFC 8439 A.3 #1 and #5 to #11, the last ones exercise the final reduction
+        for (key, m, tag) in [
+            (
+                "0000000000000000000000000000000000000000000000000000000000000000",
+                "0000000000000000000000000000000000000000000000000000000000000000\
+                 0000000000000000000000000000000000000000000000000000000000000000",
+                "00000000000000000000000000000000",
+            ),
+            (
+                "0200000000000000000000000000000000000000000000000000000000000000",
+                "ffffffffffffffffffffffffffffffff",
+                "03000000000000000000000000000000",
+            ),
+            (
+                "02000000000000000000000000000000ffffffffffffffffffffffffffffffff",
+                "02000000000000000000000000000000",
+                "03000000000000000000000000000000",
+            ),
+            (
+                "0100000000000000000000000000000000000000000000000000000000000000",
+                "fffffffffffffffffffffffffffffffff0ffffffffffffffffffffffffffffff\
+                 11000000000000000000000000000000",
+                "05000000000000000000000000000000",
+            ),
+            (
+                "0100000000000000000000000000000000000000000000000000000000000000",
+                "fffffffffffffffffffffffffffffffffbfefefefefefefefefefefefefefefe\
+                 01010101010101010101010101010101",
+                "00000000000000000000000000000000",
+            ),
+            (
+                "0200000000000000000000000000000000000000000000000000000000000000",
+                "fdffffffffffffffffffffffffffffff",
+                "faffffffffffffffffffffffffffffff",
+            ),
+            (
+                "0100000000000000040000000000000000000000000000000000000000000000",
+                "e33594d7505e43b900000000000000003394d7505e4379cd0100000000000000\
+                 0000000000000000000000000000000001000000000000000000000000000000",
+                "14000000000000005500000000000000",
+            ),
+            (
+                "0100000000000000040000000000000000000000000000000000000000000000",
+                "e33594d7505e43b900000000000000003394d7505e4379cd0100000000000000\
+                 00000000000000000000000000000000",
+                "13000000000000000000000000000000",
+            ),
+        ] {
+            assert_eq!(poly1305(&hex(key), &hex(m)), hex(tag), "{}", m);
+        }
+    }
+
+    /// The key, nonce and additional data of RFC 8439 2.8.2.
+    fn aead_example() -> ([u8; 32], [u8; 12], Vec<u8>) {
+        let key = core::array::from_fn(|i| 0x80 + i as u8);
+        let nonce = hex("070000004041424344454647").try_into().unwrap();
+        (key, nonce, hex("50515253c0c1c2c3c4c5c6c7"))
+    }
+
+    #[test]
+    fn aead_vector() {
+        let (key, nonce, aad) = aead_example();
+        let m = b"Ladies and Gentlemen of the class of '99: If I could offer you only one tip for \
+            the future, sunscreen would be it.";
+        let c = chacha20_poly1305_seal(&key, &nonce, &aad, m);
+        let expected = [
+            hex(
+                "d31a8d34648e60db7b86afbc53ef7ec2a4aded51296e08fea9e2b5a736ee62d6\
+                 3dbea45e8ca9671282fafb69da92728b1a71de0a9e060b2905d6a5b67ecd3b36\
+                 92ddbd7f2d778b8c9803aee328091b58fab324e4fad675945585808b4831d7bc\
+                 3ff4def08e4b7a9de576d26586cec64b6116",
+            ),
+            hex("1ae10b594f09e26a7e902ecbd0600691"),
+        ]
+        .concat();
+        assert_eq!(c, expected);
+        assert_eq!(
+            chacha20_poly1305_open(&key, &nonce, &aad, &c).as_deref(),
+            Some(&m[..])
+        );
+    }
+
+    #[test]
+    fn aead_rejects_tampering() {
+        let (key, nonce, aad) = aead_example();
+        let c = chacha20_poly1305_seal(&key, &nonce, &aad, b"attack at dawn");
+        for i in 0..c.len() {
+            for bit in [0, 7] {
+                let mut tampered = c.clone();
+                tampered[i] ^= 1 << bit;
+                assert_eq!(chacha20_poly1305_open(&key, &nonce, &aad, &tampered), None);
+            }
         }
+        let mut wrong_aad = aad.clone();
+        wrong_aad[0] ^= 1;
+        assert_eq!(chacha20_poly1305_open(&key, &nonce, &wrong_aad, &c), None);
+        assert_eq!(chacha20_poly1305_open(&key, &[0; 12], &aad, &c), None);
+        assert_eq!(
+            chacha20_poly1305_open(&key, &nonce, &aad, &c[..c.len() - 1]),
+            None
+        );
+        assert_eq!(chacha20_poly1305_open(&key, &nonce, &aad, &c[..15]), None);
+        assert_eq!(
+            chacha20_poly1305_open(
+                &key,
+                &nonce,
+                &aad,
+                &chacha20_poly1305_seal(&key, &nonce, &aad, b"")
+            ),
+            Some(vec![])
+        );
     }
 }
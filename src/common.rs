@@ -1,5 +1,5 @@
 use digest::{Digest, FixedOutputReset};
-use num::{one, zero, BigInt, BigUint, Integer};
+use num::{bigint::Sign, one, zero, BigInt, BigUint, Integer};
 
 /// Computes the modular inverse of x mod n such that:
 /// x * mod_inv(x) % n = gcd(x, n)
@@ -32,9 +32,137 @@ pub fn mod_div(x: &BigInt, y: &BigInt, n: &BigInt) -> BigInt {
 // }
 
 pub fn hash_bigint<D: Digest + FixedOutputReset>(n: &BigInt, h: &mut D) -> BigInt {
-    Digest::update(h, &[n.sign() as u8]);
+    Digest::update(h, [n.sign() as u8]);
     for digit in n.iter_u64_digits() {
-        Digest::update(h, &digit.to_ne_bytes());
+        Digest::update(h, digit.to_ne_bytes());
     }
     BigUint::from_bytes_le(&h.finalize_reset()).into()
 }
+
+/// Hashes several integers, each prefixed with its sign and number of digits so that different
+/// lists can't hash the same.
+pub fn hash_bigints<D: Digest + FixedOutputReset>(ns: &[&BigInt], h: &mut D) -> BigInt {
+    for n in ns {
+        Digest::update(h, [n.sign() as u8]);
+        Digest::update(h, (n.iter_u64_digits().len() as u64).to_le_bytes());
+        for digit in n.iter_u64_digits() {
+            Digest::update(h, digit.to_le_bytes());
+        }
+    }
+    BigUint::from_bytes_le(&h.finalize_reset()).into()
+}
+
+/// Converts a non-negative integer to a big-endian byte string of exactly `len` bytes (I2OSP).
+/// Returns `None` if the integer is negative or too large to fit.
+pub fn i2osp(n: &BigInt, len: usize) -> Option<Vec<u8>> {
+    let (sign, bytes) = n.to_bytes_be();
+    if sign == Sign::Minus || sign == Sign::Plus && len < bytes.len() {
+        return None;
+    }
+    let mut out = vec![0; len];
+    if sign == Sign::Plus {
+        out[len - bytes.len()..].copy_from_slice(&bytes);
+    }
+    Some(out)
+}
+
+/// Converts a big-endian byte string to a non-negative integer (OS2IP).
+pub fn os2ip(bytes: &[u8]) -> BigInt {
+    BigInt::from_bytes_be(Sign::Plus, bytes)
+}
+
+pub const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+pub const BASE64URL: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+
+/// Encodes the data in base64 with the given alphabet, with `=` padding if `pad` is set.
+pub fn base64_encode(data: &[u8], alphabet: &[u8; 64], pad: bool) -> String {
+    let mut out = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, &b)| n | ((b as u32) << (16 - 8 * i)));
+        for i in 0..=chunk.len() {
+            out.push(alphabet[((n >> (18 - 6 * i)) & 63) as usize] as char);
+        }
+        if pad {
+            for _ in chunk.len()..3 {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+/// Decodes base64 with the given alphabet, padding is optional.
+/// Returns `None` on invalid characters or length.
+pub fn base64_decode(s: &str, alphabet: &[u8; 64]) -> Option<Vec<u8>> {
+    let s = s.trim_end_matches('=').as_bytes();
+    if s.len() % 4 == 1 {
+        return None;
+    }
+    let mut out = Vec::with_capacity(s.len() * 3 / 4);
+    for chunk in s.chunks(4) {
+        let mut n = 0u32;
+        for (i, c) in chunk.iter().enumerate() {
+            let v = alphabet.iter().position(|a| a == c)? as u32;
+            n |= v << (18 - 6 * i);
+        }
+        for i in 0..chunk.len() - 1 {
+            out.push((n >> (16 - 8 * i)) as u8);
+        }
+    }
+    Some(out)
+}
+
+/// Computes a square root of x mod p for an odd prime p with the Tonelli-Shanks algorithm.
+/// Returns `None` if x isn't a quadratic residue mod p.
+pub fn mod_sqrt(x: &BigInt, p: &BigInt) -> Option<BigInt> {
+    let x = x.mod_floor(p);
+    if x == zero() {
+        return Some(zero());
+    }
+    let p1: BigInt = p - 1;
+    if x.modpow(&(&p1 >> 1), p) != one() {
+        return None;
+    }
+    if p.mod_floor(&4.into()) == 3.into() {
+        return Some(x.modpow(&((p + 1) >> 2), p));
+    }
+
+    // p - 1 = q * 2^s with q odd
+    let s = p1.trailing_zeros().unwrap();
+    let q = &p1 >> s;
+    let mut z: BigInt = 2.into();
+    while z.modpow(&(&p1 >> 1), p) != p1 {
+        z += 1;
+    }
+    let mut m = s;
+    let mut c = z.modpow(&q, p);
+    let mut t = x.modpow(&q, p);
+    let mut r = x.modpow(&((&q + 1) >> 1), p);
+    while t != one() {
+        let mut i = 0;
+        let mut t2 = t.clone();
+        while t2 != one() {
+            t2 = (&t2 * &t2).mod_floor(p);
+            i += 1;
+        }
+        let b = c.modpow(&(BigInt::from(1) << (m - i - 1)), p);
+        m = i;
+        c = (&b * &b).mod_floor(p);
+        t = (t * &c).mod_floor(p);
+        r = (r * b).mod_floor(p);
+    }
+    Some(r)
+}
+
+/// Decodes a hex string, for test vectors.
+#[cfg(test)]
+pub fn hex(s: &str) -> Vec<u8> {
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap())
+        .collect()
+}
@@ -0,0 +1,406 @@
+//! A minimal ASN.1 DER encoder and decoder, only supporting the types needed for key formats,
+//! and PEM armoring.
+use crate::common::{base64_decode, base64_encode, BASE64};
+use num::{bigint::Sign, BigInt};
+use std::fmt;
+
+pub const TAG_INTEGER: u8 = 0x02;
+pub const TAG_BIT_STRING: u8 = 0x03;
+pub const TAG_OCTET_STRING: u8 = 0x04;
+pub const TAG_NULL: u8 = 0x05;
+pub const TAG_OID: u8 = 0x06;
+pub const TAG_SEQUENCE: u8 = 0x30;
+
+/// Context specific constructed tag `[n]`.
+pub const fn tag_context(n: u8) -> u8 {
+    0xa0 | n
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DerError {
+    UnexpectedEnd,
+    UnexpectedTag { expected: u8, found: u8 },
+    InvalidLength,
+    InvalidValue,
+    TrailingData,
+    UnsupportedVersion,
+    UnknownAlgorithm,
+    InvalidPem,
+    UnexpectedLabel(String),
+}
+
+impl fmt::Display for DerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnexpectedEnd => write!(f, "unexpected end of DER data"),
+            Self::UnexpectedTag { expected, found } => write!(
+                f,
+                "unexpected DER tag 0x{:02x}, expected 0x{:02x}",
+                found, expected
+            ),
+            Self::InvalidLength => write!(f, "invalid DER length"),
+            Self::InvalidValue => write!(f, "invalid DER value"),
+            Self::TrailingData => write!(f, "trailing data after DER value"),
+            Self::UnsupportedVersion => write!(f, "unsupported key version"),
+            Self::UnknownAlgorithm => write!(f, "unknown key algorithm"),
+            Self::InvalidPem => write!(f, "invalid PEM"),
+            Self::UnexpectedLabel(label) => write!(f, "unexpected PEM label \"{}\"", label),
+        }
+    }
+}
+
+impl std::error::Error for DerError {}
+
+#[derive(Clone, Debug, Default)]
+pub struct DerWriter {
+    buf: Vec<u8>,
+}
+
+impl DerWriter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn finish(self) -> Vec<u8> {
+        self.buf
+    }
+
+    /// Writes a TLV with an arbitrary tag.
+    pub fn tlv(&mut self, tag: u8, value: &[u8]) -> &mut Self {
+        self.buf.push(tag);
+        let len = value.len();
+        if len < 0x80 {
+            self.buf.push(len as u8);
+        } else {
+            let bytes = len.to_be_bytes();
+            let skip = bytes.iter().take_while(|&&b| b == 0).count();
+            self.buf.push(0x80 | (bytes.len() - skip) as u8);
+            self.buf.extend_from_slice(&bytes[skip..]);
+        }
+        self.buf.extend_from_slice(value);
+        self
+    }
+
+    pub fn integer(&mut self, n: &BigInt) -> &mut Self {
+        self.tlv(TAG_INTEGER, &n.to_signed_bytes_be())
+    }
+
+    pub fn small_integer(&mut self, n: i64) -> &mut Self {
+        self.integer(&n.into())
+    }
+
+    pub fn null(&mut self) -> &mut Self {
+        self.tlv(TAG_NULL, &[])
+    }
+
+    pub fn oid(&mut self, oid: &[u64]) -> &mut Self {
+        let mut value = vec![];
+        let mut push_arc = |mut arc: u64| {
+            let mut bytes = vec![(arc & 0x7f) as u8];
+            arc >>= 7;
+            while arc != 0 {
+                bytes.push(0x80 | (arc & 0x7f) as u8);
+                arc >>= 7;
+            }
+            value.extend(bytes.iter().rev());
+        };
+        push_arc(40 * oid[0] + oid[1]);
+        for &arc in &oid[2..] {
+            push_arc(arc);
+        }
+        self.tlv(TAG_OID, &value)
+    }
+
+    pub fn octet_string(&mut self, bytes: &[u8]) -> &mut Self {
+        self.tlv(TAG_OCTET_STRING, bytes)
+    }
+
+    /// Writes a bit string with no unused bits.
+    pub fn bit_string(&mut self, bytes: &[u8]) -> &mut Self {
+        let mut value = Vec::with_capacity(bytes.len() + 1);
+        value.push(0);
+        value.extend_from_slice(bytes);
+        self.tlv(TAG_BIT_STRING, &value)
+    }
+
+    /// Writes a constructed value with the given tag, whose contents are written by `f`.
+    pub fn constructed(&mut self, tag: u8, f: impl FnOnce(&mut DerWriter)) -> &mut Self {
+        let mut inner = DerWriter::new();
+        f(&mut inner);
+        self.tlv(tag, &inner.buf)
+    }
+
+    pub fn sequence(&mut self, f: impl FnOnce(&mut DerWriter)) -> &mut Self {
+        self.constructed(TAG_SEQUENCE, f)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct DerReader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> DerReader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Self { data }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    /// Fails if there is any data left.
+    pub fn finish(self) -> Result<(), DerError> {
+        if self.is_empty() {
+            Ok(())
+        } else {
+            Err(DerError::TrailingData)
+        }
+    }
+
+    pub fn peek_tag(&self) -> Option<u8> {
+        self.data.first().copied()
+    }
+
+    /// Reads a TLV with the given tag and returns its value.
+    pub fn tlv(&mut self, tag: u8) -> Result<&'a [u8], DerError> {
+        let (&found, rest) = self.data.split_first().ok_or(DerError::UnexpectedEnd)?;
+        if found != tag {
+            return Err(DerError::UnexpectedTag {
+                expected: tag,
+                found,
+            });
+        }
+        let (&first, mut rest) = rest.split_first().ok_or(DerError::UnexpectedEnd)?;
+        let len = if first < 0x80 {
+            first as usize
+        } else {
+            let n = (first & 0x7f) as usize;
+            if n == 0 || size_of::<usize>() < n || rest.len() < n || rest[0] == 0 {
+                return Err(DerError::InvalidLength);
+            }
+            let len = rest[..n].iter().fold(0, |len, &b| (len << 8) | b as usize);
+            rest = &rest[n..];
+            if len < 0x80 {
+                return Err(DerError::InvalidLength);
+            }
+            len
+        };
+        if rest.len() < len {
+            return Err(DerError::UnexpectedEnd);
+        }
+        let (value, rest) = rest.split_at(len);
+        self.data = rest;
+        Ok(value)
+    }
+
+    pub fn integer(&mut self) -> Result<BigInt, DerError> {
+        let value = self.tlv(TAG_INTEGER)?;
+        match value {
+            [] => Err(DerError::InvalidValue),
+            [0x00, b, ..] | [0xff, b, ..] if (value[0] ^ b) & 0x80 == 0 => {
+                Err(DerError::InvalidValue)
+            }
+            _ => Ok(BigInt::from_signed_bytes_be(value)),
+        }
+    }
+
+    /// Reads a non-negative integer.
+    pub fn unsigned_integer(&mut self) -> Result<BigInt, DerError> {
+        let n = self.integer()?;
+        if n.sign() == Sign::Minus {
+            Err(DerError::InvalidValue)
+        } else {
+            Ok(n)
+        }
+    }
+
+    pub fn null(&mut self) -> Result<(), DerError> {
+        if self.tlv(TAG_NULL)?.is_empty() {
+            Ok(())
+        } else {
+            Err(DerError::InvalidValue)
+        }
+    }
+
+    pub fn oid(&mut self) -> Result<Vec<u64>, DerError> {
+        let value = self.tlv(TAG_OID)?;
+        if value.is_empty() || value.last().unwrap() & 0x80 != 0 {
+            return Err(DerError::InvalidValue);
+        }
+        let mut arcs = vec![];
+        let mut arc = 0u64;
+        for &b in value {
+            // Leading 0x80 bytes aren't minimal, and arcs larger than 64 bits aren't supported.
+            if (arc == 0 && b == 0x80) || arc >> 57 != 0 {
+                return Err(DerError::InvalidValue);
+            }
+            arc = (arc << 7) | (b & 0x7f) as u64;
+            if b & 0x80 == 0 {
+                arcs.push(arc);
+                arc = 0;
+            }
+        }
+        let first = arcs[0];
+        let (a, b) = if first < 80 {
+            (first / 40, first % 40)
+        } else {
+            (2, first - 80)
+        };
+        arcs[0] = b;
+        arcs.insert(0, a);
+        Ok(arcs)
+    }
+
+    pub fn octet_string(&mut self) -> Result<&'a [u8], DerError> {
+        self.tlv(TAG_OCTET_STRING)
+    }
+
+    /// Reads a bit string with no unused bits.
+    pub fn bit_string(&mut self) -> Result<&'a [u8], DerError> {
+        match self.tlv(TAG_BIT_STRING)? {
+            [0, rest @ ..] => Ok(rest),
+            _ => Err(DerError::InvalidValue),
+        }
+    }
+
+    /// Reads a constructed value with the given tag and returns a reader over its contents.
+    pub fn constructed(&mut self, tag: u8) -> Result<DerReader<'a>, DerError> {
+        Ok(DerReader::new(self.tlv(tag)?))
+    }
+
+    pub fn sequence(&mut self) -> Result<DerReader<'a>, DerError> {
+        self.constructed(TAG_SEQUENCE)
+    }
+}
+
+/// Armors DER data in PEM (RFC 7468) with the given label.
+pub fn pem_encode(label: &str, der: &[u8]) -> String {
+    let mut out = format!("-----BEGIN {}-----\n", label);
+    let b64 = base64_encode(der, BASE64, true);
+    for line in b64.as_bytes().chunks(64) {
+        out.push_str(std::str::from_utf8(line).unwrap());
+        out.push('\n');
+    }
+    out.push_str(&format!("-----END {}-----\n", label));
+    out
+}
+
+/// Decodes the first PEM block in the text, returns its label and the DER data.
+pub fn pem_decode(pem: &str) -> Result<(String, Vec<u8>), DerError> {
+    let mut lines = pem.lines().map(str::trim);
+    let label = lines
+        .find_map(|line| {
+            line.strip_prefix("-----BEGIN ")
+                .and_then(|line| line.strip_suffix("-----"))
+        })
+        .ok_or(DerError::InvalidPem)?;
+    let end = format!("-----END {}-----", label);
+    let mut b64 = String::new();
+    for line in lines.by_ref() {
+        if line == end {
+            let der = base64_decode(&b64, BASE64).ok_or(DerError::InvalidPem)?;
+            return Ok((label.to_owned(), der));
+        }
+        b64.push_str(line);
+    }
+    Err(DerError::InvalidPem)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::hex;
+
+    #[test]
+    fn integers() {
+        for (n, der) in [
+            (0, "020100"),
+            (127, "02017f"),
+            (128, "02020080"),
+            (256, "02020100"),
+            (-1, "0201ff"),
+            (-128, "020180"),
+            (-129, "0202ff7f"),
+        ] {
+            let mut w = DerWriter::new();
+            w.small_integer(n);
+            assert_eq!(w.finish(), hex(der));
+            assert_eq!(DerReader::new(&hex(der)).integer(), Ok(n.into()));
+        }
+        // Empty and non-minimal encodings.
+        for der in ["0200", "0202007f", "0202ff80"] {
+            assert_eq!(
+                DerReader::new(&hex(der)).integer(),
+                Err(DerError::InvalidValue)
+            );
+        }
+        assert_eq!(
+            DerReader::new(&hex("0201ff")).unsigned_integer(),
+            Err(DerError::InvalidValue)
+        );
+    }
+
+    #[test]
+    fn lengths() {
+        for (len, header) in [(0x7f, "047f"), (0x80, "048180"), (0x12c, "0482012c")] {
+            let value = vec![0x55; len];
+            let mut w = DerWriter::new();
+            w.octet_string(&value);
+            let der = w.finish();
+            assert_eq!(der, [hex(header), value.clone()].concat());
+            let mut r = DerReader::new(&der);
+            assert_eq!(r.octet_string(), Ok(&value[..]));
+            assert_eq!(r.finish(), Ok(()));
+        }
+        // The long form for a short length, a leading zero and the indefinite form.
+        for der in ["04817f", "048200ff", "0480"] {
+            assert_eq!(
+                DerReader::new(&hex(der)).octet_string(),
+                Err(DerError::InvalidLength)
+            );
+        }
+        assert_eq!(
+            DerReader::new(&hex("04050102")).octet_string(),
+            Err(DerError::UnexpectedEnd)
+        );
+        assert_eq!(
+            DerReader::new(&hex("0500")).octet_string(),
+            Err(DerError::UnexpectedTag {
+                expected: TAG_OCTET_STRING,
+                found: TAG_NULL
+            })
+        );
+    }
+
+    #[test]
+    fn oids() {
+        for (oid, der) in [
+            (&[1, 2, 840, 113549, 1, 1, 1][..], "06092a864886f70d010101"),
+            // X.690 8.19.5
+            (&[2, 999, 3], "0603883703"),
+        ] {
+            let mut w = DerWriter::new();
+            w.oid(oid);
+            assert_eq!(w.finish(), hex(der));
+            assert_eq!(DerReader::new(&hex(der)).oid().as_deref(), Ok(oid));
+        }
+        // A leading 0x80 byte and a truncated arc.
+        for der in ["06032a8001", "06022a86", "0600"] {
+            assert_eq!(DerReader::new(&hex(der)).oid(), Err(DerError::InvalidValue));
+        }
+    }
+
+    #[test]
+    fn pem() {
+        let der = (0..=255).collect::<Vec<u8>>();
+        let pem = pem_encode("TEST DATA", &der);
+        assert!(pem.lines().all(|line| line.len() <= 64));
+        let text = format!("Some text before the block\n{}", pem);
+        assert_eq!(pem_decode(&text), Ok(("TEST DATA".into(), der)));
+        let truncated = pem.lines().take(3).collect::<Vec<_>>().join("\n");
+        assert_eq!(pem_decode(&truncated), Err(DerError::InvalidPem));
+        let bad = pem.replace("AAEC", "AA!C");
+        assert_eq!(pem_decode(&bad), Err(DerError::InvalidPem));
+    }
+}
@@ -0,0 +1,410 @@
+//! Finite field Diffie-Hellman in the RFC 7919 FFDHE groups, the RFC 3526 MODP groups or a
+//! validated custom group. The shared secret goes through HKDF-SHA256 before it's used.
+use crate::common::i2osp;
+use crate::primes::is_probable_prime;
+use crate::rsa::MIN_BITS;
+use hmac::{Hmac, Mac};
+use lazy_static::lazy_static;
+use num::{bigint::RandBigInt, one, BigInt, Integer, One};
+use rand::prelude::*;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+
+/// The minimum size of the prime order subgroup of a custom group.
+const MIN_ORDER_BITS: u64 = 224;
+/// The random Miller-Rabin bases after Baillie-PSW when checking the primes of a custom group.
+const PRIME_TEST_ROUNDS: usize = 10;
+/// The length of the derived keys.
+const KEY_LEN: usize = 32;
+
+lazy_static! {
+    /// The RFC 7919 ffdhe2048 prime.
+    static ref FFDHE2048: DhGroup = DhGroup::safe_prime(
+        b"FFFFFFFFFFFFFFFFADF85458A2BB4A9AAFDC5620273D3CF1D8B9C583CE2D3695\
+          A9E13641146433FBCC939DCE249B3EF97D2FE363630C75D8F681B202AEC4617A\
+          D3DF1ED5D5FD65612433F51F5F066ED0856365553DED1AF3B557135E7F57C935\
+          984F0C70E0E68B77E2A689DAF3EFE8721DF158A136ADE73530ACCA4F483A797A\
+          BC0AB182B324FB61D108A94BB2C8E3FBB96ADAB760D7F4681D4F42A3DE394DF4\
+          AE56EDE76372BB190B07A7C8EE0A6D709E02FCE1CDF7E2ECC03404CD28342F61\
+          9172FE9CE98583FF8E4F1232EEF28183C3FE3B1B4C6FAD733BB5FCBC2EC22005\
+          C58EF1837D1683B2C6F34A26C1B2EFFA886B423861285C97FFFFFFFFFFFFFFFF"
+    );
+    /// The RFC 7919 ffdhe3072 prime.
+    static ref FFDHE3072: DhGroup = DhGroup::safe_prime(
+        b"FFFFFFFFFFFFFFFFADF85458A2BB4A9AAFDC5620273D3CF1D8B9C583CE2D3695\
+          A9E13641146433FBCC939DCE249B3EF97D2FE363630C75D8F681B202AEC4617A\
+          D3DF1ED5D5FD65612433F51F5F066ED0856365553DED1AF3B557135E7F57C935\
+          984F0C70E0E68B77E2A689DAF3EFE8721DF158A136ADE73530ACCA4F483A797A\
+          BC0AB182B324FB61D108A94BB2C8E3FBB96ADAB760D7F4681D4F42A3DE394DF4\
+          AE56EDE76372BB190B07A7C8EE0A6D709E02FCE1CDF7E2ECC03404CD28342F61\
+          9172FE9CE98583FF8E4F1232EEF28183C3FE3B1B4C6FAD733BB5FCBC2EC22005\
+          C58EF1837D1683B2C6F34A26C1B2EFFA886B4238611FCFDCDE355B3B6519035B\
+          BC34F4DEF99C023861B46FC9D6E6C9077AD91D2691F7F7EE598CB0FAC186D91C\
+          AEFE130985139270B4130C93BC437944F4FD4452E2D74DD364F2E21E71F54BFF\
+          5CAE82AB9C9DF69EE86D2BC522363A0DABC521979B0DEADA1DBF9A42D5C4484E\
+          0ABCD06BFA53DDEF3C1B20EE3FD59D7C25E41D2B66C62E37FFFFFFFFFFFFFFFF"
+    );
+    /// The RFC 7919 ffdhe4096 prime.
+    static ref FFDHE4096: DhGroup = DhGroup::safe_prime(
+        b"FFFFFFFFFFFFFFFFADF85458A2BB4A9AAFDC5620273D3CF1D8B9C583CE2D3695\
+          A9E13641146433FBCC939DCE249B3EF97D2FE363630C75D8F681B202AEC4617A\
+          D3DF1ED5D5FD65612433F51F5F066ED0856365553DED1AF3B557135E7F57C935\
+          984F0C70E0E68B77E2A689DAF3EFE8721DF158A136ADE73530ACCA4F483A797A\
+          BC0AB182B324FB61D108A94BB2C8E3FBB96ADAB760D7F4681D4F42A3DE394DF4\
+          AE56EDE76372BB190B07A7C8EE0A6D709E02FCE1CDF7E2ECC03404CD28342F61\
+          9172FE9CE98583FF8E4F1232EEF28183C3FE3B1B4C6FAD733BB5FCBC2EC22005\
+          C58EF1837D1683B2C6F34A26C1B2EFFA886B4238611FCFDCDE355B3B6519035B\
+          BC34F4DEF99C023861B46FC9D6E6C9077AD91D2691F7F7EE598CB0FAC186D91C\
+          AEFE130985139270B4130C93BC437944F4FD4452E2D74DD364F2E21E71F54BFF\
+          5CAE82AB9C9DF69EE86D2BC522363A0DABC521979B0DEADA1DBF9A42D5C4484E\
+          0ABCD06BFA53DDEF3C1B20EE3FD59D7C25E41D2B669E1EF16E6F52C3164DF4FB\
+          7930E9E4E58857B6AC7D5F42D69F6D187763CF1D5503400487F55BA57E31CC7A\
+          7135C886EFB4318AED6A1E012D9E6832A907600A918130C46DC778F971AD0038\
+          092999A333CB8B7A1A1DB93D7140003C2A4ECEA9F98D0ACC0A8291CDCEC97DCF\
+          8EC9B55A7F88A46B4DB5A851F44182E1C68A007E5E655F6AFFFFFFFFFFFFFFFF"
+    );
+    /// The RFC 3526 group 14, 2048 bits prime.
+    static ref MODP2048: DhGroup = DhGroup::safe_prime(
+        b"FFFFFFFFFFFFFFFFC90FDAA22168C234C4C6628B80DC1CD129024E088A67CC74\
+          020BBEA63B139B22514A08798E3404DDEF9519B3CD3A431B302B0A6DF25F1437\
+          4FE1356D6D51C245E485B576625E7EC6F44C42E9A637ED6B0BFF5CB6F406B7ED\
+          EE386BFB5A899FA5AE9F24117C4B1FE649286651ECE45B3DC2007CB8A163BF05\
+          98DA48361C55D39A69163FA8FD24CF5F83655D23DCA3AD961C62F356208552BB\
+          9ED529077096966D670C354E4ABC9804F1746C08CA18217C32905E462E36CE3B\
+          E39E772C180E86039B2783A2EC07A28FB5C55DF06F4C52C9DE2BCBF695581718\
+          3995497CEA956AE515D2261898FA051015728E5A8AACAA68FFFFFFFFFFFFFFFF"
+    );
+    /// The RFC 3526 group 15, 3072 bits prime.
+    static ref MODP3072: DhGroup = DhGroup::safe_prime(
+        b"FFFFFFFFFFFFFFFFC90FDAA22168C234C4C6628B80DC1CD129024E088A67CC74\
+          020BBEA63B139B22514A08798E3404DDEF9519B3CD3A431B302B0A6DF25F1437\
+          4FE1356D6D51C245E485B576625E7EC6F44C42E9A637ED6B0BFF5CB6F406B7ED\
+          EE386BFB5A899FA5AE9F24117C4B1FE649286651ECE45B3DC2007CB8A163BF05\
+          98DA48361C55D39A69163FA8FD24CF5F83655D23DCA3AD961C62F356208552BB\
+          9ED529077096966D670C354E4ABC9804F1746C08CA18217C32905E462E36CE3B\
+          E39E772C180E86039B2783A2EC07A28FB5C55DF06F4C52C9DE2BCBF695581718\
+          3995497CEA956AE515D2261898FA051015728E5A8AAAC42DAD33170D04507A33\
+          A85521ABDF1CBA64ECFB850458DBEF0A8AEA71575D060C7DB3970F85A6E1E4C7\
+          ABF5AE8CDB0933D71E8C94E04A25619DCEE3D2261AD2EE6BF12FFA06D98A0864\
+          D87602733EC86A64521F2B18177B200CBBE117577A615D6C770988C0BAD946E2\
+          08E24FA074E5AB3143DB5BFCE0FD108E4B82D120A93AD2CAFFFFFFFFFFFFFFFF"
+    );
+    /// The RFC 3526 group 16, 4096 bits prime.
+    static ref MODP4096: DhGroup = DhGroup::safe_prime(
+        b"FFFFFFFFFFFFFFFFC90FDAA22168C234C4C6628B80DC1CD129024E088A67CC74\
+          020BBEA63B139B22514A08798E3404DDEF9519B3CD3A431B302B0A6DF25F1437\
+          4FE1356D6D51C245E485B576625E7EC6F44C42E9A637ED6B0BFF5CB6F406B7ED\
+          EE386BFB5A899FA5AE9F24117C4B1FE649286651ECE45B3DC2007CB8A163BF05\
+          98DA48361C55D39A69163FA8FD24CF5F83655D23DCA3AD961C62F356208552BB\
+          9ED529077096966D670C354E4ABC9804F1746C08CA18217C32905E462E36CE3B\
+          E39E772C180E86039B2783A2EC07A28FB5C55DF06F4C52C9DE2BCBF695581718\
+          3995497CEA956AE515D2261898FA051015728E5A8AAAC42DAD33170D04507A33\
+          A85521ABDF1CBA64ECFB850458DBEF0A8AEA71575D060C7DB3970F85A6E1E4C7\
+          ABF5AE8CDB0933D71E8C94E04A25619DCEE3D2261AD2EE6BF12FFA06D98A0864\
+          D87602733EC86A64521F2B18177B200CBBE117577A615D6C770988C0BAD946E2\
+          08E24FA074E5AB3143DB5BFCE0FD108E4B82D120A92108011A723C12A787E6D7\
+          88719A10BDBA5B2699C327186AF4E23C1A946834B6150BDA2583E9CA2AD44CE8\
+          DBBBC2DB04DE8EF92E8EFC141FBECAA6287C59474E6BC05D99B2964FA090C3A2\
+          233BA186515BE7ED1F612970CEE2D7AFB81BDD762170481CD0069127D5B05AA9\
+          93B4EA988D8FDDC186FFB7DC90A6C08F4DF435C934063199FFFFFFFFFFFFFFFF"
+    );
+}
+
+/// A named group, all of them are safe-prime groups with the generator 2 and at least
+/// `MIN_BITS` long, so they pass `DhGroup::validate`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NamedGroup {
+    Ffdhe2048,
+    Ffdhe3072,
+    Ffdhe4096,
+    Modp2048,
+    Modp3072,
+    Modp4096,
+}
+
+impl NamedGroup {
+    pub fn from_name(s: &str) -> Option<Self> {
+        match s {
+            "ffdhe2048" => Some(Self::Ffdhe2048),
+            "ffdhe3072" => Some(Self::Ffdhe3072),
+            "ffdhe4096" => Some(Self::Ffdhe4096),
+            "modp2048" => Some(Self::Modp2048),
+            "modp3072" => Some(Self::Modp3072),
+            "modp4096" => Some(Self::Modp4096),
+            _ => None,
+        }
+    }
+
+    pub fn group(self) -> &'static DhGroup {
+        match self {
+            Self::Ffdhe2048 => &FFDHE2048,
+            Self::Ffdhe3072 => &FFDHE3072,
+            Self::Ffdhe4096 => &FFDHE4096,
+            Self::Modp2048 => &MODP2048,
+            Self::Modp3072 => &MODP3072,
+            Self::Modp4096 => &MODP4096,
+        }
+    }
+}
+
+/// The subgroup of order q generated by g mod the prime p.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DhGroup {
+    p: BigInt,
+    q: BigInt,
+    g: BigInt,
+}
+
+impl DhGroup {
+    /// A custom group, it should be checked with `validate` before it's used.
+    pub fn new(p: BigInt, q: BigInt, g: BigInt) -> Self {
+        Self { p, q, g }
+    }
+
+    /// The group of the safe prime p given in hex, with q = (p - 1) / 2 and g = 2.
+    fn safe_prime(hex: &[u8]) -> Self {
+        let p = BigInt::parse_bytes(hex, 16).unwrap();
+        let q = (&p - 1u8) >> 1u8;
+        Self::new(p, q, 2.into())
+    }
+
+    pub fn p(&self) -> &BigInt {
+        &self.p
+    }
+
+    pub fn q(&self) -> &BigInt {
+        &self.q
+    }
+
+    pub fn g(&self) -> &BigInt {
+        &self.g
+    }
+
+    /// The length of the elements in bytes.
+    pub fn size(&self) -> usize {
+        self.p.bits().div_ceil(8) as usize
+    }
+
+    /// Checks that p and q are large primes with q | p - 1, and that g generates the subgroup
+    /// of order q.
+    pub fn validate<R: RandBigInt>(&self, rng: &mut R) -> Result<(), DhError> {
+        let DhGroup { p, q, g } = self;
+        if p.bits() < MIN_BITS || q.bits() < MIN_ORDER_BITS {
+            return Err(DhError::GroupSize);
+        }
+        if !is_probable_prime(p, PRIME_TEST_ROUNDS, rng) {
+            return Err(DhError::CompositeModulus);
+        }
+        if !is_probable_prime(q, PRIME_TEST_ROUNDS, rng) {
+            return Err(DhError::CompositeOrder);
+        }
+        if !(p - 1u8).is_multiple_of(q) {
+            return Err(DhError::OrderMismatch);
+        }
+        if !self.in_subgroup(g) {
+            return Err(DhError::InvalidGenerator);
+        }
+        Ok(())
+    }
+
+    /// Whether 1 < y < p - 1 and y^q ≡ 1 (mod p).
+    fn in_subgroup(&self, y: &BigInt) -> bool {
+        BigInt::one() < *y && *y < &self.p - 1u8 && y.modpow(&self.q, &self.p).is_one()
+    }
+}
+
+/// A failed group or public value validation check.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DhError {
+    GroupSize,
+    CompositeModulus,
+    CompositeOrder,
+    OrderMismatch,
+    InvalidGenerator,
+    InvalidPublicValue,
+    GroupMismatch,
+}
+
+impl std::fmt::Display for DhError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::GroupSize => "the DH group is too small",
+            Self::CompositeModulus => "the DH modulus is composite",
+            Self::CompositeOrder => "the DH subgroup order is composite",
+            Self::OrderMismatch => "the DH subgroup order doesn't divide p - 1",
+            Self::InvalidGenerator => "the DH generator isn't in the subgroup",
+            Self::InvalidPublicValue => "the DH public value isn't in the subgroup",
+            Self::GroupMismatch => "the DH keys are in different groups",
+        })
+    }
+}
+
+impl std::error::Error for DhError {}
+
+/// y = g^x
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PublicDhKey(BigInt, DhGroup);
+
+/// x in [1, q)
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SecretDhKey(BigInt, PublicDhKey);
+
+/// Generates a key pair in the group.
+pub fn gen_dh_key<CR: RandBigInt + CryptoRng>(group: &DhGroup, crng: &mut CR) -> SecretDhKey {
+    let x = crng.gen_bigint_range(&one(), &group.q);
+    let y = group.g.modpow(&x, &group.p);
+    SecretDhKey(x, PublicDhKey(y, group.clone()))
+}
+
+impl PublicDhKey {
+    pub fn y(&self) -> &BigInt {
+        &self.0
+    }
+
+    pub fn group(&self) -> &DhGroup {
+        &self.1
+    }
+
+    /// Checks 1 < y < p - 1 and y^q ≡ 1 (mod p).
+    pub fn validate(&self) -> Result<(), DhError> {
+        if self.1.in_subgroup(&self.0) {
+            Ok(())
+        } else {
+            Err(DhError::InvalidPublicValue)
+        }
+    }
+}
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// HKDF-SHA256 (RFC 5869) without salt, for a single block of output.
+fn hkdf_sha256(ikm: &[u8], info: &[u8]) -> [u8; KEY_LEN] {
+    let mut mac = HmacSha256::new_from_slice(&[0; KEY_LEN]).unwrap();
+    mac.update(ikm);
+    let prk = mac.finalize().into_bytes();
+    let mut mac = HmacSha256::new_from_slice(&prk).unwrap();
+    mac.update(info);
+    mac.update(&[1]);
+    mac.finalize().into_bytes().into()
+}
+
+impl SecretDhKey {
+    pub fn pub_key(&self) -> PublicDhKey {
+        self.1.clone()
+    }
+
+    /// Derives a key shared with the owner of the other public key: the shared secret
+    /// y^x mod p in `size()` bytes, through HKDF-SHA256 with the info.
+    pub fn agree(&self, other: &PublicDhKey, info: &[u8]) -> Result<[u8; KEY_LEN], DhError> {
+        let group = &self.1 .1;
+        if *group != other.1 {
+            return Err(DhError::GroupMismatch);
+        }
+        other.validate()?;
+        let z = other.0.modpow(&self.0, &group.p);
+        Ok(hkdf_sha256(&i2osp(&z, group.size()).unwrap(), info))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NAMED_GROUPS: [NamedGroup; 6] = [
+        NamedGroup::Ffdhe2048,
+        NamedGroup::Ffdhe3072,
+        NamedGroup::Ffdhe4096,
+        NamedGroup::Modp2048,
+        NamedGroup::Modp3072,
+        NamedGroup::Modp4096,
+    ];
+
+    #[test]
+    fn named_groups() {
+        let mut rng = StdRng::seed_from_u64(0);
+        for named in NAMED_GROUPS {
+            let group = named.group();
+            assert!(MIN_BITS <= group.p.bits(), "{:?}", named);
+            assert!(group.in_subgroup(&group.g), "{:?}", named);
+        }
+        // the primality tests of the larger groups are slow in debug builds
+        for named in [NamedGroup::Ffdhe2048, NamedGroup::Modp2048] {
+            assert_eq!(named.group().validate(&mut rng), Ok(()));
+        }
+        assert_eq!(
+            NamedGroup::from_name("ffdhe2048"),
+            Some(NamedGroup::Ffdhe2048)
+        );
+        assert_eq!(NamedGroup::from_name("modp1536"), None);
+    }
+
+    #[test]
+    fn custom_groups() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let DhGroup { p, q, g } = NamedGroup::Ffdhe2048.group().clone();
+        let small = DhGroup::new(23.into(), 11.into(), 2.into());
+        assert_eq!(small.validate(&mut rng), Err(DhError::GroupSize));
+        let composite_p = DhGroup::new(&p + 2, q.clone(), g.clone());
+        assert_eq!(
+            composite_p.validate(&mut rng),
+            Err(DhError::CompositeModulus)
+        );
+        let composite_q = DhGroup::new(p.clone(), &q - 2, g.clone());
+        assert_eq!(composite_q.validate(&mut rng), Err(DhError::CompositeOrder));
+        let mut q2: BigInt = &q + 2;
+        while !is_probable_prime(&q2, 1, &mut rng) {
+            q2 += 2;
+        }
+        let wrong_q = DhGroup::new(p.clone(), q2, g.clone());
+        assert_eq!(wrong_q.validate(&mut rng), Err(DhError::OrderMismatch));
+        let mut g2: BigInt = &g + 1;
+        while g2.modpow(&q, &p).is_one() {
+            g2 += 1;
+        }
+        let wrong_g = DhGroup::new(p.clone(), q.clone(), g2);
+        assert_eq!(wrong_g.validate(&mut rng), Err(DhError::InvalidGenerator));
+        let one_g = DhGroup::new(p, q, 1.into());
+        assert_eq!(one_g.validate(&mut rng), Err(DhError::InvalidGenerator));
+    }
+
+    #[test]
+    fn key_agreement() {
+        let mut crng = StdRng::seed_from_u64(0);
+        let group = NamedGroup::Ffdhe2048.group();
+        let alice = gen_dh_key(group, &mut crng);
+        let bob = gen_dh_key(group, &mut crng);
+        let key = alice.agree(&bob.pub_key(), b"info").unwrap();
+        assert_eq!(bob.agree(&alice.pub_key(), b"info"), Ok(key));
+        assert_ne!(bob.agree(&alice.pub_key(), b"other info"), Ok(key));
+
+        let other_group = gen_dh_key(NamedGroup::Modp2048.group(), &mut crng);
+        assert_eq!(
+            alice.agree(&other_group.pub_key(), b"info"),
+            Err(DhError::GroupMismatch)
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_public_values() {
+        let mut crng = StdRng::seed_from_u64(0);
+        let group = NamedGroup::Ffdhe2048.group();
+        let alice = gen_dh_key(group, &mut crng);
+        // a quadratic non-residue, of order 2q
+        let mut outside = BigInt::from(3);
+        while outside.modpow(&group.q, &group.p).is_one() {
+            outside += 1;
+        }
+        for y in [
+            0.into(),
+            1.into(),
+            &group.p - 1,
+            group.p.clone(),
+            &group.p + 4,
+            outside,
+        ] {
+            let pk = PublicDhKey(y, group.clone());
+            assert_eq!(pk.validate(), Err(DhError::InvalidPublicValue));
+            assert_eq!(alice.agree(&pk, b"info"), Err(DhError::InvalidPublicValue));
+        }
+    }
+}
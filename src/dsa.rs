@@ -0,0 +1,393 @@
+//! DSA (FIPS 186-4) with SHA-256: domain parameters from a seed (A.1.1.2), a canonical
+//! generator (A.2.3), and signatures with deterministic nonces (RFC 6979).
+use crate::common::{i2osp, mod_inv, os2ip};
+use crate::primes::{is_probable_prime, quick_prime_check};
+use digest::Digest;
+use hmac::{Hmac, Mac};
+use num::{bigint::RandBigInt, one, BigInt, One, Signed, Zero};
+use rand::prelude::*;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+
+/// The bit length of a SHA-256 hash.
+const OUTLEN: u64 = 256;
+/// The random Miller-Rabin bases after Baillie-PSW, its Lucas test allows far fewer rounds
+/// than plain Miller-Rabin (FIPS 186-4 table C.1).
+const P_ROUNDS: usize = 4;
+const Q_ROUNDS: usize = 27;
+/// The index of the canonical generator.
+const GGEN_INDEX: u8 = 1;
+
+/// The approved (L, N) sizes for SHA-256.
+fn approved_sizes(l: u64, n: u64) -> bool {
+    matches!((l, n), (2048, 224) | (2048, 256) | (3072, 256))
+}
+
+fn sha256(parts: &[&[u8]]) -> BigInt {
+    let mut h = Sha256::new();
+    for part in parts {
+        h.update(part);
+    }
+    os2ip(&h.finalize())
+}
+
+/// p, q and g, where q is an N bit prime dividing p - 1 and g generates the subgroup of
+/// order q.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DsaParams {
+    p: BigInt,
+    q: BigInt,
+    g: BigInt,
+}
+
+/// The domain parameter seed and the counter the primes were found at, anyone can check the
+/// parameters with them.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DomainSeed {
+    seed: Vec<u8>,
+    counter: u32,
+}
+
+/// The primes of a seed (FIPS 186-4 A.1.1.2 from step 6): q from the hash of the seed, then up
+/// to 4L candidates for p from the hashes of the following seeds. Returns `None` if q isn't
+/// prime or no candidate is.
+fn primes_from_seed<R: RandBigInt>(
+    l: u64,
+    n: u64,
+    seed: &[u8],
+    rng: &mut R,
+) -> Option<(BigInt, BigInt, u32)> {
+    let seedlen = seed.len() as u64 * 8;
+    let u = sha256(&[seed]) % (BigInt::one() << (n - 1));
+    let q = (BigInt::one() << (n - 1)) + &u + 1u8 - (&u % 2u8);
+    if !is_probable_prime(&q, Q_ROUNDS, rng) {
+        return None;
+    }
+    let blocks = l.div_ceil(OUTLEN) - 1;
+    let b = l - 1 - blocks * OUTLEN;
+    let seed_mod = BigInt::one() << seedlen;
+    let seed = os2ip(seed);
+    let mut offset = 1u64;
+    for counter in 0..4 * l as u32 {
+        let mut w = BigInt::zero();
+        for j in 0..=blocks {
+            let s = (&seed + offset + j) % &seed_mod;
+            let mut v = sha256(&[&i2osp(&s, seedlen as usize / 8).unwrap()]);
+            if j == blocks {
+                v %= BigInt::one() << b;
+            }
+            w += v << (j * OUTLEN);
+        }
+        let x = w + (BigInt::one() << (l - 1));
+        let c = &x % (&q << 1u8);
+        let p = x - (c - 1u8);
+        if p.bits() == l && quick_prime_check(&p) && is_probable_prime(&p, P_ROUNDS, rng) {
+            return Some((p, q, counter));
+        }
+        offset += blocks + 1;
+    }
+    None
+}
+
+/// The canonical generator of FIPS 186-4 A.2.3: the first W^((p - 1) / q) > 1, where W is the
+/// hash of the seed, "ggen", the index and a counter.
+fn canonical_generator(p: &BigInt, q: &BigInt, seed: &[u8]) -> BigInt {
+    let e = (p - 1u8) / q;
+    (1u16..)
+        .map(|count| {
+            let w = sha256(&[seed, b"ggen", &[GGEN_INDEX], &count.to_be_bytes()]);
+            w.modpow(&e, p)
+        })
+        .find(|g| BigInt::one() < *g)
+        .unwrap()
+}
+
+/// Generates domain parameters with an L bit p and an N bit q, from random N bit seeds.
+/// Returns `None` if (L, N) isn't (2048, 224), (2048, 256) or (3072, 256).
+pub fn gen_dsa_params<R: RandBigInt, CR: RngCore + CryptoRng>(
+    l: u64,
+    n: u64,
+    rng: &mut R,
+    crng: &mut CR,
+) -> Option<(DsaParams, DomainSeed)> {
+    if !approved_sizes(l, n) {
+        return None;
+    }
+    let mut seed = vec![0; n as usize / 8];
+    loop {
+        crng.fill_bytes(&mut seed);
+        if let Some((p, q, counter)) = primes_from_seed(l, n, &seed, rng) {
+            let g = canonical_generator(&p, &q, &seed);
+            return Some((DsaParams { p, q, g }, DomainSeed { seed, counter }));
+        }
+    }
+}
+
+impl DsaParams {
+    pub fn p(&self) -> &BigInt {
+        &self.p
+    }
+
+    pub fn q(&self) -> &BigInt {
+        &self.q
+    }
+
+    pub fn g(&self) -> &BigInt {
+        &self.g
+    }
+
+    /// Checks that the primes come from the seed at the counter (FIPS 186-4 A.1.1.3) and that
+    /// g is the canonical generator of the seed (A.2.4).
+    pub fn verify_seed<R: RandBigInt>(&self, seed: &DomainSeed, rng: &mut R) -> bool {
+        let (l, n) = (self.p.bits(), self.q.bits());
+        if !approved_sizes(l, n) || (seed.seed.len() as u64) < n / 8 || 4 * l <= seed.counter as u64
+        {
+            return false;
+        }
+        primes_from_seed(l, n, &seed.seed, rng)
+            == Some((self.p.clone(), self.q.clone(), seed.counter))
+            && self.g == canonical_generator(&self.p, &self.q, &seed.seed)
+    }
+}
+
+/// y = g^x
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PublicDsaKey(BigInt, DsaParams);
+
+/// x in [1, q)
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SecretDsaKey(BigInt, PublicDsaKey);
+
+/// Generates a key pair with the domain parameters.
+pub fn gen_dsa_key<CR: RandBigInt + CryptoRng>(params: &DsaParams, crng: &mut CR) -> SecretDsaKey {
+    let x = crng.gen_bigint_range(&one(), &params.q);
+    let y = params.g.modpow(&x, &params.p);
+    SecretDsaKey(x, PublicDsaKey(y, params.clone()))
+}
+
+/// The leftmost qlen bits of the bytes, as an integer.
+fn bits2int(b: &[u8], qlen: u64) -> BigInt {
+    let x = os2ip(b);
+    let blen = b.len() as u64 * 8;
+    if qlen < blen {
+        x >> (blen - qlen)
+    } else {
+        x
+    }
+}
+
+type HmacSha256 = Hmac<Sha256>;
+
+fn hmac(key: &[u8], parts: &[&[u8]]) -> Vec<u8> {
+    let mut mac = HmacSha256::new_from_slice(key).unwrap();
+    for part in parts {
+        mac.update(part);
+    }
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// The HMAC-DRBG of RFC 6979 section 3.2, it yields the candidate nonces k in [1, q).
+struct Rfc6979<'a> {
+    q: &'a BigInt,
+    k: Vec<u8>,
+    v: Vec<u8>,
+}
+
+impl<'a> Rfc6979<'a> {
+    fn new(x: &BigInt, h1: &[u8], q: &'a BigInt) -> Self {
+        let qlen = q.bits();
+        let rlen = qlen.div_ceil(8) as usize;
+        let x = i2osp(x, rlen).unwrap();
+        let h = i2osp(&(bits2int(h1, qlen) % q), rlen).unwrap();
+        let v = vec![1; 32];
+        let k = hmac(&[0; 32], &[&v, &[0], &x, &h]);
+        let v = hmac(&k, &[&v]);
+        let k = hmac(&k, &[&v, &[1], &x, &h]);
+        let v = hmac(&k, &[&v]);
+        Self { q, k, v }
+    }
+}
+
+impl Iterator for Rfc6979<'_> {
+    type Item = BigInt;
+
+    fn next(&mut self) -> Option<BigInt> {
+        let qlen = self.q.bits();
+        loop {
+            let mut t = vec![];
+            while (t.len() as u64 * 8) < qlen {
+                self.v = hmac(&self.k, &[&self.v]);
+                t.extend_from_slice(&self.v);
+            }
+            let k = bits2int(&t, qlen);
+            self.k = hmac(&self.k, &[&self.v, &[0]]);
+            self.v = hmac(&self.k, &[&self.v]);
+            if !k.is_zero() && k < *self.q {
+                return Some(k);
+            }
+        }
+    }
+}
+
+impl PublicDsaKey {
+    pub fn y(&self) -> &BigInt {
+        &self.0
+    }
+
+    pub fn params(&self) -> &DsaParams {
+        &self.1
+    }
+
+    /// Checks 1 < y < p and y^q ≡ 1 (mod p).
+    pub fn validate(&self) -> bool {
+        let DsaParams { p, q, .. } = &self.1;
+        BigInt::one() < self.0 && self.0 < *p && self.0.modpow(q, p).is_one()
+    }
+
+    /// Verifies the signature (r, s) of the message.
+    pub fn verify(&self, m: &[u8], sig: &(BigInt, BigInt)) -> bool {
+        let DsaParams { p, q, g } = &self.1;
+        let (r, s) = sig;
+        let in_range = |x: &BigInt| x.is_positive() && x < q;
+        if !in_range(r) || !in_range(s) || !self.validate() {
+            return false;
+        }
+        let z = bits2int(&Sha256::digest(m), q.bits());
+        let w = mod_inv(s, q);
+        let u1 = (z * &w) % q;
+        let u2 = (r * w) % q;
+        let v = (g.modpow(&u1, p) * self.0.modpow(&u2, p)) % p % q;
+        v == *r
+    }
+}
+
+impl SecretDsaKey {
+    pub fn pub_key(&self) -> PublicDsaKey {
+        self.1.clone()
+    }
+
+    /// Signs the message, with the nonce derived from the key and the hash of the message.
+    pub fn sign(&self, m: &[u8]) -> (BigInt, BigInt) {
+        let DsaParams { p, q, g } = &self.1 .1;
+        let h1 = Sha256::digest(m);
+        let z = bits2int(&h1, q.bits());
+        Rfc6979::new(&self.0, &h1, q)
+            .find_map(|k| {
+                let r = g.modpow(&k, p) % q;
+                let s = (mod_inv(&k, q) * (&z + &self.0 * &r)) % q;
+                (!r.is_zero() && !s.is_zero()).then_some((r, s))
+            })
+            .unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::hex;
+    use crate::der::{pem_decode, DerReader};
+
+    /// Domain parameters from `openssl genpkey -genparam -algorithm DHX` with the FIPS 186-4
+    /// generation, SHA-256 and the generator index 1. The X9.42 encoding keeps the seed and
+    /// the counter.
+    fn openssl_params() -> (DsaParams, DomainSeed) {
+        let (_, der) = pem_decode(include_str!("../testdata/dhx-2048-224.pem")).unwrap();
+        let mut reader = DerReader::new(&der);
+        let mut params = reader.sequence().unwrap();
+        let p = params.unsigned_integer().unwrap();
+        let g = params.unsigned_integer().unwrap();
+        let q = params.unsigned_integer().unwrap();
+        let mut validation = params.sequence().unwrap();
+        let seed = validation.bit_string().unwrap().to_vec();
+        let counter = validation.unsigned_integer().unwrap().try_into().unwrap();
+        (DsaParams { p, q, g }, DomainSeed { seed, counter })
+    }
+
+    #[test]
+    fn openssl_domain_seed() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let (params, seed) = openssl_params();
+        assert_eq!((params.p.bits(), params.q.bits()), (2048, 224));
+        assert!(params.verify_seed(&seed, &mut rng));
+
+        let wrong_counter = DomainSeed {
+            counter: seed.counter + 1,
+            ..seed.clone()
+        };
+        assert!(!params.verify_seed(&wrong_counter, &mut rng));
+        let mut wrong_seed = seed.clone();
+        wrong_seed.seed[0] ^= 1;
+        assert!(!params.verify_seed(&wrong_seed, &mut rng));
+        let wrong_g = DsaParams {
+            g: params.g.modpow(&2.into(), &params.p),
+            ..params.clone()
+        };
+        assert!(!wrong_g.verify_seed(&seed, &mut rng));
+    }
+
+    #[test]
+    fn rfc6979_nonces() {
+        // RFC 6979 A.2.1 and A.2.2 with SHA-256, the first nonce is the one used
+        let q160 = os2ip(&hex("996f967f6c8e388d9e28d01e205fba957a5698b1"));
+        let x160 = os2ip(&hex("411602cb19a6ccc34494d79d98ef1e7ed5af25f7"));
+        let q256 = os2ip(&hex(
+            "f2c3119374ce76c9356990b465374a17f23f9ed35089bd969f61c6dde9998c1f",
+        ));
+        let x256 = os2ip(&hex(
+            "69c7548c21d0dfea6b9a51c9ead4e27c33d3b3f180316e5bcab92c933f0e4dbc",
+        ));
+        for (q, x, m, k) in [
+            (
+                &q160,
+                &x160,
+                "sample",
+                "519ba0546d0c39202a7d34d7dfa5e760b318bcfb",
+            ),
+            (
+                &q160,
+                &x160,
+                "test",
+                "5a67592e8128e03a417b0484410fb72c0b630e1a",
+            ),
+            (
+                &q256,
+                &x256,
+                "sample",
+                "8926a27c40484216f052f4427cfd5647338b7b3939bc6573af4333569d597c52",
+            ),
+            (
+                &q256,
+                &x256,
+                "test",
+                "1d6ce6dda1c5d37307839cd03ab0a5cbb18e60d800937d67dfb4479aac8dead7",
+            ),
+        ] {
+            let h1 = Sha256::digest(m);
+            assert_eq!(
+                Rfc6979::new(x, &h1, q).next(),
+                Some(os2ip(&hex(k))),
+                "{}",
+                m
+            );
+        }
+    }
+
+    #[test]
+    fn signatures() {
+        let mut crng = StdRng::seed_from_u64(0);
+        let (params, _) = openssl_params();
+        let sk = gen_dsa_key(&params, &mut crng);
+        let pk = sk.pub_key();
+        assert!(pk.validate());
+        let sig = sk.sign(b"message");
+        assert_eq!(sk.sign(b"message"), sig);
+        assert!(pk.verify(b"message", &sig));
+        assert!(!pk.verify(b"other message", &sig));
+        let (r, s) = sig;
+        assert!(!pk.verify(b"message", &(r.clone(), &s + 1)));
+        assert!(!pk.verify(b"message", &(&r + &params.q, s.clone())));
+        assert!(!pk.verify(b"message", &(r, 0.into())));
+        let other = gen_dsa_key(&params, &mut crng).pub_key();
+        assert!(!other.verify(b"message", &sk.sign(b"message")));
+    }
+}
@@ -1,13 +1,18 @@
-use crate::common::{hash_bigint, mod_div, mod_inv};
+use crate::common::{hash_bigint, hash_bigints, mod_div, mod_inv, mod_sqrt};
 use digest::{Digest, FixedOutputReset};
 use lazy_static::lazy_static;
-use num::{bigint::RandBigInt, one, zero, BigInt, Integer};
+use num::{
+    bigint::{RandBigInt, Sign},
+    one, zero, BigInt, Integer, Signed, Zero,
+};
 use rand::prelude::*;
 use serde::{Deserialize, Serialize};
+use sha2::Sha512;
+use std::hash::Hash;
 use std::ops::{Add, Mul, Neg, Sub};
 
 pub trait AddGroup: PartialEq + Eq + 'static {
-    type Point: Clone + Eq + PartialEq + 'static;
+    type Point: Clone + Eq + PartialEq + Hash + 'static;
     fn identity(&self) -> Self::Point;
     fn generator(&self) -> &Self::Point;
     fn order(&self) -> &BigInt;
@@ -20,32 +25,50 @@ pub trait AddGroup: PartialEq + Eq + 'static {
         self.add(p, p)
     }
     fn mul(&self, k: &BigInt, p: &Self::Point) -> Self::Point {
-        let mut out = self.identity();
-        let mut p = p.clone();
-        let mut k = k.clone();
-        while zero::<BigInt>() < k {
-            if k == one() {
-                out = self.add(&out, &p);
-            } else {
-                if k.is_odd() {
-                    out = self.add(&out, &p);
-                }
-                p = self.double(&p);
-            }
-            k >>= 1;
-        }
-        out
+        double_and_add(self, k, p)
     }
     fn validate(&self, p: &Self::Point) -> bool;
     fn to_bigint(p: &Self::Point) -> &BigInt;
 }
 
-#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+/// Double-and-add with the group law alone, the default scalar multiplication of `AddGroup`.
+/// Negative k give the identity.
+fn double_and_add<C: AddGroup + ?Sized>(curve: &C, k: &BigInt, p: &C::Point) -> C::Point {
+    let mut out = curve.identity();
+    let mut p = p.clone();
+    let mut k = k.clone();
+    while zero::<BigInt>() < k {
+        if k == one() {
+            out = curve.add(&out, &p);
+        } else {
+            if k.is_odd() {
+                out = curve.add(&out, &p);
+            }
+            p = curve.double(&p);
+        }
+        k >>= 1;
+    }
+    out
+}
+
+#[derive(Clone, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
 pub struct Pos {
     x: BigInt,
     y: BigInt,
 }
 
+impl Pos {
+    pub fn new(x: BigInt, y: BigInt) -> Self {
+        Self { x, y }
+    }
+    pub fn x(&self) -> &BigInt {
+        &self.x
+    }
+    pub fn y(&self) -> &BigInt {
+        &self.y
+    }
+}
+
 #[derive(Clone, PartialEq, Eq, Debug)]
 pub struct Point<C: AddGroup + 'static> {
     pub curve: &'static C,
@@ -184,7 +207,7 @@ pub fn ecdh<D: Digest + FixedOutputReset, C: AddGroup>(
     other_pk: &Point<C>,
     h: &mut D,
 ) -> BigInt {
-    hash_bigint(&(sk * other_pk).to_bigint(), h)
+    hash_bigint((sk * other_pk).to_bigint(), h)
 }
 
 pub fn ecdsa_sign<D: Digest + FixedOutputReset, C: AddGroup, CR: RandBigInt + CryptoRng>(
@@ -193,6 +216,16 @@ pub fn ecdsa_sign<D: Digest + FixedOutputReset, C: AddGroup, CR: RandBigInt + Cr
     curve: &'static C,
     crng: &mut CR,
     h: &mut D,
+) -> (BigInt, BigInt) {
+    ecdsa_sign_prehashed(&hash_bigint(m, h), sk, curve, crng)
+}
+
+/// ECDSA signature of an already hashed message `z`.
+pub fn ecdsa_sign_prehashed<C: AddGroup, CR: RandBigInt + CryptoRng>(
+    z: &BigInt,
+    sk: &BigInt,
+    curve: &'static C,
+    crng: &mut CR,
 ) -> (BigInt, BigInt) {
     let g = Point::<C> {
         pos: curve.generator().clone(),
@@ -200,7 +233,7 @@ pub fn ecdsa_sign<D: Digest + FixedOutputReset, C: AddGroup, CR: RandBigInt + Cr
     };
     let n = curve.order();
 
-    let z = hash_bigint(m, h).mod_floor(n);
+    let z = z.mod_floor(n);
     let k = crng.gen_bigint_range(&one(), n);
     let r = C::to_bigint(&(&k * g).pos).mod_floor(n);
     let s = mod_div(&(z + &r * sk), &k, n);
@@ -213,6 +246,16 @@ pub fn ecdsa_verify<D: Digest + FixedOutputReset, C: AddGroup>(
     ds: &(BigInt, BigInt),
     curve: &'static C,
     h: &mut D,
+) -> bool {
+    ecdsa_verify_prehashed(&hash_bigint(m, h), pk, ds, curve)
+}
+
+/// ECDSA verification of a signature of an already hashed message `z`.
+pub fn ecdsa_verify_prehashed<C: AddGroup>(
+    z: &BigInt,
+    pk: &C::Point,
+    ds: &(BigInt, BigInt),
+    curve: &'static C,
 ) -> bool {
     let g = Point::<C> {
         pos: curve.generator().clone(),
@@ -223,19 +266,28 @@ pub fn ecdsa_verify<D: Digest + FixedOutputReset, C: AddGroup>(
         pos: pk.clone(),
         curve,
     };
-    if pk.validate() && pk.pos != curve.identity() && (curve.order() * &pk).pos == curve.identity()
+    let (r, s) = ds;
+    let in_range = |x: &BigInt| &zero::<BigInt>() < x && x < n;
+    if in_range(r)
+        && in_range(s)
+        && pk.validate()
+        && pk.pos != curve.identity()
+        && (curve.order() * &pk).pos == curve.identity()
     {
-        let (r, s) = ds;
-        let z = hash_bigint(m, h).mod_floor(n);
-        let inv_s = mod_inv(&s, n);
+        let z = z.mod_floor(n);
+        let inv_s = mod_inv(s, n);
         let u1 = (z * &inv_s).mod_floor(n);
         let u2 = (r * &inv_s).mod_floor(n);
-        *r == (u1 * g + u2 * pk).to_bigint().mod_floor(n)
+        let p = u1 * g + u2 * pk;
+        p.pos != curve.identity() && *r == p.to_bigint().mod_floor(n)
     } else {
         false
     }
 }
 
+/// Schnorr signature (R, s) with a random nonce k, R = kG and s = k + H(R || pk || m) sk. The
+/// challenge binds the nonce point and the public key, but it isn't compatible with Ed25519. Use
+/// `ed25519_sign` for RFC 8032 signatures.
 pub fn eddsa_sign<D: Digest + FixedOutputReset, C: AddGroup, CR: RandBigInt + CryptoRng>(
     m: &BigInt,
     sk: &BigInt,
@@ -249,13 +301,15 @@ pub fn eddsa_sign<D: Digest + FixedOutputReset, C: AddGroup, CR: RandBigInt + Cr
     };
     let n = curve.order();
 
-    let k = crng.gen_bigint_range(&zero(), n);
-    let r = &k * g;
-    let z = hash_bigint(m, h).mod_floor(n);
+    let k = crng.gen_bigint_range(&one(), n);
+    let r = &k * &g;
+    let pk = sk * g;
+    let z = hash_bigints(&[r.to_bigint(), pk.to_bigint(), m], h).mod_floor(n);
     let s = (k + z * sk).mod_floor(n);
     (r.pos, s)
 }
 
+/// Verifies an `eddsa_sign` signature.
 pub fn eddsa_verify<D: Digest + FixedOutputReset, C: AddGroup>(
     m: &BigInt,
     pk: &C::Point,
@@ -278,13 +332,378 @@ pub fn eddsa_verify<D: Digest + FixedOutputReset, C: AddGroup>(
             pos: r.clone(),
             curve,
         };
-        let z = hash_bigint(m, h).mod_floor(curve.order());
+        if !r.validate() || r.pos == curve.identity() {
+            return false;
+        }
+        let z = hash_bigints(&[r.to_bigint(), pk.to_bigint(), m], h).mod_floor(curve.order());
         s * g == r + z * pk
     } else {
         false
     }
 }
 
+/// The u-coordinate of the Curve25519 base point.
+pub const X25519_BASE_POINT: [u8; 32] = {
+    let mut u = [0; 32];
+    u[0] = 9;
+    u
+};
+
+const MASK51: u64 = (1 << 51) - 1;
+
+/// An element of GF(2^255 - 19) in five 51-bit limbs, not necessarily reduced. All the
+/// operations run in time independent of the values, unlike `BigInt` arithmetic.
+#[derive(Clone, Copy)]
+struct Fe([u64; 5]);
+
+impl Fe {
+    const ZERO: Fe = Fe([0; 5]);
+    const ONE: Fe = Fe([1, 0, 0, 0, 0]);
+
+    /// Loads 255 bits in little endian, the high bit is ignored.
+    fn from_bytes(b: &[u8; 32]) -> Fe {
+        let w: [u64; 4] =
+            std::array::from_fn(|i| u64::from_le_bytes(b[8 * i..8 * i + 8].try_into().unwrap()));
+        Fe([
+            w[0] & MASK51,
+            (w[0] >> 51 | w[1] << 13) & MASK51,
+            (w[1] >> 38 | w[2] << 26) & MASK51,
+            (w[2] >> 25 | w[3] << 39) & MASK51,
+            (w[3] >> 12) & MASK51,
+        ])
+    }
+
+    /// The canonical encoding, fully reduced mod p.
+    fn to_bytes(self) -> [u8; 32] {
+        let mut h = Fe::carry(self.0.map(u128::from)).0;
+        // q = 1 if h >= p, then h + 19q with the carry out of bit 255 dropped is h - qp
+        let mut q = (h[0] + 19) >> 51;
+        for l in &h[1..] {
+            q = (l + q) >> 51;
+        }
+        h[0] += 19 * q;
+        for i in 0..4 {
+            h[i + 1] += h[i] >> 51;
+            h[i] &= MASK51;
+        }
+        h[4] &= MASK51;
+        let w = [
+            h[0] | h[1] << 51,
+            h[1] >> 13 | h[2] << 38,
+            h[2] >> 26 | h[3] << 25,
+            h[3] >> 39 | h[4] << 12,
+        ];
+        let mut out = [0; 32];
+        for (chunk, w) in out.chunks_exact_mut(8).zip(w) {
+            chunk.copy_from_slice(&w.to_le_bytes());
+        }
+        out
+    }
+
+    /// Carries the limbs down to 51 bits, the carry out of the top limb wraps around times 19
+    /// since 2^255 ≡ 19.
+    fn carry(mut l: [u128; 5]) -> Fe {
+        for i in 0..4 {
+            l[i + 1] += l[i] >> 51;
+            l[i] &= MASK51 as u128;
+        }
+        l[0] += 19 * (l[4] >> 51);
+        l[4] &= MASK51 as u128;
+        l[1] += l[0] >> 51;
+        l[0] &= MASK51 as u128;
+        Fe(l.map(|x| x as u64))
+    }
+
+    fn square(&self) -> Fe {
+        self * self
+    }
+
+    fn mul_small(&self, k: u32) -> Fe {
+        Fe::carry(self.0.map(|x| x as u128 * k as u128))
+    }
+
+    /// x^(p - 2), the exponent is public so square-and-multiply is fine.
+    fn invert(&self) -> Fe {
+        // p - 2 = 2^255 - 21
+        let mut out = Fe::ONE;
+        for i in (0..255).rev() {
+            out = out.square();
+            if i >= 5 || (0b01011 >> i) & 1 == 1 {
+                out = &out * self;
+            }
+        }
+        out
+    }
+
+    /// Swaps a and b if swap is 1 and leaves them if it's 0, with a mask instead of a branch.
+    fn cswap(swap: u64, a: &mut Fe, b: &mut Fe) {
+        let mask = swap.wrapping_neg();
+        for (x, y) in a.0.iter_mut().zip(b.0.iter_mut()) {
+            let t = mask & (*x ^ *y);
+            *x ^= t;
+            *y ^= t;
+        }
+    }
+}
+
+impl Add for &Fe {
+    type Output = Fe;
+    fn add(self, other: &Fe) -> Fe {
+        Fe::carry(std::array::from_fn(|i| {
+            self.0[i] as u128 + other.0[i] as u128
+        }))
+    }
+}
+
+impl Sub for &Fe {
+    type Output = Fe;
+    /// Adds 2p first so that the limbs stay positive, the inputs are carried.
+    fn sub(self, other: &Fe) -> Fe {
+        let two_p = [
+            (MASK51 - 18) << 1,
+            MASK51 << 1,
+            MASK51 << 1,
+            MASK51 << 1,
+            MASK51 << 1,
+        ];
+        Fe::carry(std::array::from_fn(|i| {
+            (self.0[i] + two_p[i] - other.0[i]) as u128
+        }))
+    }
+}
+
+impl Mul for &Fe {
+    type Output = Fe;
+    /// Schoolbook multiplication, the products beyond the fifth limb are folded back times 19.
+    fn mul(self, other: &Fe) -> Fe {
+        let a = self.0.map(u128::from);
+        let b = other.0.map(u128::from);
+        let b19 = b.map(|x| 19 * x);
+        Fe::carry([
+            a[0] * b[0] + a[1] * b19[4] + a[2] * b19[3] + a[3] * b19[2] + a[4] * b19[1],
+            a[0] * b[1] + a[1] * b[0] + a[2] * b19[4] + a[3] * b19[3] + a[4] * b19[2],
+            a[0] * b[2] + a[1] * b[1] + a[2] * b[0] + a[3] * b19[4] + a[4] * b19[3],
+            a[0] * b[3] + a[1] * b[2] + a[2] * b[1] + a[3] * b[0] + a[4] * b19[4],
+            a[0] * b[4] + a[1] * b[3] + a[2] * b[2] + a[3] * b[1] + a[4] * b[0],
+        ])
+    }
+}
+
+/// The RFC 7748 X25519 function: the u-coordinate of the clamped scalar times the point with
+/// the u-coordinate u, both in little endian. The high bit of u is ignored and non-canonical
+/// values are reduced.
+///
+/// The ladder runs on fixed-width field elements and swaps with masks, so neither the control
+/// flow nor the time of the arithmetic depends on the scalar.
+pub fn x25519(scalar: [u8; 32], u: [u8; 32]) -> [u8; 32] {
+    let mut k = scalar;
+    k[0] &= 0xf8;
+    k[31] &= 0x7f;
+    k[31] |= 0x40;
+    let x1 = Fe::from_bytes(&u);
+    let (mut x2, mut z2) = (Fe::ONE, Fe::ZERO);
+    let (mut x3, mut z3) = (x1, Fe::ONE);
+    let mut swap = 0;
+    for t in (0..255).rev() {
+        let k_t = (k[t / 8] >> (t % 8)) as u64 & 1;
+        swap ^= k_t;
+        Fe::cswap(swap, &mut x2, &mut x3);
+        Fe::cswap(swap, &mut z2, &mut z3);
+        swap = k_t;
+        let a = &x2 + &z2;
+        let aa = a.square();
+        let b = &x2 - &z2;
+        let bb = b.square();
+        let e = &aa - &bb;
+        let c = &x3 + &z3;
+        let d = &x3 - &z3;
+        let da = &d * &a;
+        let cb = &c * &b;
+        x3 = (&da + &cb).square();
+        z3 = &x1 * &(&da - &cb).square();
+        z2 = &e * &(&aa + &e.mul_small(121665));
+        x2 = &aa * &bb;
+    }
+    Fe::cswap(swap, &mut x2, &mut x3);
+    Fe::cswap(swap, &mut z2, &mut z3);
+    (&x2 * &z2.invert()).to_bytes()
+}
+
+/// The X25519 public key of a secret scalar.
+pub fn x25519_pub_key(scalar: [u8; 32]) -> [u8; 32] {
+    x25519(scalar, X25519_BASE_POINT)
+}
+
+/// The X25519 shared secret with the other public key, `None` if it's all zero because the
+/// public key is a low order point.
+pub fn x25519_agree(scalar: [u8; 32], other_pk: [u8; 32]) -> Option<[u8; 32]> {
+    let z = x25519(scalar, other_pk);
+    // Ors all the bytes so that the check doesn't depend on where the first non-zero byte is.
+    (z.iter().fold(0, |acc, b| acc | b) != 0).then_some(z)
+}
+
+/// Expands an RFC 8032 Ed25519 seed into the secret scalar and the nonce prefix.
+pub fn ed25519_expand(seed: &[u8; 32]) -> (BigInt, [u8; 32]) {
+    let h = Sha512::digest(seed);
+    let mut a: [u8; 32] = h[..32].try_into().unwrap();
+    a[0] &= 0xf8;
+    a[31] &= 0x7f;
+    a[31] |= 0x40;
+    (
+        BigInt::from_bytes_le(Sign::Plus, &a),
+        h[32..].try_into().unwrap(),
+    )
+}
+
+/// The Ed25519 public key of an RFC 8032 seed.
+pub fn ed25519_pub_key(seed: &[u8; 32]) -> Pos {
+    ED25519.mul(&ed25519_expand(seed).0, &ED25519.b)
+}
+
+/// SHA-512 of the concatenated parts as a little endian integer mod L.
+fn ed25519_hash(parts: &[&[u8]]) -> BigInt {
+    let mut h = Sha512::new();
+    for part in parts {
+        Digest::update(&mut h, part);
+    }
+    BigInt::from_bytes_le(Sign::Plus, &h.finalize()).mod_floor(&ED25519.l)
+}
+
+/// RFC 8032 Ed25519 signature: R || S.
+pub fn ed25519_sign(seed: &[u8; 32], m: &[u8]) -> [u8; 64] {
+    let (a, prefix) = ed25519_expand(seed);
+    let pk = ED25519.encode_point(&ED25519.mul(&a, &ED25519.b));
+    let r = ed25519_hash(&[&prefix, m]);
+    let big_r = ED25519.encode_point(&ED25519.mul(&r, &ED25519.b));
+    let k = ed25519_hash(&[&big_r, &pk, m]);
+    let s = (r + k * a).mod_floor(&ED25519.l);
+
+    let mut sig = [0; 64];
+    sig[..32].copy_from_slice(&big_r);
+    let s = s.to_bytes_le().1;
+    sig[32..32 + s.len()].copy_from_slice(&s);
+    sig
+}
+
+/// RFC 8032 Ed25519 signature verification with the cofactored equation
+/// [8][S]B = [8]R + [8][k]A, which accepts the same signatures as batch verification.
+/// Public keys of small order are rejected, the equation holds for them with R = [S]B and any
+/// message.
+pub fn ed25519_verify(pk: &Pos, m: &[u8], sig: &[u8]) -> bool {
+    let eight = BigInt::from(8u8);
+    if sig.len() != 64 || !ED25519.validate(pk) || ED25519.mul(&eight, pk) == ED25519.identity() {
+        return false;
+    }
+    let Some(r) = ED25519.decode_point(&sig[..32]) else {
+        return false;
+    };
+    let s = BigInt::from_bytes_le(Sign::Plus, &sig[32..]);
+    if ED25519.l <= s {
+        return false;
+    }
+    let k = ed25519_hash(&[&sig[..32], &ED25519.encode_point(pk), m]);
+    let diff = ED25519.sub(
+        &ED25519.mul(&s, &ED25519.b),
+        &ED25519.add(&r, &ED25519.mul(&k, pk)),
+    );
+    ED25519.mul(&eight, &diff) == ED25519.identity()
+}
+
+/// RFC 8032 Ed25519 signature verification with an encoded public key.
+pub fn ed25519_verify_bytes(pk: &[u8; 32], m: &[u8], sig: &[u8]) -> bool {
+    ED25519
+        .decode_point(pk)
+        .is_some_and(|pk| ed25519_verify(&pk, m, sig))
+}
+
+/// A point (X / Z², Y / Z³) in Jacobian coordinates, Z = 0 is the point at infinity.
+#[derive(Clone, Debug)]
+struct Jacobian {
+    x: BigInt,
+    y: BigInt,
+    z: BigInt,
+}
+
+impl EllipticCurve {
+    fn to_jacobian(&self, p: &Option<Pos>) -> Jacobian {
+        match p {
+            Some(Pos { x, y }) => Jacobian {
+                x: x.mod_floor(&self.p),
+                y: y.mod_floor(&self.p),
+                z: one(),
+            },
+            None => Jacobian {
+                x: one(),
+                y: one(),
+                z: zero(),
+            },
+        }
+    }
+
+    /// Converts back to affine coordinates with a single inversion.
+    fn to_affine(&self, p: &Jacobian) -> Option<Pos> {
+        if p.z.is_zero() {
+            return None;
+        }
+        let z_inv = mod_inv(&p.z, &self.p);
+        let z_inv2 = (&z_inv * &z_inv) % &self.p;
+        Some(Pos {
+            x: (&p.x * &z_inv2) % &self.p,
+            y: (&p.y * z_inv2 * z_inv) % &self.p,
+        })
+    }
+
+    /// dbl-2007-bl, or dbl-2001-b with M = 3(X - Z²)(X + Z²) when a = -3.
+    fn jacobian_double(&self, p: &Jacobian, a_is_minus_3: bool) -> Jacobian {
+        let m = |x: BigInt| x.mod_floor(&self.p);
+        if p.z.is_zero() || p.y.is_zero() {
+            return self.to_jacobian(&None);
+        }
+        let xx = m(&p.x * &p.x);
+        let yy = m(&p.y * &p.y);
+        let yyyy = m(&yy * &yy);
+        let zz = m(&p.z * &p.z);
+        let s = m(((&p.x + &yy).pow(2) - &xx - &yyyy) * 2u8);
+        let mm = if a_is_minus_3 {
+            m((&p.x - &zz) * (&p.x + &zz) * 3u8)
+        } else {
+            m(&xx * 3u8 + &self.a * &zz * &zz)
+        };
+        let x = m(&mm * &mm - &s * 2u8);
+        let y = m(&mm * (s - &x) - yyyy * 8u8);
+        let z = m((&p.y + &p.z).pow(2) - yy - zz);
+        Jacobian { x, y, z }
+    }
+
+    /// madd-2007-bl, adds the affine point q.
+    fn jacobian_add_affine(&self, p: &Jacobian, q: &Pos, a_is_minus_3: bool) -> Jacobian {
+        let m = |x: BigInt| x.mod_floor(&self.p);
+        if p.z.is_zero() {
+            return self.to_jacobian(&Some(q.clone()));
+        }
+        let z1z1 = m(&p.z * &p.z);
+        let u2 = m(&q.x * &z1z1);
+        let s2 = m(&q.y * &p.z * &z1z1);
+        let h = m(u2 - &p.x);
+        let r = m((s2 - &p.y) * 2u8);
+        if h.is_zero() {
+            return if r.is_zero() {
+                self.jacobian_double(p, a_is_minus_3)
+            } else {
+                self.to_jacobian(&None)
+            };
+        }
+        let hh = m(&h * &h);
+        let i = m(&hh * 4u8);
+        let j = m(&h * &i);
+        let v = m(&p.x * i);
+        let x = m(&r * &r - &j - &v * 2u8);
+        let y = m(r * (v - &x) - &p.y * j * 2u8);
+        let z = m((&p.z + h).pow(2) - z1z1 - hh);
+        Jacobian { x, y, z }
+    }
+}
+
 impl AddGroup for EllipticCurve {
     type Point = Option<Pos>;
     fn identity(&self) -> Self::Point {
@@ -299,9 +718,12 @@ impl AddGroup for EllipticCurve {
     fn add(&self, p: &Self::Point, q: &Self::Point) -> Self::Point {
         if let (Some(Pos { x: x1, y: y1 }), Some(Pos { x: x2, y: y2 })) = (p, q) {
             let s;
-            if *x1 == *x2 && *y1 == -y2 {
+            if *x1 == *x2 && (y1 + y2).mod_floor(&self.p) == zero() {
                 return None;
-            } else if *x1 == *x2 && *y1 == *y2 {
+            } else if *x1 == *x2 && (y1 - y2).mod_floor(&self.p) == zero() {
+                // The tangent slope (3x² + 2ax + 1) / 2by from differentiating by² = x³ + ax² + x.
+                // ecc.ss puts b in the numerator instead of the denominator and leaves it out of
+                // x_new, which only agrees since b = 1 there.
                 s = mod_div(&(3 * x1 * x1 + &self.a), &(2 * y1), &self.p);
             } else {
                 s = mod_div(&(y1 - y2), &(x1 - x2), &self.p);
@@ -316,13 +738,32 @@ impl AddGroup for EllipticCurve {
         }
     }
     fn neg(&self, p: &Self::Point) -> Self::Point {
-        match p {
-            Some(Pos { x, y }) => Some(Pos {
-                x: x.clone(),
-                y: -y,
-            }),
-            None => None,
+        p.as_ref().map(|Pos { x, y }| Pos {
+            x: x.clone(),
+            y: (-y).mod_floor(&self.p),
+        })
+    }
+    /// Double-and-add in Jacobian coordinates, with a single inversion at the end.
+    fn mul(&self, k: &BigInt, p: &Self::Point) -> Self::Point {
+        let (k, p) = if k.is_negative() {
+            (-k, self.neg(p))
+        } else {
+            (k.clone(), p.clone())
+        };
+        let q = p?;
+        let q = Pos {
+            x: q.x.mod_floor(&self.p),
+            y: q.y.mod_floor(&self.p),
+        };
+        let a_is_minus_3 = (&self.a + 3u8).mod_floor(&self.p).is_zero();
+        let mut out = self.to_jacobian(&None);
+        for i in (0..k.bits()).rev() {
+            out = self.jacobian_double(&out, a_is_minus_3);
+            if k.bit(i) {
+                out = self.jacobian_add_affine(&out, &q, a_is_minus_3);
+            }
         }
+        self.to_affine(&out)
     }
     fn validate(&self, p: &Self::Point) -> bool {
         match p {
@@ -339,6 +780,53 @@ impl AddGroup for EllipticCurve {
     }
 }
 
+impl MontgomeryCurve {
+    /// The x-only Montgomery ladder of RFC 7748: the x-coordinate of k times the point with the
+    /// x-coordinate u. It always runs over as many bits as p has and swaps with arithmetic
+    /// instead of branches, so k must be below 2^bits(p). The point at infinity maps to 0.
+    ///
+    /// Only the control flow is independent of the scalar bits. It isn't constant time: the
+    /// `BigInt` arithmetic and `mod_floor` take time depending on the sizes of the values. Use
+    /// `x25519` for secret scalars on Curve25519.
+    pub fn ladder(&self, k: &BigInt, u: &BigInt) -> BigInt {
+        let p = &self.p;
+        let m = |x: BigInt| x.mod_floor(p);
+        let a24 = mod_div(&(&self.a - 2), &4.into(), p);
+        let x1 = u.mod_floor(p);
+        let (mut x2, mut z2): (BigInt, BigInt) = (one(), zero());
+        let (mut x3, mut z3) = (x1.clone(), one());
+        let cswap = |swap: u8, a: &mut BigInt, b: &mut BigInt| {
+            let d = (&*a - &*b) * swap;
+            *a -= &d;
+            *b += d;
+        };
+        let mut swap = 0;
+        for t in (0..p.bits()).rev() {
+            let k_t = k.bit(t) as u8;
+            swap ^= k_t;
+            cswap(swap, &mut x2, &mut x3);
+            cswap(swap, &mut z2, &mut z3);
+            swap = k_t;
+            let a = m(&x2 + &z2);
+            let aa = m(&a * &a);
+            let b = m(&x2 - &z2);
+            let bb = m(&b * &b);
+            let e = m(&aa - &bb);
+            let c = m(&x3 + &z3);
+            let d = m(&x3 - &z3);
+            let da = m(d * a);
+            let cb = m(c * b);
+            x3 = m((&da + &cb).pow(2));
+            z3 = m(&x1 * (da - cb).pow(2));
+            z2 = m(&e * (&aa + &a24 * &e));
+            x2 = m(aa * bb);
+        }
+        cswap(swap, &mut x2, &mut x3);
+        cswap(swap, &mut z2, &mut z3);
+        m(x2 * z2.modpow(&(p - 2), p))
+    }
+}
+
 impl AddGroup for MontgomeryCurve {
     type Point = Option<Pos>;
     fn identity(&self) -> Self::Point {
@@ -353,9 +841,12 @@ impl AddGroup for MontgomeryCurve {
     fn add(&self, p: &Self::Point, q: &Self::Point) -> Self::Point {
         if let (Some(Pos { x: x1, y: y1 }), Some(Pos { x: x2, y: y2 })) = (p, q) {
             let s;
-            if *x1 == *x2 && *y1 == -y2 {
+            if *x1 == *x2 && (y1 + y2).mod_floor(&self.p) == zero() {
                 return None;
-            } else if *x1 == *x2 && *y1 == *y2 {
+            } else if *x1 == *x2 && (y1 - y2).mod_floor(&self.p) == zero() {
+                // The tangent slope (3x² + 2ax + 1) / 2by from differentiating by² = x³ + ax² + x.
+                // ecc.ss puts b in the numerator instead of the denominator and leaves it out of
+                // x_new, which only agrees since b = 1 there.
                 s = mod_div(
                     &(3 * x1 * x1 + 2 * &self.a * x1 + 1),
                     &(2 * &self.b * y1),
@@ -374,13 +865,10 @@ impl AddGroup for MontgomeryCurve {
         }
     }
     fn neg(&self, p: &Self::Point) -> Self::Point {
-        match p {
-            Some(Pos { x, y }) => Some(Pos {
-                x: x.clone(),
-                y: -y,
-            }),
-            None => None,
-        }
+        p.as_ref().map(|Pos { x, y }| Pos {
+            x: x.clone(),
+            y: (-y).mod_floor(&self.p),
+        })
     }
     fn validate(&self, p: &Self::Point) -> bool {
         match p {
@@ -397,6 +885,149 @@ impl AddGroup for MontgomeryCurve {
     }
 }
 
+impl TwistedEdwardsCurve {
+    /// The length of an encoded point in bytes.
+    pub fn encoded_len(&self) -> usize {
+        (self.p.bits() as usize + 1).div_ceil(8)
+    }
+
+    /// RFC 8032 point encoding: y in little endian with the sign (lowest bit) of x in the most
+    /// significant bit.
+    pub fn encode_point(&self, p: &Pos) -> Vec<u8> {
+        let len = self.encoded_len();
+        let mut out = p.y.mod_floor(&self.p).to_bytes_le().1;
+        out.resize(len, 0);
+        if p.x.mod_floor(&self.p).is_odd() {
+            out[len - 1] |= 0x80;
+        }
+        out
+    }
+
+    /// RFC 8032 point decoding, returns `None` if the bytes don't encode a point on the curve.
+    pub fn decode_point(&self, bytes: &[u8]) -> Option<Pos> {
+        let len = self.encoded_len();
+        if bytes.len() != len {
+            return None;
+        }
+        let mut bytes = bytes.to_vec();
+        let x_odd = bytes[len - 1] & 0x80 != 0;
+        bytes[len - 1] &= 0x7f;
+        let y = BigInt::from_bytes_le(Sign::Plus, &bytes);
+        if self.p <= y {
+            return None;
+        }
+        // ax² + y² = 1 + dx²y² => x² = (1 - y²) / (a - dy²)
+        let y2 = &y * &y;
+        let x2 = mod_div(&(1 - &y2), &(&self.a - &self.d * &y2), &self.p);
+        let mut x = mod_sqrt(&x2, &self.p)?;
+        if x == zero() && x_odd {
+            return None;
+        }
+        if x.is_odd() != x_odd {
+            x = &self.p - x;
+        }
+        Some(Pos { x, y })
+    }
+}
+
+/// A point (X / Z, Y / Z) in extended twisted Edwards coordinates, with T = XY / Z.
+#[derive(Clone, Debug)]
+struct Extended {
+    x: BigInt,
+    y: BigInt,
+    z: BigInt,
+    t: BigInt,
+}
+
+/// A point prepared for additions: (Y + X, Y - X, 2Z, 2dT) when a = -1, otherwise (X, Y, Z, dT).
+#[derive(Clone, Debug)]
+struct Cached(BigInt, BigInt, BigInt, BigInt);
+
+impl TwistedEdwardsCurve {
+    fn a_is_minus_1(&self) -> bool {
+        (&self.a + 1u8).mod_floor(&self.p).is_zero()
+    }
+
+    fn to_extended(&self, p: &Pos) -> Extended {
+        let x = p.x.mod_floor(&self.p);
+        let y = p.y.mod_floor(&self.p);
+        Extended {
+            t: (&x * &y) % &self.p,
+            x,
+            y,
+            z: one(),
+        }
+    }
+
+    /// Converts back to affine coordinates with a single inversion.
+    fn to_affine(&self, p: &Extended) -> Pos {
+        let z_inv = mod_inv(&p.z, &self.p);
+        Pos {
+            x: (&p.x * &z_inv) % &self.p,
+            y: (&p.y * z_inv) % &self.p,
+        }
+    }
+
+    fn to_cached(&self, p: &Extended) -> Cached {
+        let m = |x: BigInt| x.mod_floor(&self.p);
+        if self.a_is_minus_1() {
+            Cached(
+                m(&p.y + &p.x),
+                m(&p.y - &p.x),
+                m(&p.z * 2u8),
+                m(&self.d * &p.t * 2u8),
+            )
+        } else {
+            Cached(p.x.clone(), p.y.clone(), p.z.clone(), m(&self.d * &p.t))
+        }
+    }
+
+    /// The unified addition of Hisil, Wong, Carter and Dawson: add-2008-hwcd-3 when a = -1,
+    /// add-2008-hwcd otherwise.
+    fn extended_add(&self, p: &Extended, q: &Cached) -> Extended {
+        let m = |x: BigInt| x.mod_floor(&self.p);
+        let (e, f, g, h) = if self.a_is_minus_1() {
+            let a = m((&p.y - &p.x) * &q.1);
+            let b = m((&p.y + &p.x) * &q.0);
+            let c = m(&p.t * &q.3);
+            let d = m(&p.z * &q.2);
+            (m(&b - &a), m(&d - &c), m(d + c), m(b + a))
+        } else {
+            let a = m(&p.x * &q.0);
+            let b = m(&p.y * &q.1);
+            let c = m(&p.t * &q.3);
+            let d = m(&p.z * &q.2);
+            let e = m((&p.x + &p.y) * (&q.0 + &q.1) - &a - &b);
+            (e, m(&d - &c), m(d + c), m(b - &self.a * a))
+        };
+        Extended {
+            x: m(&e * &f),
+            y: m(&g * &h),
+            z: m(f * g),
+            t: m(e * h),
+        }
+    }
+
+    /// dbl-2008-hwcd
+    fn extended_double(&self, p: &Extended) -> Extended {
+        let m = |x: BigInt| x.mod_floor(&self.p);
+        let a = m(&p.x * &p.x);
+        let b = m(&p.y * &p.y);
+        let c = m(&p.z * &p.z * 2u8);
+        let d = m(&self.a * a.clone());
+        let e = m((&p.x + &p.y).pow(2) - &a - &b);
+        let g = m(&d + &b);
+        let f = m(&g - c);
+        let h = m(d - b);
+        Extended {
+            x: m(&e * &f),
+            y: m(&g * &h),
+            z: m(f * g),
+            t: m(e * h),
+        }
+    }
+}
+
 impl AddGroup for TwistedEdwardsCurve {
     type Point = Pos;
     fn identity(&self) -> Self::Point {
@@ -412,43 +1043,42 @@ impl AddGroup for TwistedEdwardsCurve {
         &self.l
     }
     fn double(&self, p: &Self::Point) -> Self::Point {
-        let Pos { x, y } = p;
-        Pos {
-            x: mod_div(&(2 * x * y), &(&self.a * x * x + y * y), &self.p),
-            y: mod_div(
-                &(y * y - &self.a * x * x),
-                &(2 - &self.a * x * x - y * y),
-                &self.p,
-            ),
-        }
+        self.to_affine(&self.extended_double(&self.to_extended(p)))
     }
     fn add(&self, p: &Self::Point, q: &Self::Point) -> Self::Point {
-        if *p == *q {
-            self.double(p)
-        } else {
-            let f = |n| BigInt::mod_floor(&n, &self.p);
-            let Pos { x: x1, y: y1 } = p;
-            let Pos { x: x2, y: y2 } = q;
-            Pos {
-                x: mod_div(
-                    &(x1 * y2 + x2 * y1),
-                    &(1 + f(&self.d * x1 * x2) * y1 * y2),
-                    &self.p,
-                ),
-                y: mod_div(
-                    &(y1 * y2 - &self.a * x1 * x2),
-                    &(1 - f(&self.d * x1 * x2) * y1 * y2),
-                    &self.p,
-                ),
-            }
-        }
+        let q = self.to_cached(&self.to_extended(q));
+        self.to_affine(&self.extended_add(&self.to_extended(p), &q))
     }
     fn neg(&self, p: &Self::Point) -> Self::Point {
         Pos {
-            x: -&p.x,
+            x: (-&p.x).mod_floor(&self.p),
             y: p.y.clone(),
         }
     }
+    /// Fixed window scalar multiplication in extended coordinates, with the multiples 0 to 15
+    /// of the point cached, and a single inversion at the end.
+    fn mul(&self, k: &BigInt, p: &Self::Point) -> Self::Point {
+        let (k, p) = if k.is_negative() {
+            (-k, self.neg(p))
+        } else {
+            (k.clone(), p.clone())
+        };
+        let p = self.to_extended(&p);
+        let mut table = vec![self.to_extended(&self.identity()), p.clone()];
+        for _ in 2..16 {
+            table.push(self.extended_add(&table[table.len() - 1], &self.to_cached(&p)));
+        }
+        let table = table.iter().map(|q| self.to_cached(q)).collect::<Vec<_>>();
+        let mut out = self.to_extended(&self.identity());
+        for i in (0..k.bits().div_ceil(4)).rev() {
+            for _ in 0..4 {
+                out = self.extended_double(&out);
+            }
+            let digit = (0..4).fold(0, |d, j| d | (k.bit(4 * i + j) as usize) << j);
+            out = self.extended_add(&out, &table[digit]);
+        }
+        self.to_affine(&out)
+    }
     fn validate(&self, p: &Self::Point) -> bool {
         let Pos { x, y } = p;
         (&self.a * x * x + y * y).mod_floor(&self.p)
@@ -468,7 +1098,7 @@ impl<C: AddGroup> Point<C> {
     }
 }
 
-impl<'a, 'b, C: AddGroup> Add<&'b Point<C>> for &'a Point<C> {
+impl<'b, C: AddGroup> Add<&'b Point<C>> for &Point<C> {
     type Output = Point<C>;
     fn add(self, other: &'b Point<C>) -> Point<C> {
         Point::<C> {
@@ -477,13 +1107,13 @@ impl<'a, 'b, C: AddGroup> Add<&'b Point<C>> for &'a Point<C> {
         }
     }
 }
-impl<'a, C: AddGroup> Add<&'a Point<C>> for Point<C> {
+impl<C: AddGroup> Add<&Point<C>> for Point<C> {
     type Output = Point<C>;
     fn add(self, other: &Point<C>) -> Point<C> {
         &self + other
     }
 }
-impl<'a, C: AddGroup> Add<Point<C>> for &'a Point<C> {
+impl<C: AddGroup> Add<Point<C>> for &Point<C> {
     type Output = Point<C>;
     fn add(self, other: Point<C>) -> Point<C> {
         self + &other
@@ -496,7 +1126,7 @@ impl<C: AddGroup> Add<Point<C>> for Point<C> {
     }
 }
 
-impl<'a, 'b, C: AddGroup> Mul<&'b BigInt> for &'a Point<C> {
+impl<C: AddGroup> Mul<&BigInt> for &Point<C> {
     type Output = Point<C>;
     fn mul(self, n: &BigInt) -> Point<C> {
         Point::<C> {
@@ -505,13 +1135,13 @@ impl<'a, 'b, C: AddGroup> Mul<&'b BigInt> for &'a Point<C> {
         }
     }
 }
-impl<'a, C: AddGroup> Mul<&'a BigInt> for Point<C> {
+impl<C: AddGroup> Mul<&BigInt> for Point<C> {
     type Output = Point<C>;
     fn mul(self, n: &BigInt) -> Point<C> {
         &self * n
     }
 }
-impl<'a, C: AddGroup> Mul<BigInt> for &'a Point<C> {
+impl<C: AddGroup> Mul<BigInt> for &Point<C> {
     type Output = Point<C>;
     fn mul(self, n: BigInt) -> Point<C> {
         self * &n
@@ -523,19 +1153,19 @@ impl<C: AddGroup> Mul<BigInt> for Point<C> {
         &self * &n
     }
 }
-impl<'a, 'b, C: AddGroup> Mul<&'b Point<C>> for &'a BigInt {
+impl<C: AddGroup> Mul<&Point<C>> for &BigInt {
     type Output = Point<C>;
     fn mul(self, p: &Point<C>) -> Point<C> {
         p * self
     }
 }
-impl<'a, C: AddGroup> Mul<&'a Point<C>> for BigInt {
+impl<C: AddGroup> Mul<&Point<C>> for BigInt {
     type Output = Point<C>;
     fn mul(self, p: &Point<C>) -> Point<C> {
         p * &self
     }
 }
-impl<'a, C: AddGroup> Mul<Point<C>> for &'a BigInt {
+impl<C: AddGroup> Mul<Point<C>> for &BigInt {
     type Output = Point<C>;
     fn mul(self, p: Point<C>) -> Point<C> {
         &p * self
@@ -548,7 +1178,7 @@ impl<C: AddGroup> Mul<Point<C>> for BigInt {
     }
 }
 
-impl<'a, C: AddGroup> Neg for &'a Point<C> {
+impl<C: AddGroup> Neg for &Point<C> {
     type Output = Point<C>;
     fn neg(self) -> Point<C> {
         Point::<C> {
@@ -564,7 +1194,7 @@ impl<C: AddGroup> Neg for Point<C> {
     }
 }
 
-impl<'a, 'b, C: AddGroup> Sub<&'b Point<C>> for &'a Point<C> {
+impl<C: AddGroup> Sub<&Point<C>> for &Point<C> {
     type Output = Point<C>;
     fn sub(self, other: &Point<C>) -> Point<C> {
         Point::<C> {
@@ -573,13 +1203,13 @@ impl<'a, 'b, C: AddGroup> Sub<&'b Point<C>> for &'a Point<C> {
         }
     }
 }
-impl<'a, C: AddGroup> Sub<&'a Point<C>> for Point<C> {
+impl<C: AddGroup> Sub<&Point<C>> for Point<C> {
     type Output = Point<C>;
     fn sub(self, other: &Point<C>) -> Point<C> {
         &self - other
     }
 }
-impl<'a, C: AddGroup> Sub<Point<C>> for &'a Point<C> {
+impl<C: AddGroup> Sub<Point<C>> for &Point<C> {
     type Output = Point<C>;
     fn sub(self, other: Point<C>) -> Point<C> {
         self - &other
@@ -598,3 +1228,341 @@ impl<C: AddGroup> Sub<Point<C>> for Point<C> {
 //     }
 // }
 // impl<C: AddGroup> Eq for Point<C> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::hex;
+
+    fn bytes32(s: &str) -> [u8; 32] {
+        hex(s).try_into().unwrap()
+    }
+
+    /// RFC 7748 5.2: k and u are set to the base point, then (k, u) = (X25519(k, u), k).
+    fn x25519_iterated(iterations: u32) -> [u8; 32] {
+        let (mut k, mut u) = (X25519_BASE_POINT, X25519_BASE_POINT);
+        for _ in 0..iterations {
+            (k, u) = (x25519(k, u), k);
+        }
+        k
+    }
+
+    #[test]
+    fn x25519_vectors() {
+        for (k, u, out) in [
+            (
+                "a546e36bf0527c9d3b16154b82465edd62144c0ac1fc5a18506a2244ba449ac4",
+                "e6db6867583030db3594c1a424b15f7c726624ec26b3353b10a903a6d0ab1c4c",
+                "c3da55379de9c6908e94ea4df28d084f32eccf03491c71f754b4075577a28552",
+            ),
+            (
+                "4b66e9d4d1b4673c5ad22691957d6af5c11b6421e0ea01d42ca4169e7918ba0d",
+                "e5210f12786811d3f4b7959d0538ae2c31dbe7106fc03c3efc4cd549c715a493",
+                "95cbde9476e8907d7aade45cb4b873f88b595a68799fa152e6f8f7647aac7957",
+            ),
+        ] {
+            assert_eq!(x25519(bytes32(k), bytes32(u)), bytes32(out));
+        }
+    }
+
+    #[test]
+    fn x25519_iterated_1() {
+        assert_eq!(
+            x25519_iterated(1),
+            bytes32("422c8e7a6227d7bca1350b3e2bb7279f7897b87bb6854b783c60e80311ae3079")
+        );
+    }
+
+    #[test]
+    fn x25519_iterated_1000() {
+        assert_eq!(
+            x25519_iterated(1000),
+            bytes32("684cf59ba83309552800ef566f2f4d3c1c3887c49360e3875f2eb94d99532c51")
+        );
+    }
+
+    #[test]
+    #[cfg_attr(
+        debug_assertions,
+        ignore = "a million ladders, about two minutes with --release"
+    )]
+    fn x25519_iterated_1000000() {
+        assert_eq!(
+            x25519_iterated(1000000),
+            bytes32("7c3911e0ab2586fd864497297e575e6f3bc601c0883c30df5f4dd2d24f665424")
+        );
+    }
+
+    #[test]
+    fn x25519_matches_bigint_ladder() {
+        let mut rng = StdRng::seed_from_u64(3);
+        let p = &CURVE25519.p;
+        // p itself, the largest non-canonical value and values with the high bit set
+        let mut us = vec![p.clone(), (one::<BigInt>() << 255) - 1u8, p + 7u8];
+        us.extend((0..8).map(|_| rng.gen_biguint(256).into()));
+        for u in us {
+            let k: [u8; 32] = rng.gen();
+            let mut u_bytes = u.to_bytes_le().1;
+            u_bytes.resize(32, 0);
+            let mut clamped = k;
+            clamped[0] &= 0xf8;
+            clamped[31] = clamped[31] & 0x7f | 0x40;
+            let expected = CURVE25519.ladder(
+                &BigInt::from_bytes_le(Sign::Plus, &clamped),
+                &(u % (one::<BigInt>() << 255)),
+            );
+            let out = x25519(k, u_bytes.try_into().unwrap());
+            assert_eq!(BigInt::from_bytes_le(Sign::Plus, &out), expected);
+        }
+    }
+
+    #[test]
+    fn fe_cswap() {
+        let (a, b) = (Fe([1, 2, 3, 4, 5]), Fe([MASK51, 0, MASK51, 7, 0]));
+        let (mut x, mut y) = (a, b);
+        Fe::cswap(0, &mut x, &mut y);
+        assert_eq!((x.0, y.0), (a.0, b.0));
+        Fe::cswap(1, &mut x, &mut y);
+        assert_eq!((x.0, y.0), (b.0, a.0));
+    }
+
+    #[test]
+    fn montgomery_doubling() {
+        // The slope depends on b, which is 1 for Curve25519. b = 2 gives its quadratic twist,
+        // and the ladder doesn't use b at all.
+        let twist = MontgomeryCurve {
+            b: 2.into(),
+            ..CURVE25519.clone()
+        };
+        for curve in [&*CURVE25519, &twist] {
+            let p = &curve.p;
+            let point = (10u8..)
+                .find_map(|x| {
+                    let x = BigInt::from(x);
+                    let rhs = (&x * &x * &x + &curve.a * &x * &x + &x).mod_floor(p);
+                    let y = mod_sqrt(&mod_div(&rhs, &curve.b, p), p)?;
+                    Some(Some(Pos { x, y }))
+                })
+                .unwrap();
+            assert!(curve.validate(&point));
+            let doubled = curve.add(&point, &point);
+            assert!(curve.validate(&doubled));
+            let x = &point.as_ref().unwrap().x;
+            assert_eq!(doubled.as_ref().unwrap().x, curve.ladder(&2.into(), x));
+            let tripled = curve.add(&doubled, &point);
+            assert_eq!(tripled.as_ref().unwrap().x, curve.ladder(&3.into(), x));
+        }
+    }
+
+    #[test]
+    fn x25519_diffie_hellman() {
+        // RFC 7748 6.1
+        let a = bytes32("77076d0a7318a57d3c16c17251b26645df4c2f87ebc0992ab177fba51db92c2a");
+        let b = bytes32("5dab087e624a8a4b79e17f8b83800ee66f3bb1292618b6fd1c2f8b27ff88e0eb");
+        let a_pub = x25519_pub_key(a);
+        let b_pub = x25519_pub_key(b);
+        assert_eq!(
+            a_pub,
+            bytes32("8520f0098930a754748b7ddcb43ef75a0dbf3a0d26381af4eba4a98eaa9b4e6a")
+        );
+        assert_eq!(
+            b_pub,
+            bytes32("de9edb7d7b7dc1b4d35b61c2ece435373f8343c85b78674dadfc7e146f882b4f")
+        );
+        let shared = bytes32("4a5d9d5ba4ce2de1728e3bf480350f25e07e21c947d19e3376f09b3c1e161742");
+        assert_eq!(x25519_agree(a, b_pub), Some(shared));
+        assert_eq!(x25519_agree(b, a_pub), Some(shared));
+    }
+
+    #[test]
+    fn x25519_agree_rejects_low_order_points() {
+        let k = bytes32("77076d0a7318a57d3c16c17251b26645df4c2f87ebc0992ab177fba51db92c2a");
+        for u in [
+            "0000000000000000000000000000000000000000000000000000000000000000",
+            "0100000000000000000000000000000000000000000000000000000000000000",
+            "e0eb7a7c3b41b8ae1656e3faf19fc46ada098deb9c32b1fd866205165f49b800",
+            "5f9c95bca3508c24b1d0b1559c83ef5b04445cc4581c8e86d8224eddd09f1157",
+            "ecffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff7f",
+        ] {
+            assert_eq!(x25519_agree(k, bytes32(u)), None, "{u}");
+        }
+    }
+
+    /// RFC 8032 7.1: (secret key, public key, message, signature)
+    const ED25519_VECTORS: [(&str, &str, &str, &str); 4] = [
+        // TEST 1
+        (
+            "9d61b19deffd5a60ba844af492ec2cc44449c5697b326919703bac031cae7f60",
+            "d75a980182b10ab7d54bfed3c964073a0ee172f3daa62325af021a68f707511a",
+            "",
+            "e5564300c360ac729086e2cc806e828a84877f1eb8e5d974d873e06522490155\
+                5fb8821590a33bacc61e39701cf9b46bd25bf5f0595bbe24655141438e7a100b",
+        ),
+        // TEST 2
+        (
+            "4ccd089b28ff96da9db6c346ec114e0f5b8a319f35aba624da8cf6ed4fb8a6fb",
+            "3d4017c3e843895a92b70aa74d1b7ebc9c982ccf2ec4968cc0cd55f12af4660c",
+            "72",
+            "92a009a9f0d4cab8720e820b5f642540a2b27b5416503f8fb3762223ebdb69da\
+                085ac1e43e15996e458f3613d0f11d8c387b2eaeb4302aeeb00d291612bb0c00",
+        ),
+        // TEST 3
+        (
+            "c5aa8df43f9f837bedb7442f31dcb7b166d38535076f094b85ce3a2e0b4458f7",
+            "fc51cd8e6218a1a38da47ed00230f0580816ed13ba3303ac5deb911548908025",
+            "af82",
+            "6291d657deec24024827e69c3abe01a30ce548a284743a445e3680d7db5ac3ac\
+                18ff9b538d16f290ae67f760984dc6594a7c15e9716ed28dc027beceea1ec40a",
+        ),
+        // TEST SHA(abc)
+        (
+            "833fe62409237b9d62ec77587520911e9a759cec1d19755b7da901b96dca3d42",
+            "ec172b93ad5e563bf4932c70e1245034c35467ef2efd4d64ebf819683467e2bf",
+            "ddaf35a193617abacc417349ae20413112e6fa4e89a97ea20a9eeee64b55d39a\
+                2192992a274fc1a836ba3c23a3feebbd454d4423643ce80e2a9ac94fa54ca49f",
+            "dc2a4459e7369633a52b1bf277839a00201009a3efbf3ecb69bea2186c26b589\
+                09351fc9ac90b3ecfdfbc7c66431e0303dca179c138ac17ad9bef1177331a704",
+        ),
+    ];
+
+    fn ed25519_sig(r: &Pos, s: &BigInt) -> [u8; 64] {
+        let mut sig = [0; 64];
+        sig[..32].copy_from_slice(&ED25519.encode_point(r));
+        let s = s.to_bytes_le().1;
+        sig[32..32 + s.len()].copy_from_slice(&s);
+        sig
+    }
+
+    /// Compares `mul` with `double_and_add` for the points on random and edge case scalars,
+    /// negative ones included.
+    fn check_mul<C: AddGroup>(curve: &C, points: &[C::Point], seed: u64)
+    where
+        C::Point: std::fmt::Debug,
+    {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let n = curve.order();
+        let mut scalars: Vec<BigInt> = [0, 1, 2, 3, 15, 16, 17, -1, -2, -16]
+            .into_iter()
+            .map(BigInt::from)
+            .chain([n - 1u8, n.clone(), n + 1u8, n * 2u8 + 5u8, -n, 1 - n])
+            .collect();
+        for _ in 0..6 {
+            let k = rng.gen_bigint_range(&zero(), n);
+            scalars.push(-&k);
+            scalars.push(k);
+        }
+        scalars.push(rng.gen_biguint(n.bits() + 40).into());
+        for p in points {
+            for k in &scalars {
+                let expected = if k.is_negative() {
+                    double_and_add(curve, &-k, &curve.neg(p))
+                } else {
+                    double_and_add(curve, k, p)
+                };
+                assert_eq!(curve.mul(k, p), expected, "{} {:?}", k, p);
+            }
+        }
+    }
+
+    #[test]
+    fn jacobian_mul() {
+        for (seed, curve) in [(0, &*P256), (1, &*SECP256K1)] {
+            let g = curve.generator();
+            let p = curve.mul(&12345.into(), g);
+            check_mul(curve, &[g.clone(), p, curve.identity()], seed);
+        }
+    }
+
+    /// A point of order 8: [L]P for a point P whose torsion component has order 8.
+    fn ed25519_order_8_point() -> Pos {
+        (2u8..)
+            .filter_map(|y| ED25519.decode_point(&[&[y], &[0; 31][..]].concat()))
+            .map(|p| ED25519.mul(&ED25519.l, &p))
+            .find(|t| ED25519.mul(&4.into(), t) != ED25519.identity())
+            .unwrap()
+    }
+
+    #[test]
+    fn extended_mul() {
+        let curve = &*ED25519;
+        let b = curve.generator();
+        let p = curve.mul(&12345.into(), b);
+        let t = ed25519_order_8_point();
+        let mixed = curve.add(&p, &t);
+        check_mul(curve, &[b.clone(), p, t, mixed, curve.identity()], 2);
+    }
+
+    #[test]
+    fn ed25519_vectors() {
+        for (seed, pk, m, sig) in ED25519_VECTORS {
+            let seed = bytes32(seed);
+            let pk = bytes32(pk);
+            let (m, sig) = (hex(m), hex(sig));
+            assert_eq!(ED25519.encode_point(&ed25519_pub_key(&seed)), pk);
+            assert_eq!(ed25519_sign(&seed, &m).to_vec(), sig);
+            assert!(ed25519_verify_bytes(&pk, &m, &sig));
+            assert!(!ed25519_verify_bytes(&pk, b"other message", &sig));
+        }
+    }
+
+    #[test]
+    fn ed25519_rejects_modified_signatures() {
+        let (_, pk, m, sig) = ED25519_VECTORS[2];
+        let (pk, m, sig) = (bytes32(pk), hex(m), hex(sig));
+        let mut flipped_r = sig.clone();
+        flipped_r[3] ^= 0x10;
+        assert!(!ed25519_verify_bytes(&pk, &m, &flipped_r));
+        let mut flipped_s = sig.clone();
+        flipped_s[40] ^= 1;
+        assert!(!ed25519_verify_bytes(&pk, &m, &flipped_s));
+        assert!(!ed25519_verify_bytes(&pk, &m, &sig[..63]));
+
+        // S + L satisfies the equation as well, but S must be below L.
+        let s = BigInt::from_bytes_le(Sign::Plus, &sig[32..]);
+        let r = ED25519.decode_point(&sig[..32]).unwrap();
+        assert!(ed25519_verify_bytes(&pk, &m, &ed25519_sig(&r, &s)));
+        assert!(!ed25519_verify_bytes(
+            &pk,
+            &m,
+            &ed25519_sig(&r, &(&s + &ED25519.l))
+        ));
+    }
+
+    #[test]
+    fn ed25519_torsion_components() {
+        let t = ed25519_order_8_point();
+        let (a, _) = ed25519_expand(&[7; 32]);
+        let a_point = ED25519.mul(&a, &ED25519.b);
+        let m = b"torsion";
+        // Signs with the secret scalar a for the public key pk and the nonce point R.
+        let sign = |pk: &Pos, r_scalar: &BigInt, r: &Pos| {
+            let k = ed25519_hash(&[&ED25519.encode_point(r), &ED25519.encode_point(pk), m]);
+            ed25519_sig(r, &(r_scalar + k * &a).mod_floor(&ED25519.l))
+        };
+        let r_scalar = BigInt::from(1234);
+        let r = ED25519.mul(&r_scalar, &ED25519.b);
+
+        // A torsion component in A or R only changes the equation by a point of order 8, the
+        // cofactored equation accepts the signatures either way.
+        let mixed_pk = ED25519.add(&a_point, &t);
+        assert!(ed25519_verify(
+            &mixed_pk,
+            m,
+            &sign(&mixed_pk, &r_scalar, &r)
+        ));
+        let mixed_r = ED25519.add(&r, &t);
+        assert!(ed25519_verify(
+            &a_point,
+            m,
+            &sign(&a_point, &r_scalar, &mixed_r)
+        ));
+        assert!(ed25519_verify(&a_point, m, &sign(&a_point, &zero(), &t)));
+
+        // With a small order A, R = [S]B would verify for any message.
+        for pk in [t.clone(), ED25519.add(&t, &t), ED25519.identity()] {
+            let s = BigInt::from(5);
+            let forged = ed25519_sig(&ED25519.mul(&s, &ED25519.b), &s);
+            assert!(!ed25519_verify(&pk, m, &forged));
+        }
+    }
+}
@@ -0,0 +1,220 @@
+//! ElGamal encryption over any `AddGroup`, with the secret key sk and the public key sk * G.
+//!
+//! Messages are group elements. Exponential ElGamal encrypts a small integer m as m * G, which
+//! makes the ciphertexts additively homomorphic, and recovers m with baby-step giant-step.
+use crate::ecc::AddGroup;
+use num::{bigint::RandBigInt, one, BigInt, Integer};
+use rand::prelude::*;
+use std::collections::HashMap;
+
+/// (r * G, M + r * pk)
+#[derive(Clone, PartialEq, Eq)]
+pub struct Ciphertext<C: AddGroup> {
+    pub c1: C::Point,
+    pub c2: C::Point,
+}
+
+/// Encrypts the group element m to the public key.
+pub fn elgamal_encrypt<C: AddGroup, CR: RandBigInt + CryptoRng>(
+    m: &C::Point,
+    pk: &C::Point,
+    curve: &C,
+    crng: &mut CR,
+) -> Ciphertext<C> {
+    let r = crng.gen_bigint_range(&one(), curve.order());
+    Ciphertext {
+        c1: curve.mul(&r, curve.generator()),
+        c2: curve.add(m, &curve.mul(&r, pk)),
+    }
+}
+
+/// Decrypts to the group element c2 - sk * c1. Returns `None` if a point of the ciphertext
+/// isn't in the group generated by G, since sk * c1 would leak sk for other points.
+pub fn elgamal_decrypt<C: AddGroup>(c: &Ciphertext<C>, sk: &BigInt, curve: &C) -> Option<C::Point> {
+    let in_group =
+        |p: &C::Point| curve.validate(p) && curve.mul(curve.order(), p) == curve.identity();
+    if !in_group(&c.c1) || !in_group(&c.c2) {
+        return None;
+    }
+    Some(curve.sub(&c.c2, &curve.mul(sk, &c.c1)))
+}
+
+/// Encrypts the integer m as m * G.
+pub fn elgamal_encrypt_int<C: AddGroup, CR: RandBigInt + CryptoRng>(
+    m: &BigInt,
+    pk: &C::Point,
+    curve: &C,
+    crng: &mut CR,
+) -> Ciphertext<C> {
+    let m = m.mod_floor(curve.order());
+    elgamal_encrypt(&curve.mul(&m, curve.generator()), pk, curve, crng)
+}
+
+/// Decrypts an exponential ElGamal ciphertext. Returns `None` if the ciphertext is invalid or
+/// the integer is beyond the range of the table.
+pub fn elgamal_decrypt_int<C: AddGroup>(
+    c: &Ciphertext<C>,
+    sk: &BigInt,
+    table: &BsgsTable<C>,
+) -> Option<u64> {
+    table.solve(&elgamal_decrypt(c, sk, table.curve)?)
+}
+
+/// Adds a fresh encryption of the identity, the result decrypts to the same message but can't
+/// be linked to the original ciphertext.
+pub fn elgamal_rerandomize<C: AddGroup, CR: RandBigInt + CryptoRng>(
+    c: &Ciphertext<C>,
+    pk: &C::Point,
+    curve: &C,
+    crng: &mut CR,
+) -> Ciphertext<C> {
+    elgamal_add(
+        c,
+        &elgamal_encrypt(&curve.identity(), pk, curve, crng),
+        curve,
+    )
+}
+
+/// The ciphertext of the sum of the messages: m1 + m2 for group elements, and so the sum of
+/// the integers for exponential ElGamal.
+pub fn elgamal_add<C: AddGroup>(c: &Ciphertext<C>, d: &Ciphertext<C>, curve: &C) -> Ciphertext<C> {
+    Ciphertext {
+        c1: curve.add(&c.c1, &d.c1),
+        c2: curve.add(&c.c2, &d.c2),
+    }
+}
+
+/// A baby-step giant-step table for the discrete logarithms of m * G with 0 <= m <= max. It
+/// keeps the ⌈√(max + 1)⌉ baby steps j * G, and a lookup takes as many giant steps at most.
+pub struct BsgsTable<'a, C: AddGroup> {
+    curve: &'a C,
+    max: u64,
+    /// -(steps * G)
+    giant: C::Point,
+    steps: u64,
+    baby: HashMap<C::Point, u64>,
+}
+
+impl<'a, C: AddGroup> BsgsTable<'a, C> {
+    pub fn new(curve: &'a C, max: u64) -> Self {
+        let n = max as u128 + 1;
+        let mut steps = n.isqrt();
+        if steps * steps < n {
+            steps += 1;
+        }
+        let steps = steps as u64;
+        let mut baby = HashMap::with_capacity(steps as usize);
+        let mut p = curve.identity();
+        for j in 0..steps {
+            baby.entry(p.clone()).or_insert(j);
+            p = curve.add(&p, curve.generator());
+        }
+        Self {
+            curve,
+            max,
+            giant: curve.neg(&p),
+            steps,
+            baby,
+        }
+    }
+
+    /// The m <= max with m * G = p.
+    pub fn solve(&self, p: &C::Point) -> Option<u64> {
+        let mut gamma = p.clone();
+        for i in 0..self.steps {
+            if let Some(j) = self.baby.get(&gamma) {
+                let m = i * self.steps + j;
+                return (m <= self.max).then_some(m);
+            }
+            gamma = self.curve.add(&gamma, &self.giant);
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ecc::{ED25519, SECP256K1};
+
+    fn round_trips<C: AddGroup>(curve: &C, seed: u64)
+    where
+        C::Point: std::fmt::Debug,
+    {
+        let mut crng = StdRng::seed_from_u64(seed);
+        let sk = crng.gen_bigint_range(&one(), curve.order());
+        let pk = curve.mul(&sk, curve.generator());
+
+        let m = curve.mul(
+            &crng.gen_bigint_range(&one(), curve.order()),
+            curve.generator(),
+        );
+        let c = elgamal_encrypt(&m, &pk, curve, &mut crng);
+        assert_eq!(elgamal_decrypt(&c, &sk, curve), Some(m.clone()));
+        assert_ne!(elgamal_decrypt(&c, &(&sk + 1), curve), Some(m.clone()));
+
+        let r = elgamal_rerandomize(&c, &pk, curve, &mut crng);
+        assert!(r.c1 != c.c1 && r.c2 != c.c2);
+        assert_eq!(elgamal_decrypt(&r, &sk, curve), Some(m));
+
+        let max = 1000;
+        let table = BsgsTable::new(curve, max);
+        let enc = |m: u64, crng: &mut StdRng| elgamal_encrypt_int(&m.into(), &pk, curve, crng);
+        for m in [0, 1, 2, 31, 32, 500, max - 1, max] {
+            let c = enc(m, &mut crng);
+            assert_eq!(elgamal_decrypt_int(&c, &sk, &table), Some(m));
+        }
+        for m in [max + 1, 2 * max, 1 << 40] {
+            assert_eq!(elgamal_decrypt_int(&enc(m, &mut crng), &sk, &table), None);
+        }
+        let c = elgamal_encrypt_int(&(-1).into(), &pk, curve, &mut crng);
+        assert_eq!(elgamal_decrypt_int(&c, &sk, &table), None);
+
+        let c = elgamal_add(&enc(400, &mut crng), &enc(599, &mut crng), curve);
+        assert_eq!(elgamal_decrypt_int(&c, &sk, &table), Some(999));
+        let c = elgamal_rerandomize(&c, &pk, curve, &mut crng);
+        assert_eq!(elgamal_decrypt_int(&c, &sk, &table), Some(999));
+        let c = elgamal_add(&c, &enc(2, &mut crng), curve);
+        assert_eq!(elgamal_decrypt_int(&c, &sk, &table), None);
+        assert_eq!(
+            table.solve(&curve.mul(&1001.into(), curve.generator())),
+            None
+        );
+        assert_eq!(table.solve(&curve.identity()), Some(0));
+    }
+
+    #[test]
+    fn secp256k1() {
+        round_trips(&*SECP256K1, 0);
+    }
+
+    #[test]
+    fn ed25519() {
+        round_trips(&*ED25519, 1);
+    }
+
+    #[test]
+    fn rejects_small_order_points() {
+        let mut crng = StdRng::seed_from_u64(2);
+        let curve = &*ED25519;
+        let sk = crng.gen_bigint_range(&one(), curve.order());
+        let pk = curve.mul(&sk, curve.generator());
+        let c = elgamal_encrypt(curve.generator(), &pk, curve, &mut crng);
+        // (0, -1) has order 2
+        let mut bytes = [0xff; 32];
+        bytes[0] = 0xec;
+        bytes[31] = 0x7f;
+        let t = curve.decode_point(&bytes).unwrap();
+        assert!(curve.validate(&t));
+        let tampered = Ciphertext::<crate::ecc::TwistedEdwardsCurve> {
+            c1: curve.add(&c.c1, &t),
+            c2: c.c2.clone(),
+        };
+        assert_eq!(elgamal_decrypt(&tampered, &sk, curve), None);
+        let tampered = Ciphertext {
+            c1: c.c1.clone(),
+            c2: curve.add(&c.c2, &t),
+        };
+        assert_eq!(elgamal_decrypt(&tampered, &sk, curve), None);
+    }
+}
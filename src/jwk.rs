@@ -0,0 +1,447 @@
+//! JSON Web Keys (RFC 7517) for RSA, EC (P-256, secp256k1) and OKP (Ed25519, RFC 8037) keys.
+use crate::common::{base64_decode, base64_encode, i2osp, os2ip, BASE64URL};
+use crate::ecc::{ed25519_pub_key, AddGroup, EllipticCurve, Pos, ED25519, P256, SECP256K1};
+use crate::rsa::{PublicRsaKey, RsaKeyError, SecretRsaKey};
+use num::{bigint::RandBigInt, one, BigInt, Integer};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// The byte length of the coordinates and secret scalars of the supported curves.
+const EC_LEN: usize = 32;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum JwkError {
+    InvalidJson(String),
+    UnsupportedKeyType(String),
+    UnsupportedCurve(String),
+    MissingParameter(&'static str),
+    InvalidParameter(&'static str),
+    InvalidRsaKey(RsaKeyError),
+}
+
+impl fmt::Display for JwkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidJson(err) => write!(f, "invalid JWK JSON: {}", err),
+            Self::UnsupportedKeyType(kty) => write!(f, "unsupported JWK key type \"{}\"", kty),
+            Self::UnsupportedCurve(crv) => write!(f, "unsupported JWK curve \"{}\"", crv),
+            Self::MissingParameter(name) => write!(f, "missing JWK parameter \"{}\"", name),
+            Self::InvalidParameter(name) => write!(f, "invalid JWK parameter \"{}\"", name),
+            Self::InvalidRsaKey(err) => write!(f, "invalid JWK RSA key: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for JwkError {}
+
+/// A JSON Web Key, the key material is kept as base64url strings.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Jwk {
+    pub kty: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub crv: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub kid: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub alg: Option<String>,
+    #[serde(default, rename = "use", skip_serializing_if = "Option::is_none")]
+    pub use_: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub n: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub e: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub x: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub y: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub d: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub p: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub q: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dp: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dq: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub qi: Option<String>,
+}
+
+/// The key material of a JWK.
+#[derive(Clone)]
+pub enum JwkKey {
+    RsaPublic(PublicRsaKey),
+    RsaSecret(SecretRsaKey),
+    Ec {
+        curve: &'static EllipticCurve,
+        pk: Pos,
+        sk: Option<BigInt>,
+    },
+    Ed25519 {
+        pk: Pos,
+        seed: Option<[u8; 32]>,
+    },
+}
+
+pub(crate) fn b64(bytes: &[u8]) -> String {
+    base64_encode(bytes, BASE64URL, false)
+}
+
+/// Strict base64url decoding (RFC 7515 section 2): padding and non-zero trailing bits are
+/// rejected, so every byte string has exactly one accepted encoding.
+pub(crate) fn b64_decode(s: &str) -> Option<Vec<u8>> {
+    let bytes = base64_decode(s, BASE64URL)?;
+    (b64(&bytes) == s).then_some(bytes)
+}
+
+/// Base64urlUInt encoding, the minimal big endian encoding of an unsigned integer.
+fn b64_uint(n: &BigInt) -> String {
+    b64(&n.to_bytes_be().1)
+}
+
+/// Base64url encoding of an unsigned integer padded to `len` bytes.
+fn b64_fixed(n: &BigInt, len: usize) -> String {
+    b64(&i2osp(n, len).unwrap())
+}
+
+fn param<'a>(value: &'a Option<String>, name: &'static str) -> Result<&'a str, JwkError> {
+    value.as_deref().ok_or(JwkError::MissingParameter(name))
+}
+
+fn decode(value: &Option<String>, name: &'static str) -> Result<Vec<u8>, JwkError> {
+    b64_decode(param(value, name)?).ok_or(JwkError::InvalidParameter(name))
+}
+
+fn decode_uint(value: &Option<String>, name: &'static str) -> Result<BigInt, JwkError> {
+    Ok(os2ip(&decode(value, name)?))
+}
+
+fn decode_fixed(value: &Option<String>, name: &'static str) -> Result<Vec<u8>, JwkError> {
+    let bytes = decode(value, name)?;
+    if bytes.len() == EC_LEN {
+        Ok(bytes)
+    } else {
+        Err(JwkError::InvalidParameter(name))
+    }
+}
+
+fn curve_name(curve: &EllipticCurve) -> Option<&'static str> {
+    if *curve == *P256 {
+        Some("P-256")
+    } else if *curve == *SECP256K1 {
+        Some("secp256k1")
+    } else {
+        None
+    }
+}
+
+fn curve_by_name(name: &str) -> Option<&'static EllipticCurve> {
+    match name {
+        "P-256" => Some(&P256),
+        "secp256k1" => Some(&SECP256K1),
+        _ => None,
+    }
+}
+
+impl Jwk {
+    pub fn from_json(json: &str) -> Result<Self, JwkError> {
+        serde_json::from_str(json).map_err(|err| JwkError::InvalidJson(err.to_string()))
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap()
+    }
+
+    pub fn from_rsa_public(key: &PublicRsaKey) -> Self {
+        Self {
+            kty: "RSA".to_owned(),
+            n: Some(b64_uint(key.n())),
+            e: Some(b64_uint(key.e())),
+            ..Self::default()
+        }
+    }
+
+    pub fn from_rsa_secret(key: &SecretRsaKey) -> Self {
+        let (p, q) = key.primes();
+        let p1: BigInt = p - 1;
+        let q1: BigInt = q - 1;
+        Self {
+            d: Some(b64_uint(key.d())),
+            p: Some(b64_uint(p)),
+            q: Some(b64_uint(q)),
+            dp: Some(b64_uint(&(key.d() % p1))),
+            dq: Some(b64_uint(&(key.d() % q1))),
+            qi: Some(b64_uint(key.qinv())),
+            ..Self::from_rsa_public(&key.pub_key())
+        }
+    }
+
+    /// An EC key on P-256 or secp256k1, with the secret scalar if given.
+    pub fn from_ec(
+        curve: &'static EllipticCurve,
+        pk: &Pos,
+        sk: Option<&BigInt>,
+    ) -> Result<Self, JwkError> {
+        let crv = curve_name(curve).ok_or_else(|| JwkError::UnsupportedCurve("?".to_owned()))?;
+        Ok(Self {
+            kty: "EC".to_owned(),
+            crv: Some(crv.to_owned()),
+            x: Some(b64_fixed(pk.x(), EC_LEN)),
+            y: Some(b64_fixed(pk.y(), EC_LEN)),
+            d: sk.map(|sk| b64_fixed(sk, EC_LEN)),
+            ..Self::default()
+        })
+    }
+
+    /// An Ed25519 OKP key, with the RFC 8032 seed if given.
+    pub fn from_ed25519(pk: &Pos, seed: Option<&[u8; 32]>) -> Self {
+        Self {
+            kty: "OKP".to_owned(),
+            crv: Some("Ed25519".to_owned()),
+            x: Some(b64(&ED25519.encode_point(pk))),
+            d: seed.map(|seed| b64(seed)),
+            ..Self::default()
+        }
+    }
+
+    /// The JWK without its private members.
+    pub fn to_public(&self) -> Self {
+        Self {
+            d: None,
+            p: None,
+            q: None,
+            dp: None,
+            dq: None,
+            qi: None,
+            ..self.clone()
+        }
+    }
+
+    /// Decodes and validates the key material, RSA keys with `PublicRsaKey::validate` and
+    /// `SecretRsaKey::validate`. The optional CRT parameters must match the other parameters.
+    pub fn to_key<R: RandBigInt>(&self, rng: &mut R) -> Result<JwkKey, JwkError> {
+        match self.kty.as_str() {
+            "RSA" => {
+                let n = decode_uint(&self.n, "n")?;
+                let e = decode_uint(&self.e, "e")?;
+                if self.d.is_none() {
+                    let key = PublicRsaKey::new(e, n);
+                    key.validate().map_err(JwkError::InvalidRsaKey)?;
+                    return Ok(JwkKey::RsaPublic(key));
+                }
+                let d = decode_uint(&self.d, "d")?;
+                let p = decode_uint(&self.p, "p")?;
+                let q = decode_uint(&self.q, "q")?;
+                // the CRT values are computed mod p - 1 and q - 1
+                if p <= one() || q <= one() || &p * &q != n {
+                    return Err(JwkError::InvalidParameter("p"));
+                }
+                if self.dp.is_some() && decode_uint(&self.dp, "dp")? != d.mod_floor(&(&p - 1)) {
+                    return Err(JwkError::InvalidParameter("dp"));
+                }
+                if self.dq.is_some() && decode_uint(&self.dq, "dq")? != d.mod_floor(&(&q - 1)) {
+                    return Err(JwkError::InvalidParameter("dq"));
+                }
+                let key = SecretRsaKey::from_components(n, e, d, p, q);
+                if self.qi.is_some() && decode_uint(&self.qi, "qi")? != *key.qinv() {
+                    return Err(JwkError::InvalidParameter("qi"));
+                }
+                key.validate(rng).map_err(JwkError::InvalidRsaKey)?;
+                Ok(JwkKey::RsaSecret(key))
+            }
+            "EC" => {
+                let crv = param(&self.crv, "crv")?;
+                let curve =
+                    curve_by_name(crv).ok_or_else(|| JwkError::UnsupportedCurve(crv.to_owned()))?;
+                let pk = Pos::new(
+                    os2ip(&decode_fixed(&self.x, "x")?),
+                    os2ip(&decode_fixed(&self.y, "y")?),
+                );
+                if !curve.validate(&Some(pk.clone())) {
+                    return Err(JwkError::InvalidParameter("x"));
+                }
+                let sk = match self.d {
+                    Some(_) => {
+                        let sk = os2ip(&decode_fixed(&self.d, "d")?);
+                        if curve.mul(&sk, &curve.g) != Some(pk.clone()) {
+                            return Err(JwkError::InvalidParameter("d"));
+                        }
+                        Some(sk)
+                    }
+                    None => None,
+                };
+                Ok(JwkKey::Ec { curve, pk, sk })
+            }
+            "OKP" => {
+                let crv = param(&self.crv, "crv")?;
+                if crv != "Ed25519" {
+                    return Err(JwkError::UnsupportedCurve(crv.to_owned()));
+                }
+                let pk = ED25519
+                    .decode_point(&decode_fixed(&self.x, "x")?)
+                    .ok_or(JwkError::InvalidParameter("x"))?;
+                let seed = match self.d {
+                    Some(_) => {
+                        let seed: [u8; 32] = decode_fixed(&self.d, "d")?.try_into().unwrap();
+                        if ed25519_pub_key(&seed) != pk {
+                            return Err(JwkError::InvalidParameter("d"));
+                        }
+                        Some(seed)
+                    }
+                    None => None,
+                };
+                Ok(JwkKey::Ed25519 { pk, seed })
+            }
+            kty => Err(JwkError::UnsupportedKeyType(kty.to_owned())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::prelude::*;
+
+    /// The public keys of RFC 7517 appendix A.1.
+    const RFC7517_EC_KEY: &str = "{\"kty\":\"EC\",\"crv\":\"P-256\",\
+        \"x\":\"MKBCTNIcKUSDii11ySs3526iDZ8AiTo7Tu6KPAqv7D4\",\
+        \"y\":\"4Etl6SRW2YiLUrN5vfvVHuhp7x8PxltmWWlbbM4IFyM\",\
+        \"use\":\"enc\",\"kid\":\"1\"}";
+    const RFC7517_RSA_KEY: &str = "{\"kty\":\"RSA\",\
+        \"n\":\"0vx7agoebGcQSuuPiLJXZptN9nndrQmbXEps2aiAFbWhM78LhWx4cbbfAAtVT86zwu1RK7aPFFxuhDR1\
+            L6tSoc_BJECPebWKRXjBZCiFV4n3oknjhMstn64tZ_2W-5JsGY4Hc5n9yBXArwl93lqt7_RN5w6Cf0h4QyQ5v-6\
+            5YGjQR0_FDW2QvzqY368QQMicAtaSqzs8KJZgnYb9c7d0zgdAZHzu6qMQvRL5hajrn1n91CbOpbISD08qNLyrdk\
+            t-bFTWhAI4vMQFh6WeZu0fM4lFd2NcRwr3XPksINHaQ-G_xBniIqbw0Ls1jF44-csFCur-kEgU8awapJzKnqDKgw\",\
+        \"e\":\"AQAB\",\"alg\":\"RS256\",\"kid\":\"2011-04-29\"}";
+    /// The secret scalar of the EC key from RFC 7517 appendix A.2.
+    const RFC7517_EC_D: &str = "870MB6gfuTJ4HtUnUvYMyJpr5eUZNP4Bk43bVdj3eAE";
+
+    #[test]
+    fn rfc7517_keys() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let ec = Jwk::from_json(RFC7517_EC_KEY).unwrap();
+        assert_eq!(ec.kid.as_deref(), Some("1"));
+        assert_eq!(ec.use_.as_deref(), Some("enc"));
+        let Ok(JwkKey::Ec {
+            curve,
+            pk,
+            sk: None,
+        }) = ec.to_key(&mut rng)
+        else {
+            panic!("not a public EC key");
+        };
+        assert!(*curve == *P256);
+        assert_eq!(Jwk::from_ec(curve, &pk, None).unwrap().x, ec.x);
+        assert_eq!(Jwk::from_json(&ec.to_json()).unwrap(), ec);
+
+        let secret = Jwk {
+            d: Some(RFC7517_EC_D.to_owned()),
+            ..ec.clone()
+        };
+        let Ok(JwkKey::Ec { sk: Some(sk), .. }) = secret.to_key(&mut rng) else {
+            panic!("not a secret EC key");
+        };
+        assert_eq!(b64_fixed(&sk, EC_LEN), RFC7517_EC_D);
+        let wrong_d = Jwk {
+            d: Some(b64_fixed(&(sk + 1), EC_LEN)),
+            ..ec.clone()
+        };
+        assert_eq!(
+            wrong_d.to_key(&mut rng).err(),
+            Some(JwkError::InvalidParameter("d"))
+        );
+        let off_curve = Jwk {
+            y: ec.x.clone(),
+            ..ec
+        };
+        assert_eq!(
+            off_curve.to_key(&mut rng).err(),
+            Some(JwkError::InvalidParameter("x"))
+        );
+
+        let rsa = Jwk::from_json(RFC7517_RSA_KEY).unwrap();
+        let Ok(JwkKey::RsaPublic(pk)) = rsa.to_key(&mut rng) else {
+            panic!("not a public RSA key");
+        };
+        assert_eq!(pk.size(), 256);
+        let encoded = Jwk::from_rsa_public(&pk);
+        assert_eq!((&encoded.n, &encoded.e), (&rsa.n, &rsa.e));
+        assert_eq!(Jwk::from_json(&rsa.to_json()).unwrap(), rsa);
+        assert!(matches!(
+            Jwk::from_json("{\"kty\":\"oct\",\"k\":\"AQAB\"}")
+                .unwrap()
+                .to_key(&mut rng),
+            Err(JwkError::UnsupportedKeyType(_))
+        ));
+    }
+
+    #[test]
+    fn strict_base64url() {
+        assert_eq!(b64_decode("AQAB"), Some(vec![1, 0, 1]));
+        assert_eq!(b64_decode("_-8"), Some(vec![0xff, 0xef]));
+        assert_eq!(b64_decode(""), Some(vec![]));
+        // padding, the standard alphabet and non-zero trailing bits
+        assert_eq!(b64_decode("AQ=="), None);
+        assert_eq!(b64_decode("/+8"), None);
+        assert_eq!(b64_decode("AR"), None);
+        assert_eq!(b64_decode("_-9"), None);
+        assert_eq!(b64_decode("A"), None);
+    }
+
+    #[test]
+    fn rsa_keys_are_validated() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let key = SecretRsaKey::from_pem(include_str!("../testdata/rsa-2048.pem")).unwrap();
+        let jwk = Jwk::from_rsa_secret(&key);
+        assert!(matches!(jwk.to_key(&mut rng), Ok(JwkKey::RsaSecret(_))));
+        assert!(matches!(
+            jwk.to_public().to_key(&mut rng),
+            Ok(JwkKey::RsaPublic(_))
+        ));
+
+        let small_e = Jwk {
+            e: Some(b64_uint(&3.into())),
+            ..jwk.to_public()
+        };
+        assert_eq!(
+            small_e.to_key(&mut rng).err(),
+            Some(JwkError::InvalidRsaKey(RsaKeyError::ExponentOutOfRange))
+        );
+        let swapped_crt = Jwk {
+            dp: jwk.dq.clone(),
+            ..jwk.clone()
+        };
+        assert_eq!(
+            swapped_crt.to_key(&mut rng).err(),
+            Some(JwkError::InvalidParameter("dp"))
+        );
+        let swapped_crt = Jwk {
+            dq: jwk.dp.clone(),
+            ..jwk.clone()
+        };
+        assert_eq!(
+            swapped_crt.to_key(&mut rng).err(),
+            Some(JwkError::InvalidParameter("dq"))
+        );
+        let wrong_d = Jwk {
+            d: Some(b64_uint(&(key.d() + 2))),
+            dp: None,
+            dq: None,
+            ..jwk.clone()
+        };
+        assert_eq!(
+            wrong_d.to_key(&mut rng).err(),
+            Some(JwkError::InvalidRsaKey(RsaKeyError::ExponentMismatch))
+        );
+        let trivial_p = Jwk {
+            p: Some(b64_uint(&1.into())),
+            q: jwk.n.clone(),
+            ..jwk.clone()
+        };
+        assert_eq!(
+            trivial_p.to_key(&mut rng).err(),
+            Some(JwkError::InvalidParameter("p"))
+        );
+    }
+}
@@ -0,0 +1,357 @@
+//! Compact JSON Web Signatures (RFC 7515) with the RS256, PS256, ES256, ES256K and EdDSA algorithms.
+use crate::common::{i2osp, os2ip};
+use crate::ecc::{
+    ecdsa_sign_prehashed, ecdsa_verify_prehashed, ed25519_sign, ed25519_verify, P256, SECP256K1,
+};
+use crate::jwk::{b64, b64_decode, Jwk, JwkError, JwkKey};
+use crate::rsa::PublicRsaKey;
+use digest::Digest;
+use num::{bigint::RandBigInt, BigInt};
+use rand::prelude::*;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::fmt;
+
+/// The byte length of the ES256 and ES256K signature halves.
+const EC_LEN: usize = 32;
+/// The PSS salt length of PS256, the output length of SHA-256.
+const PSS_SALT_LEN: usize = 32;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Algorithm {
+    RS256,
+    PS256,
+    ES256,
+    ES256K,
+    EdDSA,
+}
+
+impl Algorithm {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::RS256 => "RS256",
+            Self::PS256 => "PS256",
+            Self::ES256 => "ES256",
+            Self::ES256K => "ES256K",
+            Self::EdDSA => "EdDSA",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "RS256" => Some(Self::RS256),
+            "PS256" => Some(Self::PS256),
+            "ES256" => Some(Self::ES256),
+            "ES256K" => Some(Self::ES256K),
+            "EdDSA" => Some(Self::EdDSA),
+            _ => None,
+        }
+    }
+
+    /// Whether the algorithm can be used with the key.
+    fn fits(self, key: &JwkKey) -> bool {
+        match (self, key) {
+            (Self::RS256 | Self::PS256, JwkKey::RsaPublic(_) | JwkKey::RsaSecret(_)) => true,
+            (Self::ES256, JwkKey::Ec { curve, .. }) => **curve == *P256,
+            (Self::ES256K, JwkKey::Ec { curve, .. }) => **curve == *SECP256K1,
+            (Self::EdDSA, JwkKey::Ed25519 { .. }) => true,
+            _ => false,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum JwsError {
+    Key(JwkError),
+    InvalidFormat,
+    InvalidHeader,
+    UnsupportedAlgorithm(String),
+    AlgorithmMismatch,
+    UnsupportedCritical,
+    KeyIdMismatch,
+    MissingSecretKey,
+    InvalidSignature,
+}
+
+impl fmt::Display for JwsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Key(err) => err.fmt(f),
+            Self::InvalidFormat => write!(f, "invalid compact JWS"),
+            Self::InvalidHeader => write!(f, "invalid JWS header"),
+            Self::UnsupportedAlgorithm(alg) => write!(f, "unsupported JWS algorithm \"{}\"", alg),
+            Self::AlgorithmMismatch => write!(f, "the JWS algorithm doesn't match the key"),
+            Self::UnsupportedCritical => write!(f, "unsupported critical JWS header parameters"),
+            Self::KeyIdMismatch => write!(f, "the JWS key id doesn't match the key"),
+            Self::MissingSecretKey => write!(f, "signing requires a secret key"),
+            Self::InvalidSignature => write!(f, "invalid JWS signature"),
+        }
+    }
+}
+
+impl std::error::Error for JwsError {}
+
+impl From<JwkError> for JwsError {
+    fn from(err: JwkError) -> Self {
+        Self::Key(err)
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct Header {
+    alg: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    typ: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    kid: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    crit: Option<Vec<String>>,
+}
+
+/// Checks that the algorithm may be used with the key, also against the JWK's `alg`.
+fn check_algorithm(alg: Algorithm, jwk: &Jwk, key: &JwkKey) -> Result<(), JwsError> {
+    if !alg.fits(key) || jwk.alg.as_deref().is_some_and(|a| a != alg.name()) {
+        Err(JwsError::AlgorithmMismatch)
+    } else {
+        Ok(())
+    }
+}
+
+fn sha256_int(m: &[u8]) -> BigInt {
+    os2ip(&Sha256::digest(m))
+}
+
+fn verify_rsa(alg: Algorithm, pk: &PublicRsaKey, input: &[u8], sig: &[u8]) -> bool {
+    match alg {
+        Algorithm::RS256 => pk.verify_pkcs1v15(&mut Sha256::new(), input, sig),
+        _ => pk.verify_pss(&mut Sha256::new(), input, sig, PSS_SALT_LEN),
+    }
+}
+
+/// Signs the payload, returns the compact serialization `header.payload.signature`.
+/// The header contains the algorithm and the JWK's `kid`.
+pub fn sign<CR: RandBigInt + RngCore + CryptoRng>(
+    payload: &[u8],
+    alg: Algorithm,
+    jwk: &Jwk,
+    crng: &mut CR,
+) -> Result<String, JwsError> {
+    let key = jwk.to_key(crng)?;
+    check_algorithm(alg, jwk, &key)?;
+    let header = Header {
+        alg: alg.name().to_owned(),
+        typ: None,
+        kid: jwk.kid.clone(),
+        crit: None,
+    };
+    let input = format!(
+        "{}.{}",
+        b64(serde_json::to_string(&header).unwrap().as_bytes()),
+        b64(payload)
+    );
+
+    let sig = match key {
+        JwkKey::RsaSecret(sk) => match alg {
+            Algorithm::RS256 => sk.sign_pkcs1v15(&mut Sha256::new(), input.as_bytes(), crng),
+            _ => sk.sign_pss(&mut Sha256::new(), input.as_bytes(), PSS_SALT_LEN, crng),
+        }
+        .ok_or(JwsError::InvalidSignature)?,
+        JwkKey::Ec {
+            curve,
+            sk: Some(sk),
+            ..
+        } => {
+            let (r, s) = ecdsa_sign_prehashed(&sha256_int(input.as_bytes()), &sk, curve, crng);
+            [i2osp(&r, EC_LEN).unwrap(), i2osp(&s, EC_LEN).unwrap()].concat()
+        }
+        JwkKey::Ed25519 {
+            seed: Some(seed), ..
+        } => ed25519_sign(&seed, input.as_bytes()).to_vec(),
+        _ => return Err(JwsError::MissingSecretKey),
+    };
+    Ok(format!("{}.{}", input, b64(&sig)))
+}
+
+/// Verifies a compact JWS against the key and returns the payload.
+/// The header's algorithm must fit the key, and `crit` extensions are rejected.
+/// The RNG is used to validate secret RSA keys.
+pub fn verify<R: RandBigInt>(token: &str, jwk: &Jwk, rng: &mut R) -> Result<Vec<u8>, JwsError> {
+    let mut parts = token.split('.');
+    let (Some(header_b64), Some(payload_b64), Some(sig_b64), None) =
+        (parts.next(), parts.next(), parts.next(), parts.next())
+    else {
+        return Err(JwsError::InvalidFormat);
+    };
+    let header = b64_decode(header_b64).ok_or(JwsError::InvalidFormat)?;
+    let payload = b64_decode(payload_b64).ok_or(JwsError::InvalidFormat)?;
+    let sig = b64_decode(sig_b64).ok_or(JwsError::InvalidFormat)?;
+
+    let header: Header = serde_json::from_slice(&header).map_err(|_| JwsError::InvalidHeader)?;
+    let alg = Algorithm::from_name(&header.alg)
+        .ok_or_else(|| JwsError::UnsupportedAlgorithm(header.alg.clone()))?;
+    if header.crit.is_some() {
+        return Err(JwsError::UnsupportedCritical);
+    }
+    if let (Some(kid), Some(jwk_kid)) = (&header.kid, &jwk.kid) {
+        if kid != jwk_kid {
+            return Err(JwsError::KeyIdMismatch);
+        }
+    }
+    let key = jwk.to_key(rng)?;
+    check_algorithm(alg, jwk, &key)?;
+
+    let input = &token[..header_b64.len() + 1 + payload_b64.len()];
+    let valid = match key {
+        JwkKey::RsaPublic(pk) => verify_rsa(alg, &pk, input.as_bytes(), &sig),
+        JwkKey::RsaSecret(sk) => verify_rsa(alg, &sk.pub_key(), input.as_bytes(), &sig),
+        JwkKey::Ec { curve, pk, .. } => {
+            sig.len() == 2 * EC_LEN
+                && ecdsa_verify_prehashed(
+                    &sha256_int(input.as_bytes()),
+                    &Some(pk),
+                    &(os2ip(&sig[..EC_LEN]), os2ip(&sig[EC_LEN..])),
+                    curve,
+                )
+        }
+        JwkKey::Ed25519 { pk, .. } => ed25519_verify(&pk, input.as_bytes(), &sig),
+    };
+    if valid {
+        Ok(payload)
+    } else {
+        Err(JwsError::InvalidSignature)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ecc::AddGroup;
+
+    /// The RSA key of RFC 7515 appendix A.2.
+    const RFC7515_RSA_KEY: &str = "{\"kty\":\"RSA\",\
+        \"n\":\"ofgWCuLjybRlzo0tZWJjNiuSfb4p4fAkd_wWJcyQoTbji9k0l8W26mPddxHmfHQp-Vaw-4qPCJrcS2mJPMEz\
+            P1Pt0Bm4d4QlL-yRT-SFd2lZS-pCgNMsD1W_YpRPEwOWvG6b32690r2jZ47soMZo9wGzjb_7OMg0LOL-bSf63kp\
+            aSHSXndS5z5rexMdbBYUsLA9e-KXBdQOS-UTo7WTBEMa2R2CapHg665xsmtdVMTBQY4uDZlxvb3qCo5ZwKh9kG4\
+            LT6_I5IhlJH7aGhyxXFvUK-DWNmoudF8NAco9_h9iaGNj8q2ethFkMLs91kzk2PAcDTW9gb54h4FRWyuXpoQ\",\
+        \"e\":\"AQAB\",\
+        \"d\":\"Eq5xpGnNCivDflJsRQBXHx1hdR1k6Ulwe2JZD50LpXyWPEAeP88vLNO97IjlA7_GQ5sLKMgvfTeXZx9SE-7Y\
+            wVol2NXOoAJe46sui395IW_GO-pWJ1O0BkTGoVEn2bKVRUCgu-GjBVaYLU6f3l9kJfFNS3E0QbVdxzubSu3Mkqz\
+            jkn439X0M_V51gfpRLI9JYanrC4D4qAdGcopV_0ZHHzQlBjudU2QvXt4ehNYTCBr6XCLQUShb1juUO1ZdiYoFaF\
+            QT5Tw8bGUl_x_jTj3ccPDVZFD9pIuhLhBOneufuBiB4cS98l2SR_RQyGWSeWjnczT0QU91p1DhOVRuOopznQ\",\
+        \"p\":\"4BzEEOtIpmVdVEZNCqS7baC4crd0pqnRH_5IB3jw3bcxGn6QLvnEtfdUdiYrqBdss1l58BQ3KhooKeQTa9AB\
+            0Hw_Py5PJdTJNPY8cQn7ouZ2KKDcmnPGBY5t7yLc1QlQ5xHdwW1VhvKn-nXqhJTBgIPgtldC-KDV5z-y2XDwGUc\",\
+        \"q\":\"uQPEfgmVtjL0Uyyx88GZFF1fOunH3-7cepKmtH4pxhtCoHqpWmT8YAmZxaewHgHAjLYsp1ZSe7zFYHj7C6ul\
+            7TjeLQeZD_YwD66t62wDmpe_HlB-TnBA-njbglfIsRLtXlnDzQkv5dTltRJ11BKBBypeeF6689rjcJIDEz9RWdc\",\
+        \"dp\":\"BwKfV3Akq5_MFZDFZCnW-wzl-CCo83WoZvnLQwCTeDv8uzluRSnm71I3QCLdhrqE2e9YkxvuxdBfpT_PI7Yz\
+            -FOKnu1R6HsJeDCjn12Sk3vmAktV2zb34MCdy7cpdTh_YVr7tss2u6vneTwrA86rZtu5Mbr1C1XsmvkxHQAdYo0\",\
+        \"dq\":\"h_96-mK1R_7glhsum81dZxjTnYynPbZpHziZjeeHcXYsXaaMwkOlODsWa7I9xXDoRwbKgB719rrmI2oKr6N3\
+            Do9U0ajaHF-NKJnwgjMd2w9cjz3_-kyNlxAr2v4IKhGNpmM5iIgOS1VZnOZ68m6_pbLBSp3nssTdlqvd0tIiTHU\",\
+        \"qi\":\"IYd7DHOhrWvxkwPQsRM2tOgrjbcrfvtQJipd-DlcxyVuuM9sQLdgjVk2oy26F0EmpScGLq2MowX7fhd_QJQ3\
+            ydy5cY7YIBi87w93IKLEdfnbJtoOPLUW0ITrJReOgo1cq9SbsxYawBgfp_gh6A5603k2-ZQwVK0JKSHuLFkuQ3U\"\
+        }";
+
+    /// The RS256 JWS of RFC 7515 appendix A.2.
+    const RFC7515_JWS: &str = "eyJhbGciOiJSUzI1NiJ9.\
+        eyJpc3MiOiJqb2UiLA0KICJleHAiOjEzMDA4MTkzODAsDQogImh0dHA6Ly9leGFtcGxlLmNvbS9pc19yb290Ijp0cnVlfQ.\
+        cC4hiUPoj9Eetdgtv3hF80EGrhuB__dzERat0XF9g2VtQgr9PJbu3XOiZj5RZmh7AAuHIm4Bh-0Qc_lF5YKt_O8W\
+        2Fp5jujGbds9uJdbF9CUAr7t1dnZcAcQjbKBYNX4BAynRFdiuB--f_nZLgrnbyTyWzO75vRK5h6xBArLIARNPvkSj\
+        tQBMHlb1L07Qe7K0GarZRmB_eSN9383LcOLn6_dO--xi12jzDwusC-eOkHWEsqtFZESc6BfI7noOPqvhJ1phCnvWh\
+        6IeYI2w9QOYEUipUTI8np6LbgGY9Fs98rqVt5AXLIhWkWywlVmtVrBp0igcN_IoypGlUPQGe77Rw";
+
+    const RFC7515_PAYLOAD: &[u8] =
+        b"{\"iss\":\"joe\",\r\n \"exp\":1300819380,\r\n \"http://example.com/is_root\":true}";
+
+    /// The Ed25519 key of RFC 8037 appendix A.1.
+    const RFC8037_KEY: &str = "{\"kty\":\"OKP\",\"crv\":\"Ed25519\",\
+        \"d\":\"nWGxne_9WmC6hEr0kuwsxERJxWl7MmkZcDusAxyuf2A\",\
+        \"x\":\"11qYAYKxCrfVS_7TyWQHOg7hcvPapiMlrwIaaPcHURo\"}";
+
+    /// The EdDSA JWS of RFC 8037 appendix A.4.
+    const RFC8037_JWS: &str = "eyJhbGciOiJFZERTQSJ9.RXhhbXBsZSBvZiBFZDI1NTE5IHNpZ25pbmc.\
+        hgyY0il_MGCjP0JzlnLWG1PPOt7-09PGcvMg3AIbQR6dWbhijcNR4ki4iylGjg5BhVsPt9g7sVvpAr_MuM0KAg";
+
+    #[test]
+    fn rfc7515_rs256() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let jwk = Jwk::from_json(RFC7515_RSA_KEY).unwrap();
+        assert_eq!(
+            sign(RFC7515_PAYLOAD, Algorithm::RS256, &jwk, &mut rng).unwrap(),
+            RFC7515_JWS
+        );
+        assert_eq!(
+            verify(RFC7515_JWS, &jwk.to_public(), &mut rng).unwrap(),
+            RFC7515_PAYLOAD
+        );
+        assert_eq!(
+            verify(RFC7515_JWS, &jwk, &mut rng).unwrap(),
+            RFC7515_PAYLOAD
+        );
+        assert_eq!(
+            verify(&RFC7515_JWS.replace(".eyJpc3", ".eyJpc4"), &jwk, &mut rng),
+            Err(JwsError::InvalidSignature)
+        );
+        let ps256 = Jwk {
+            alg: Some("PS256".to_owned()),
+            ..jwk.to_public()
+        };
+        assert_eq!(
+            verify(RFC7515_JWS, &ps256, &mut rng),
+            Err(JwsError::AlgorithmMismatch)
+        );
+    }
+
+    #[test]
+    fn rfc8037_ed25519() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let jwk = Jwk::from_json(RFC8037_KEY).unwrap();
+        let payload = b"Example of Ed25519 signing";
+        assert_eq!(
+            sign(payload, Algorithm::EdDSA, &jwk, &mut rng).unwrap(),
+            RFC8037_JWS
+        );
+        assert_eq!(
+            verify(RFC8037_JWS, &jwk.to_public(), &mut rng).unwrap(),
+            payload
+        );
+        let (input, sig) = RFC8037_JWS.rsplit_once('.').unwrap();
+        let mut sig = b64_decode(sig).unwrap();
+        sig[0] ^= 1;
+        assert_eq!(
+            verify(&format!("{}.{}", input, b64(&sig)), &jwk, &mut rng),
+            Err(JwsError::InvalidSignature)
+        );
+        // padding and a trailing segment aren't compact JWS
+        assert_eq!(
+            verify(&format!("{}==", RFC8037_JWS), &jwk, &mut rng),
+            Err(JwsError::InvalidFormat)
+        );
+        assert_eq!(
+            verify(&format!("{}.", RFC8037_JWS), &jwk, &mut rng),
+            Err(JwsError::InvalidFormat)
+        );
+    }
+
+    #[test]
+    fn round_trips() {
+        let mut rng = StdRng::seed_from_u64(0);
+        for (curve, alg) in [(&*P256, Algorithm::ES256), (&*SECP256K1, Algorithm::ES256K)] {
+            let sk = rng.gen_bigint_range(&1.into(), curve.order());
+            let jwk = Jwk::from_ec(curve, &curve.mul(&sk, &curve.g).unwrap(), Some(&sk)).unwrap();
+            let token = sign(b"payload", alg, &jwk, &mut rng).unwrap();
+            assert_eq!(
+                verify(&token, &jwk.to_public(), &mut rng).unwrap(),
+                b"payload"
+            );
+            assert_eq!(
+                verify(&token, &Jwk::from_json(RFC8037_KEY).unwrap(), &mut rng),
+                Err(JwsError::AlgorithmMismatch)
+            );
+        }
+        let jwk = Jwk::from_json(RFC7515_RSA_KEY).unwrap();
+        let token = sign(b"payload", Algorithm::PS256, &jwk, &mut rng).unwrap();
+        assert_eq!(
+            verify(&token, &jwk.to_public(), &mut rng).unwrap(),
+            b"payload"
+        );
+    }
+}
@@ -0,0 +1,228 @@
+//! The Paillier cryptosystem, additively homomorphic public key encryption.
+use crate::common::mod_inv;
+use crate::rsa::{gen_prime, MIN_BITS};
+use num::{bigint::RandBigInt, one, BigInt, Integer, One};
+use rand::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// The modulus n and its square n², the generator is g = n + 1.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct PublicPaillierKey(BigInt, BigInt);
+/// λ = lcm(p - 1, q - 1), μ = λ^-1 mod n, the public key and the values for CRT decryption.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SecretPaillierKey(BigInt, BigInt, PublicPaillierKey, CrtValues);
+
+/// The primes, their squares and hp = L_p(g^(p - 1) mod p²)^-1 mod p, and the same for q.
+#[derive(Clone, Serialize, Deserialize)]
+struct CrtValues {
+    p: BigInt,
+    q: BigInt,
+    p2: BigInt,
+    q2: BigInt,
+    hp: BigInt,
+    hq: BigInt,
+    /// q^-1 mod p
+    qinv: BigInt,
+}
+
+/// L(x) = (x - 1) / n
+fn l(x: &BigInt, n: &BigInt) -> BigInt {
+    (x - 1) / n
+}
+
+/// Generates a key with a modulus of `bits` bits from two primes of equal size.
+pub fn gen_paillier_key<R: RandBigInt, CR: RandBigInt + CryptoRng>(
+    bits: u64,
+    rng: &mut R,
+    crng: &mut CR,
+) -> SecretPaillierKey {
+    assert!(
+        MIN_BITS <= bits && bits.is_even(),
+        "Paillier keys must have an even size of at least {} bits",
+        MIN_BITS
+    );
+    loop {
+        let p = gen_prime(bits / 2, rng, crng);
+        let q = gen_prime(bits / 2, rng, crng);
+        if p != q {
+            break SecretPaillierKey::from_primes(p, q);
+        }
+    }
+}
+
+impl PublicPaillierKey {
+    pub fn new(n: BigInt) -> Self {
+        let n2 = &n * &n;
+        Self(n, n2)
+    }
+
+    /// The modulus, the plain texts are the integers mod n.
+    pub fn n(&self) -> &BigInt {
+        &self.0
+    }
+
+    /// Encrypts 0 <= m < n as (1 + mn) r^n mod n² with a random r.
+    pub fn enc<CR: RandBigInt + CryptoRng>(&self, m: &BigInt, crng: &mut CR) -> Option<BigInt> {
+        let PublicPaillierKey(n, n2) = self;
+        if m < &BigInt::from(0) || n <= m {
+            return None;
+        }
+        let r = loop {
+            let r = crng.gen_bigint_range(&one(), n);
+            if r.gcd(n).is_one() {
+                break r;
+            }
+        };
+        Some(((m * n + 1) * r.modpow(n, n2)) % n2)
+    }
+
+    /// The encryption of the sum of the plain texts mod n.
+    pub fn add(&self, c1: &BigInt, c2: &BigInt) -> BigInt {
+        (c1 * c2) % &self.1
+    }
+
+    /// The encryption of the plain text times k mod n.
+    pub fn mul(&self, c: &BigInt, k: &BigInt) -> BigInt {
+        c.modpow(&k.mod_floor(&self.0), &self.1)
+    }
+}
+
+impl SecretPaillierKey {
+    pub fn from_primes(p: BigInt, q: BigInt) -> Self {
+        let pk = PublicPaillierKey::new(&p * &q);
+        let n = pk.n();
+        let lam = (&p - 1u8).lcm(&(&q - 1u8));
+        let g: BigInt = n + 1;
+        let mu = mod_inv(&l(&g.modpow(&lam, &pk.1), n), n);
+
+        let p2 = &p * &p;
+        let q2 = &q * &q;
+        let hp = mod_inv(&l(&g.modpow(&(&p - 1), &p2), &p), &p);
+        let hq = mod_inv(&l(&g.modpow(&(&q - 1), &q2), &q), &q);
+        let qinv = mod_inv(&q, &p);
+        let crt = CrtValues {
+            p,
+            q,
+            p2,
+            q2,
+            hp,
+            hq,
+            qinv,
+        };
+        Self(lam, mu, pk, crt)
+    }
+
+    pub fn pub_key(&self) -> PublicPaillierKey {
+        self.2.clone()
+    }
+
+    fn check_range(&self, c: &BigInt) -> bool {
+        let PublicPaillierKey(n, n2) = &self.2;
+        &BigInt::from(0) < c && c < n2 && c.gcd(n).is_one()
+    }
+
+    /// Decrypts as L(c^λ mod n²) μ mod n, returns `None` if c isn't a valid cipher text.
+    pub fn dec(&self, c: &BigInt) -> Option<BigInt> {
+        let SecretPaillierKey(lam, mu, PublicPaillierKey(n, n2), _) = self;
+        if !self.check_range(c) {
+            return None;
+        }
+        Some((l(&c.modpow(lam, n2), n) * mu) % n)
+    }
+
+    /// Decrypts mod p² and q² separately and combines the results with the CRT, about four times
+    /// faster than `dec`.
+    pub fn dec_crt(&self, c: &BigInt) -> Option<BigInt> {
+        let CrtValues {
+            p,
+            q,
+            p2,
+            q2,
+            hp,
+            hq,
+            qinv,
+        } = &self.3;
+        if !self.check_range(c) {
+            return None;
+        }
+        let mp = (l(&c.modpow(&(p - 1), p2), p) * hp) % p;
+        let mq = (l(&c.modpow(&(q - 1), q2), q) * hq) % q;
+        let h = ((mp - &mq) * qinv).mod_floor(p);
+        Some(mq + h * q)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rsa::SecretRsaKey;
+
+    fn test_key() -> SecretPaillierKey {
+        let rsa = SecretRsaKey::from_pem(include_str!("../testdata/rsa-2048.pem")).unwrap();
+        let (p, q) = rsa.primes();
+        SecretPaillierKey::from_primes(p.clone(), q.clone())
+    }
+
+    #[test]
+    fn crt_decryption() {
+        let mut crng = StdRng::seed_from_u64(0);
+        let sk = test_key();
+        let pk = sk.pub_key();
+        let n2 = pk.n() * pk.n();
+        for _ in 0..10 {
+            let c = crng.gen_bigint_range(&one(), &n2);
+            assert_eq!(sk.dec_crt(&c), sk.dec(&c));
+            assert!(sk.dec(&c).is_some());
+        }
+        for m in [
+            0.into(),
+            1.into(),
+            pk.n() - 1,
+            crng.gen_bigint_range(&0.into(), pk.n()),
+        ] {
+            let c = pk.enc(&m, &mut crng).unwrap();
+            assert_eq!(sk.dec(&c), Some(m.clone()));
+            assert_eq!(sk.dec_crt(&c), Some(m));
+        }
+        assert_eq!(pk.enc(&(-1).into(), &mut crng), None);
+        assert_eq!(pk.enc(pk.n(), &mut crng), None);
+    }
+
+    #[test]
+    fn homomorphic_operations() {
+        let mut crng = StdRng::seed_from_u64(0);
+        let sk = test_key();
+        let pk = sk.pub_key();
+        let n = pk.n();
+        let m1 = crng.gen_bigint_range(&0.into(), n);
+        let m2 = n - 5;
+        let c1 = pk.enc(&m1, &mut crng).unwrap();
+        let c2 = pk.enc(&m2, &mut crng).unwrap();
+        assert_eq!(sk.dec(&pk.add(&c1, &c2)), Some((&m1 + &m2) % n));
+        for k in [0, 1, 3, -1, -3] {
+            let k = BigInt::from(k);
+            assert_eq!(sk.dec_crt(&pk.mul(&c1, &k)), Some((&m1 * &k).mod_floor(n)));
+        }
+        let huge: BigInt = n * 7 + 2;
+        assert_eq!(sk.dec(&pk.mul(&c1, &huge)), Some((&m1 * 2) % n));
+    }
+
+    #[test]
+    fn rejects_invalid_cipher_texts() {
+        let sk = test_key();
+        let pk = sk.pub_key();
+        let n2 = pk.n() * pk.n();
+        let (p, q) = (&sk.3.p, &sk.3.q);
+        for c in [
+            0.into(),
+            p.clone(),
+            q * 5,
+            pk.n().clone(),
+            n2.clone(),
+            n2 + 1,
+        ] {
+            assert_eq!(sk.dec(&c), None);
+            assert_eq!(sk.dec_crt(&c), None);
+        }
+    }
+}
@@ -0,0 +1,456 @@
+//! Primality testing: trial division, sieving, Miller-Rabin, the strong Lucas test and
+//! Baillie-PSW.
+use lazy_static::lazy_static;
+use num::{bigint::RandBigInt, one, zero, BigInt, Integer, One, Signed, ToPrimitive, Zero};
+
+/// The first 60 primes
+pub const FIRST_PRIMES: [u32; 60] = [
+    2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53, 59, 61, 67, 71, 73, 79, 83, 89, 97,
+    101, 103, 107, 109, 113, 127, 131, 137, 139, 149, 151, 157, 163, 167, 173, 179, 181, 191, 193,
+    197, 199, 211, 223, 227, 229, 233, 239, 241, 251, 257, 263, 269, 271, 277, 281,
+];
+
+/// The bound of the primes in the sieve's residue table.
+pub const SIEVE_LIMIT: u32 = 1 << 16;
+
+lazy_static! {
+    /// The odd primes below `SIEVE_LIMIT`, 6541 of them.
+    static ref SIEVE_PRIMES: Vec<u32> = small_primes(SIEVE_LIMIT).split_off(1);
+}
+
+/// Miller-Rabin witnesses that are deterministic for every n < 3.3 * 10^24, so for all 64-bit n.
+const U64_WITNESSES: [u64; 12] = [2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37];
+
+/// Quick primallity test, loads of false positives and no false negatives.
+/// Should be used before the miller rabin test for efficiancy.
+pub fn quick_prime_check(n: &BigInt) -> bool {
+    for p in FIRST_PRIMES.iter().copied() {
+        if *n == p.into() {
+            return true;
+        } else if n % p == zero() {
+            return false;
+        }
+    }
+    true
+}
+
+/// All the primes below the limit, with the sieve of Eratosthenes.
+pub fn small_primes(limit: u32) -> Vec<u32> {
+    let limit = limit as usize;
+    let mut composite = vec![false; limit];
+    let mut primes = vec![];
+    for i in 2..limit {
+        if !composite[i] {
+            primes.push(i as u32);
+            for j in (i * i..limit).step_by(i) {
+                composite[j] = true;
+            }
+        }
+    }
+    primes
+}
+
+/// An incremental sieve over the odd numbers from a starting point, or over the numbers in an
+/// odd residue class mod a power of two. It keeps the residues of the candidate mod the odd
+/// primes below `SIEVE_LIMIT`, and only yields candidates without such a factor. The small
+/// primes themselves are skipped, so it's meant for starting points above `SIEVE_LIMIT`.
+pub struct Sieve {
+    candidate: BigInt,
+    residues: Vec<u32>,
+    step: u32,
+    /// Also sieve out the candidates q for which 2q + 1 has a small factor.
+    safe: bool,
+}
+
+impl Sieve {
+    /// A sieve starting at the first odd number not smaller than `start`.
+    pub fn new(start: &BigInt) -> Self {
+        Self::new_congruent(start, 1, 2)
+    }
+
+    /// A sieve over the numbers ≡ `residue` mod `modulus`, starting at the first one not
+    /// smaller than `start`. The modulus must be a power of two and the residue odd.
+    pub fn new_congruent(start: &BigInt, residue: u32, modulus: u32) -> Self {
+        assert!(
+            modulus.is_power_of_two() && 2 <= modulus && residue.is_odd() && residue < modulus,
+            "the sieve needs an odd residue mod a power of two"
+        );
+        let candidate = start + (BigInt::from(residue) - start).mod_floor(&modulus.into());
+        let residues = SIEVE_PRIMES
+            .iter()
+            .map(|&p| (&candidate % p).to_u32().unwrap())
+            .collect();
+        Self {
+            candidate,
+            residues,
+            step: modulus,
+            safe: false,
+        }
+    }
+
+    /// A sieve for the q of safe primes 2q + 1, where neither q nor 2q + 1 have small factors.
+    pub fn new_safe(start: &BigInt) -> Self {
+        Self {
+            safe: true,
+            ..Self::new(start)
+        }
+    }
+}
+
+impl Iterator for Sieve {
+    type Item = BigInt;
+
+    fn next(&mut self) -> Option<BigInt> {
+        loop {
+            let coprime = self
+                .residues
+                .iter()
+                .zip(SIEVE_PRIMES.iter())
+                .all(|(&r, &p)| r != 0 && !(self.safe && r == p >> 1));
+            let candidate = self.candidate.clone();
+            self.candidate += self.step;
+            for (r, &p) in self.residues.iter_mut().zip(SIEVE_PRIMES.iter()) {
+                *r += self.step;
+                if p <= *r {
+                    *r %= p;
+                }
+            }
+            if coprime {
+                return Some(candidate);
+            }
+        }
+    }
+}
+
+/// The Jacobi symbol (a/n) for an odd positive n.
+pub fn jacobi(a: &BigInt, n: &BigInt) -> i8 {
+    assert!(
+        n.is_positive() && n.is_odd(),
+        "the Jacobi symbol needs an odd positive n"
+    );
+    let mut a = a.mod_floor(n);
+    let mut n = n.clone();
+    let mut t = 1;
+    while !a.is_zero() {
+        while a.is_even() {
+            a >>= 1;
+            if matches!((&n % 8u32).to_u8(), Some(3 | 5)) {
+                t = -t;
+            }
+        }
+        std::mem::swap(&mut a, &mut n);
+        if (&a % 4u32).to_u8() == Some(3) && (&n % 4u32).to_u8() == Some(3) {
+            t = -t;
+        }
+        a = a.mod_floor(&n);
+    }
+    if n.is_one() {
+        t
+    } else {
+        0
+    }
+}
+
+/// Writes n - 1 as d * 2^s with an odd d.
+fn split_pow2(n: &BigInt) -> (BigInt, u64) {
+    let n1: BigInt = n - 1;
+    let s = n1.trailing_zeros().unwrap_or(0);
+    (n1 >> s, s)
+}
+
+/// Whether the odd n > 3 is a strong probable prime to the base a.
+pub fn miller_rabin_base(n: &BigInt, a: &BigInt) -> bool {
+    let n1: BigInt = n - 1;
+    let (d, s) = split_pow2(n);
+    let mut x = a.modpow(&d, n);
+    if x.is_one() || x == n1 {
+        return true;
+    }
+    for _ in 1..s {
+        x = (&x * &x) % n;
+        if x == n1 {
+            return true;
+        } else if x.is_one() {
+            return false;
+        }
+    }
+    false
+}
+
+/// The miller rabin primallity test with `rounds` random bases.
+/// The probability that a composite passes is at most 4^-rounds.
+pub fn miller_rabin<R: RandBigInt>(n: &BigInt, rounds: usize, rng: &mut R) -> bool {
+    if *n < 2.into() {
+        false
+    } else if *n < 4.into() {
+        true
+    } else if n.is_even() {
+        false
+    } else {
+        let upper: BigInt = n - 1;
+        (0..rounds).all(|_| miller_rabin_base(n, &rng.gen_bigint_range(&2.into(), &upper)))
+    }
+}
+
+/// Deterministic primality test of a 64-bit integer.
+pub fn is_prime_u64(n: u64) -> bool {
+    if n < 2 {
+        return false;
+    }
+    for p in U64_WITNESSES {
+        if n == p {
+            return true;
+        } else if n.is_multiple_of(p) {
+            return false;
+        }
+    }
+    let mul = |a: u64, b: u64| (a as u128 * b as u128 % n as u128) as u64;
+    let pow = |mut a: u64, mut e: u64| {
+        let mut out = 1;
+        while e != 0 {
+            if e & 1 == 1 {
+                out = mul(out, a);
+            }
+            a = mul(a, a);
+            e >>= 1;
+        }
+        out
+    };
+    let s = (n - 1).trailing_zeros();
+    let d = (n - 1) >> s;
+    U64_WITNESSES.iter().all(|&a| {
+        let mut x = pow(a, d);
+        if x == 1 || x == n - 1 {
+            return true;
+        }
+        for _ in 1..s {
+            x = mul(x, x);
+            if x == n - 1 {
+                return true;
+            }
+        }
+        false
+    })
+}
+
+/// Halves x mod the odd n.
+fn half_mod(x: BigInt, n: &BigInt) -> BigInt {
+    let x = x.mod_floor(n);
+    if x.is_odd() {
+        (x + n) >> 1u8
+    } else {
+        x >> 1u8
+    }
+}
+
+/// The strong Lucas probable prime test with Selfridge's parameters: D is the first of
+/// 5, -7, 9, -11, ... with (D/n) = -1, P = 1 and Q = (1 - D) / 4.
+pub fn strong_lucas(n: &BigInt) -> bool {
+    if *n == 2.into() {
+        return true;
+    } else if *n < 2.into() || n.is_even() {
+        return false;
+    }
+    // There's no D with (D/n) = -1 for a perfect square.
+    let root = n.sqrt();
+    if &root * &root == *n {
+        return false;
+    }
+    let mut d = BigInt::from(5);
+    loop {
+        match jacobi(&d, n) {
+            -1 => break,
+            0 if d.abs() != *n => return false,
+            _ => {}
+        }
+        d = if d.is_positive() { -d - 2 } else { -d + 2 };
+    }
+    let q: BigInt = (1 - &d) / 4;
+
+    // n + 1 = k * 2^s with an odd k, computes U_k, V_k and Q^k from the top bit down.
+    let n1: BigInt = n + 1;
+    let s = n1.trailing_zeros().unwrap();
+    let k: BigInt = &n1 >> s;
+    let mut u: BigInt = one();
+    let mut v: BigInt = one();
+    let mut qk = q.mod_floor(n);
+    for i in (0..k.bits() - 1).rev() {
+        u = (&u * &v) % n;
+        v = (&v * &v - (&qk << 1u8)).mod_floor(n);
+        qk = (&qk * &qk) % n;
+        if k.bit(i) {
+            let u1 = half_mod(&u + &v, n);
+            v = half_mod(&d * &u + &v, n);
+            u = u1;
+            qk = (&qk * &q).mod_floor(n);
+        }
+    }
+    if u.is_zero() || v.is_zero() {
+        return true;
+    }
+    for _ in 1..s {
+        v = (&v * &v - (&qk << 1u8)).mod_floor(n);
+        if v.is_zero() {
+            return true;
+        }
+        qk = (&qk * &qk) % n;
+    }
+    false
+}
+
+/// The Baillie-PSW test: trial division, a Miller-Rabin test to base 2 and a strong Lucas test.
+/// No composite that passes it is known.
+pub fn baillie_psw(n: &BigInt) -> bool {
+    if *n < 2.into() {
+        false
+    } else if *n <= FIRST_PRIMES[FIRST_PRIMES.len() - 1].into() {
+        FIRST_PRIMES.iter().any(|&p| *n == p.into())
+    } else {
+        quick_prime_check(n) && miller_rabin_base(n, &2.into()) && strong_lucas(n)
+    }
+}
+
+/// Tests whether n is prime. Inputs that fit in 64 bits are tested deterministically,
+/// larger ones with Baillie-PSW followed by `rounds` random Miller-Rabin bases.
+pub fn is_probable_prime<R: RandBigInt>(n: &BigInt, rounds: usize, rng: &mut R) -> bool {
+    match n.to_u64() {
+        Some(n) => is_prime_u64(n),
+        None if n.is_negative() => false,
+        None => baillie_psw(n) && miller_rabin(n, rounds, rng),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::SmallRng, SeedableRng};
+
+    const CARMICHAEL: [u64; 34] = [
+        561, 1105, 1729, 2465, 2821, 6601, 8911, 10585, 15841, 29341, 41041, 46657, 52633, 62745,
+        63973, 75361, 101101, 115921, 126217, 162401, 172081, 188461, 252601, 278545, 294409,
+        314821, 334153, 340561, 399001, 410041, 449065, 488881, 512461, 321197185,
+    ];
+
+    /// Strong pseudoprimes to base 2, the last five also to the bases 3, 5 and 7 and the last one
+    /// to every prime base up to 23.
+    const STRONG_PSEUDOPRIMES_BASE_2: [u64; 21] = [
+        2047,
+        3277,
+        4033,
+        4681,
+        8321,
+        15841,
+        29341,
+        42799,
+        49141,
+        52633,
+        65281,
+        74665,
+        80581,
+        85489,
+        88357,
+        90751,
+        3215031751,
+        2152302898747,
+        3474749660383,
+        341550071728321,
+        3825123056546413051,
+    ];
+
+    /// The strong Lucas pseudoprimes below 60000 with Selfridge's parameters.
+    const STRONG_LUCAS_PSEUDOPRIMES: [u64; 10] = [
+        5459, 5777, 10877, 16109, 18971, 22499, 24569, 25199, 40309, 58519,
+    ];
+
+    fn trial_division(n: u64) -> bool {
+        n >= 2
+            && (2..)
+                .take_while(|p| p * p <= n)
+                .all(|p| !n.is_multiple_of(p))
+    }
+
+    #[test]
+    fn carmichael_numbers() {
+        let mut rng = SmallRng::seed_from_u64(0);
+        for n in CARMICHAEL {
+            let big = BigInt::from(n);
+            assert!(!is_prime_u64(n), "{n}");
+            assert!(!baillie_psw(&big), "{n}");
+            assert!(!miller_rabin(&big, 20, &mut rng), "{n}");
+            assert!(!is_probable_prime(&big, 20, &mut rng), "{n}");
+        }
+    }
+
+    #[test]
+    fn strong_pseudoprimes_base_2() {
+        for n in STRONG_PSEUDOPRIMES_BASE_2 {
+            let big = BigInt::from(n);
+            assert!(miller_rabin_base(&big, &2.into()), "{n}");
+            assert!(!strong_lucas(&big), "{n}");
+            assert!(!baillie_psw(&big), "{n}");
+            assert!(!is_prime_u64(n), "{n}");
+        }
+    }
+
+    #[test]
+    fn strong_lucas_pseudoprimes() {
+        for n in STRONG_LUCAS_PSEUDOPRIMES {
+            let big = BigInt::from(n);
+            assert!(strong_lucas(&big), "{n}");
+            assert!(!miller_rabin_base(&big, &2.into()), "{n}");
+            assert!(!baillie_psw(&big), "{n}");
+            assert!(!is_prime_u64(n), "{n}");
+        }
+    }
+
+    #[test]
+    fn agrees_with_trial_division() {
+        let mut rng = SmallRng::seed_from_u64(0);
+        let primes = small_primes(20000);
+        for n in 0..20000u64 {
+            let big = BigInt::from(n);
+            let expected = trial_division(n);
+            assert_eq!(is_prime_u64(n), expected, "{n}");
+            assert_eq!(baillie_psw(&big), expected, "{n}");
+            assert_eq!(miller_rabin(&big, 10, &mut rng), expected, "{n}");
+            assert_eq!(primes.binary_search(&(n as u32)).is_ok(), expected, "{n}");
+            if n % 2 == 1 && 1 < n {
+                assert_eq!(
+                    strong_lucas(&big),
+                    expected || STRONG_LUCAS_PSEUDOPRIMES.contains(&n)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn congruent_sieve() {
+        let start = (BigInt::one() << 100u8) + 12345;
+        for (residue, modulus) in [(1, 2), (3, 8), (7, 8), (5, 16)] {
+            let expected: Vec<_> = Sieve::new(&start)
+                .filter(|n| n % modulus == residue.into())
+                .take(200)
+                .collect();
+            let sieved: Vec<_> = Sieve::new_congruent(&start, residue, modulus)
+                .take(200)
+                .collect();
+            assert_eq!(sieved, expected);
+        }
+    }
+
+    #[test]
+    fn mersenne_numbers() {
+        let mut rng = SmallRng::seed_from_u64(0);
+        for (e, prime) in [
+            (61, true),
+            (67, false),
+            (89, true),
+            (127, true),
+            (521, true),
+            (523, false),
+        ] {
+            let n = (BigInt::one() << e) - 1;
+            assert_eq!(is_probable_prime(&n, 10, &mut rng), prime, "2^{e} - 1");
+        }
+    }
+}
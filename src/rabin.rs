@@ -0,0 +1,229 @@
+//! The Rabin cryptosystem and Rabin-Williams signatures with tweaks.
+//!
+//! The primes are p ≡ 3 mod 8 and q ≡ 7 mod 8, both are Blum primes. For such primes, exactly one
+//! of h, -h, 2h and -2h is a square mod n for any h coprime to n, which is what the tweaks e and f
+//! of the signatures pick.
+use crate::common::{i2osp, mod_inv, mod_sqrt, os2ip};
+use crate::primes::jacobi;
+use crate::rsa::{gen_prime_congruent, pss::mgf1, MIN_BITS};
+use digest::{Digest, FixedOutputReset};
+use num::{bigint::RandBigInt, BigInt, Integer};
+use rand::prelude::*;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+
+/// The length of the redundancy appended to encrypted messages.
+const TAG_LEN: usize = 8;
+
+/// The modulus.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct PublicRabinKey(BigInt);
+/// The public key and the primes p ≡ 3 mod 8 and q ≡ 7 mod 8.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SecretRabinKey(PublicRabinKey, BigInt, BigInt);
+
+/// A Rabin-Williams signature: e f s^2 ≡ H(m) mod n with the tweaks e = ±1 and f ∈ {1, 2}.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RwSignature {
+    pub e: i8,
+    pub f: u8,
+    pub s: BigInt,
+}
+
+pub fn gen_rabin_key<R: RandBigInt, CR: RandBigInt + CryptoRng>(
+    bits: u64,
+    rng: &mut R,
+    crng: &mut CR,
+) -> SecretRabinKey {
+    assert!(
+        MIN_BITS <= bits && bits.is_even(),
+        "Rabin keys must have an even size of at least {} bits",
+        MIN_BITS
+    );
+    let p = gen_prime_congruent(bits / 2, 3, 8, rng, crng);
+    let q = gen_prime_congruent(bits / 2, 7, 8, rng, crng);
+    SecretRabinKey(PublicRabinKey(&p * &q), p, q)
+}
+
+/// The redundancy of a message, the start of its SHA-256 hash.
+fn tag(m: &[u8]) -> [u8; TAG_LEN] {
+    Sha256::digest(m)[..TAG_LEN].try_into().unwrap()
+}
+
+/// The full domain hash of the message mod n, MGF1 output one byte shorter than n.
+fn hash<D: Digest + FixedOutputReset>(h: &mut D, m: &[u8], k: usize) -> BigInt {
+    Digest::update(h, m);
+    let m_hash = h.finalize_reset();
+    os2ip(&mgf1(h, &m_hash, k - 1))
+}
+
+impl PublicRabinKey {
+    /// The modulus.
+    pub fn n(&self) -> &BigInt {
+        &self.0
+    }
+
+    /// The size of the modulus in bytes.
+    pub fn size(&self) -> usize {
+        self.0.bits().div_ceil(8) as usize
+    }
+
+    /// The longest message `enc` accepts.
+    pub fn max_msg_len(&self) -> usize {
+        self.size() - 2 - TAG_LEN
+    }
+
+    /// Encrypts the message as x^2 mod n, where x is 0x01, the message and the first 8 bytes of
+    /// its SHA-256 hash. The redundancy picks the right one of the four square roots when
+    /// decrypting. Returns `None` if the message is longer than `max_msg_len`.
+    pub fn enc(&self, m: &[u8]) -> Option<BigInt> {
+        if self.max_msg_len() < m.len() {
+            return None;
+        }
+        let x = os2ip(&[&[1], m, &tag(m)].concat());
+        Some((&x * &x) % &self.0)
+    }
+
+    /// Verifies the signature with a single squaring.
+    pub fn verify<D: Digest + FixedOutputReset>(
+        &self,
+        h: &mut D,
+        m: &[u8],
+        sig: &RwSignature,
+    ) -> bool {
+        let n = &self.0;
+        let RwSignature { e, f, s } = sig;
+        if !matches!(e, -1 | 1) || !matches!(f, 1 | 2) || s < &BigInt::from(0) || n <= s {
+            return false;
+        }
+        let efs2 = (BigInt::from(*e) * BigInt::from(*f) * s * s).mod_floor(n);
+        efs2 == hash(h, m, self.size())
+    }
+}
+
+impl SecretRabinKey {
+    pub fn pub_key(&self) -> PublicRabinKey {
+        self.0.clone()
+    }
+
+    /// Combines a root mod p and a root mod q into a root mod n.
+    fn crt(&self, a: &BigInt, b: &BigInt) -> BigInt {
+        let SecretRabinKey(PublicRabinKey(n), p, q) = self;
+        let h = ((a - b) * mod_inv(q, p)).mod_floor(p);
+        (b + h * q).mod_floor(n)
+    }
+
+    /// Computes the four square roots of c and returns the message of the one with valid
+    /// redundancy. Returns `None` if there's none.
+    pub fn dec(&self, c: &BigInt) -> Option<Vec<u8>> {
+        let SecretRabinKey(pk, p, q) = self;
+        if c <= &BigInt::from(0) || pk.n() <= c {
+            return None;
+        }
+        let a = mod_sqrt(c, p)?;
+        let b = mod_sqrt(c, q)?;
+        let roots = [
+            self.crt(&a, &b),
+            self.crt(&a, &-&b),
+            self.crt(&-&a, &b),
+            self.crt(&-&a, &-&b),
+        ];
+        roots.iter().find_map(|x| {
+            let x = i2osp(x, pk.size() - 1)?;
+            let start = x.iter().position(|&b| b != 0)?;
+            let (m, t) = x[start..].split_at((x.len() - start).checked_sub(TAG_LEN)?);
+            (m.first() == Some(&1) && t == tag(&m[1..])).then(|| m[1..].to_vec())
+        })
+    }
+
+    /// Signs the full domain hash of the message. The tweaks make H(m) / (e f) a square, and s is
+    /// its principal square root, the one that is a square itself, so signatures are
+    /// deterministic.
+    pub fn sign<D: Digest + FixedOutputReset>(&self, h: &mut D, m: &[u8]) -> RwSignature {
+        let SecretRabinKey(pk, p, q) = self;
+        let n = pk.n();
+        let hm = hash(h, m, pk.size());
+        // -1 isn't a square mod q, and 2 is a square mod q but not mod p.
+        let e: i8 = if jacobi(&hm, q) == -1 { -1 } else { 1 };
+        let eh = (BigInt::from(e) * &hm).mod_floor(n);
+        let f: u8 = if jacobi(&eh, p) == -1 { 2 } else { 1 };
+        // h / (e f), 1 / e = e and 1 / 2 = (n + 1) / 2
+        let x = if f == 2 {
+            (eh * ((n + 1) >> 1)) % n
+        } else {
+            eh
+        };
+        // For Blum primes these roots are the squares among the roots mod p and q.
+        let a = x.modpow(&((p + 1) >> 2), p);
+        let b = x.modpow(&((q + 1) >> 2), q);
+        RwSignature {
+            e,
+            f,
+            s: self.crt(&a, &b),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    fn test_key() -> SecretRabinKey {
+        gen_rabin_key(
+            2048,
+            &mut StdRng::seed_from_u64(0),
+            &mut StdRng::seed_from_u64(1),
+        )
+    }
+
+    #[test]
+    fn encryption() {
+        let sk = test_key();
+        let pk = sk.pub_key();
+        assert_eq!(&sk.1 % 8, 3.into());
+        assert_eq!(&sk.2 % 8, 7.into());
+        for len in [0, 1, 100, pk.max_msg_len()] {
+            let m = vec![0xa5; len];
+            assert_eq!(sk.dec(&pk.enc(&m).unwrap()), Some(m));
+        }
+        // leading zero bytes of the message are kept
+        assert_eq!(sk.dec(&pk.enc(&[0, 0, 1]).unwrap()), Some(vec![0, 0, 1]));
+        assert_eq!(pk.enc(&vec![0; pk.max_msg_len() + 1]), None);
+
+        let c = pk.enc(b"message").unwrap();
+        assert_eq!(sk.dec(&((&c * 4) % pk.n())), None);
+        assert_eq!(sk.dec(&0.into()), None);
+        assert_eq!(sk.dec(pk.n()), None);
+    }
+
+    #[test]
+    fn signatures() {
+        let sk = test_key();
+        let pk = sk.pub_key();
+        let mut h = Sha256::new();
+        let mut tweaks = HashSet::new();
+        for i in 0u32..100 {
+            let m = i.to_be_bytes();
+            let sig = sk.sign(&mut h, &m);
+            assert_eq!(sk.sign(&mut h, &m), sig);
+            assert!(pk.verify(&mut h, &m, &sig));
+            assert!(!pk.verify(&mut h, b"other message", &sig));
+            tweaks.insert((sig.e, sig.f));
+            for (e, f) in [(-1, 1), (1, 1), (-1, 2), (1, 2), (0, 1), (1, 0), (1, 4)] {
+                if (e, f) != (sig.e, sig.f) {
+                    let wrong = RwSignature {
+                        e,
+                        f,
+                        ..sig.clone()
+                    };
+                    assert!(!pk.verify(&mut h, &m, &wrong));
+                }
+            }
+            if tweaks.len() == 4 {
+                break;
+            }
+        }
+        assert_eq!(tweaks.len(), 4, "not all the tweaks were seen");
+    }
+}
@@ -1,109 +1,207 @@
 use digest::{Digest, FixedOutputReset};
 // use generic_array::{arr, typenum::*};
+use crate::chacha::ChaCha20;
 use crate::common::{hash_bigint, mod_inv};
-use num::{
-    bigint::{RandBigInt, Sign},
-    one, zero, BigInt, Integer,
-};
+use crate::primes::{is_probable_prime, quick_prime_check};
+use num::{bigint::RandBigInt, one, BigInt, Integer};
 use rand::prelude::*;
 use serde::{Deserialize, Serialize};
+
+pub mod blind;
+mod encoding;
+mod kem;
+mod pkcs1v15;
+mod primegen;
+pub(crate) mod pss;
+pub use pkcs1v15::DigestInfo;
+pub use primegen::{
+    gen_prime, gen_prime_congruent, gen_provable_prime, gen_safe_prime, gen_strong_prime,
+    mr_rounds, shawe_taylor, PocklingtonStep, PrimeCertificate,
+};
 // use std::mem::replace;
 
 const BITS: u64 = 3072;
-const PRIME_BITS: u64 = BITS >> 1;
-
-/// The first 60 primes
-const FIRST_PRIMES: [u32; 60] = [
-    2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53, 59, 61, 67, 71, 73, 79, 83, 89, 97,
-    101, 103, 107, 109, 113, 127, 131, 137, 139, 149, 151, 157, 163, 167, 173, 179, 181, 191, 193,
-    197, 199, 211, 223, 227, 229, 233, 239, 241, 251, 257, 263, 269, 271, 277, 281,
-];
-
-/// The miller rabin primallity test.
-/// for k greater than or equal to 40 the test is my be considered as completely accurate.
-fn miller_rabin<R: RandBigInt>(n: &BigInt, k: usize, rng: &mut R) -> bool {
-    if *n == 2.into() || *n == 3.into() {
-        true
-    } else if n & &one() == zero() {
-        false
-    } else {
-        let mut d: BigInt = n >> 1;
-        let mut r: BigInt = one();
-        while d.is_even() {
-            d >>= 1;
-            r <<= 1;
-        }
-        'outer_loop: for _ in 0..k {
-            let a: BigInt = rng.gen_bigint_range(&2.into(), &(n - 1));
-            let mut x = a.modpow(&d, n);
-            if x == one() || x == n - 1u32 {
-                continue;
-            }
-            while r != zero() {
-                x = (&x * &x) % n;
-                if x == n - 1 {
-                    continue 'outer_loop;
-                }
-                r -= 1;
-            }
-            return false;
-        }
-        true
-    }
+/// The smallest modulus size accepted by `SecretRsaKey::validate`.
+pub(crate) const MIN_BITS: u64 = 2048;
+const PUBLIC_EXPONENT: u32 = 65537;
+
+/// The public exponent and the modulus.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct PublicRsaKey(BigInt, BigInt);
+/// The secret exponent, the public key and the factorization of the modulus.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SecretRsaKey(BigInt, PublicRsaKey, CrtValues);
+
+/// The primes of the modulus and the values for the chinese remainder theorem.
+#[derive(Clone, Serialize, Deserialize)]
+struct CrtValues {
+    p: BigInt,
+    q: BigInt,
+    /// d mod (p - 1)
+    dp: BigInt,
+    /// d mod (q - 1)
+    dq: BigInt,
+    /// q^-1 mod p
+    qinv: BigInt,
 }
 
-/// Quick primallity test, loads of false positives and no false negatives.
-/// Should be used before the miller rabin test for efficiancy.
-fn quick_prime_check(n: &BigInt) -> bool {
-    for p in FIRST_PRIMES.iter().copied() {
-        if *n == p.into() {
-            return true;
-        } else if n % p == zero() {
-            return false;
+impl CrtValues {
+    fn new(d: &BigInt, p: BigInt, q: BigInt) -> Self {
+        Self {
+            dp: d.mod_floor(&(&p - 1)),
+            dq: d.mod_floor(&(&q - 1)),
+            qinv: mod_inv(&q, &p),
+            p,
+            q,
         }
     }
-    true
 }
 
-fn is_prime<R: RandBigInt>(n: &BigInt, rng: &mut R) -> bool {
-    quick_prime_check(n) && miller_rabin(n, 40, rng)
+/// How the primes of a key are generated.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PrimeKind {
+    /// Probable primes from the sieve.
+    Probable,
+    /// Provable primes with the Shawe-Taylor method.
+    Provable,
+    /// Strong primes, where p - 1 and p + 1 have prime factors of `factor_bits` bits.
+    Strong { factor_bits: u64 },
 }
 
-fn gen_secure_prime<R: RandBigInt, CR: CryptoRng + RandBigInt>(
-    rng: &mut R,
-    crng: &mut CR,
-) -> BigInt {
-    let mut n = BigInt::from_biguint(Sign::Plus, crng.gen_biguint(PRIME_BITS));
-    while !is_prime(&n, rng) {
-        n += 1;
-    }
-    n
+/// The options of RSA key generation.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct KeyGenOptions {
+    /// The modulus size, even and at least `MIN_BITS`.
+    pub bits: u64,
+    pub primes: PrimeKind,
 }
 
-#[derive(Clone, Serialize, Deserialize)]
-pub struct PublicRsaKey(BigInt, BigInt);
-#[derive(Clone, Serialize, Deserialize)]
-pub struct SecretRsaKey(BigInt, PublicRsaKey);
+impl Default for KeyGenOptions {
+    fn default() -> Self {
+        Self {
+            bits: BITS,
+            primes: PrimeKind::Probable,
+        }
+    }
+}
 
 pub fn gen_rsa_key<R: RandBigInt, CR: RandBigInt + CryptoRng>(
     rng: &mut R,
     crng: &mut CR,
 ) -> SecretRsaKey {
-    let p = gen_secure_prime(rng, crng);
-    let q = gen_secure_prime(rng, crng);
-    let n = &p * &q;
-    let lam = (p - one::<BigInt>()).lcm(&(q - one::<BigInt>()));
-    let e = loop {
-        let out = crng.gen_bigint_range(&one(), &lam);
-        if lam.gcd(&out) == one() {
-            break out;
+    gen_rsa_key_with(&KeyGenOptions::default(), rng, crng)
+}
+
+pub fn gen_rsa_key_with<R: RandBigInt, CR: RandBigInt + CryptoRng>(
+    opts: &KeyGenOptions,
+    rng: &mut R,
+    crng: &mut CR,
+) -> SecretRsaKey {
+    check_options(opts);
+    let prime_bits = opts.bits / 2;
+    let mut gen_prime = || match opts.primes {
+        PrimeKind::Probable => gen_prime(prime_bits, rng, crng),
+        PrimeKind::Provable => gen_provable_prime(prime_bits, crng).prime(),
+        PrimeKind::Strong { factor_bits } => gen_strong_prime(prime_bits, factor_bits, rng, crng),
+    };
+    loop {
+        if let Some(key) = key_from_primes(gen_prime(), gen_prime(), opts.bits) {
+            break key;
+        }
+    }
+}
+
+/// Derives a key from the seed, the same seed and options always give the same key.
+///
+/// All the randomness comes from the ChaCha20 keystream with the seed as the key and a zero
+/// nonce, read as bytes in the order of its little endian words. The primes p and q are drawn in
+/// turn, each from fresh bytes of the stream:
+/// - `Probable`: ⌈b / 8⌉ bytes as a big endian integer reduced to b = bits / 2 bits, with the two
+///   top bits and the low bit set, is the starting point. The prime is the first odd number from
+///   it that passes Baillie-PSW, or if there's none below 2^b the search starts over.
+/// - `Strong`: r and s are drawn like probable primes of `factor_bits` bits, then p is the first
+///   number ≡ 1 mod 2r and ≡ -1 mod s from a starting point like the above that passes
+///   Baillie-PSW, starting over from a new starting point past 2^b.
+/// - `Provable`: the next 32 bytes are the seed of `shawe_taylor`, with a new seed if it fails.
+///
+/// The pair is discarded and a new pair is drawn unless n has exactly `bits` bits,
+/// gcd(e, λ(n)) = 1 with e = 65537, and |p - q| has more than b - 100 bits.
+pub fn gen_rsa_key_from_seed(seed: [u8; 32], opts: &KeyGenOptions) -> SecretRsaKey {
+    check_options(opts);
+    let mut stream = ChaCha20::from_seed(seed);
+    let prime_bits = opts.bits / 2;
+    let mut gen_prime = || match opts.primes {
+        PrimeKind::Probable => primegen::seeded_prime(prime_bits, &mut stream),
+        PrimeKind::Provable => primegen::seeded_provable_prime(prime_bits, &mut stream),
+        PrimeKind::Strong { factor_bits } => {
+            primegen::seeded_strong_prime(prime_bits, factor_bits, &mut stream)
         }
     };
+    loop {
+        if let Some(key) = key_from_primes(gen_prime(), gen_prime(), opts.bits) {
+            break key;
+        }
+    }
+}
+
+fn check_options(opts: &KeyGenOptions) {
+    assert!(
+        MIN_BITS <= opts.bits && opts.bits.is_even(),
+        "RSA keys must have an even size of at least {} bits",
+        MIN_BITS
+    );
+}
+
+/// Builds a key from the primes, unless n doesn't have `bits` bits, e isn't invertible mod λ(n)
+/// or the primes are too close.
+fn key_from_primes(p: BigInt, q: BigInt, bits: u64) -> Option<SecretRsaKey> {
+    let e: BigInt = PUBLIC_EXPONENT.into();
+    let n = &p * &q;
+    let lam = (&p - one::<BigInt>()).lcm(&(&q - one::<BigInt>()));
+    if n.bits() != bits || lam.gcd(&e) != one() || (&p - &q).bits() <= bits / 2 - 100 {
+        return None;
+    }
     let d = mod_inv(&e, &lam);
-    SecretRsaKey(e, PublicRsaKey(d, n))
+    let crt = CrtValues::new(&d, p, q);
+    Some(SecretRsaKey(d, PublicRsaKey(e, n), crt))
 }
 
 impl PublicRsaKey {
+    pub fn new(e: BigInt, n: BigInt) -> Self {
+        Self(e, n)
+    }
+
+    /// The public exponent.
+    pub fn e(&self) -> &BigInt {
+        &self.0
+    }
+
+    /// The modulus.
+    pub fn n(&self) -> &BigInt {
+        &self.1
+    }
+
+    /// The size of the modulus in bytes.
+    pub fn size(&self) -> usize {
+        self.1.bits().div_ceil(8) as usize
+    }
+
+    /// Partial public key validation (SP 800-56B 6.4.2.2): the modulus is odd and has no small
+    /// prime factors, and the exponent is odd with 2^16 < e < 2^256.
+    pub fn validate(&self) -> Result<(), RsaKeyError> {
+        let PublicRsaKey(e, n) = self;
+        if n.is_even() {
+            return Err(RsaKeyError::EvenModulus);
+        }
+        if !quick_prime_check(n) {
+            return Err(RsaKeyError::SmallPrimeFactor);
+        }
+        if e.is_even() || e.bits() <= 16 || 256 < e.bits() {
+            return Err(RsaKeyError::ExponentOutOfRange);
+        }
+        Ok(())
+    }
+
     pub fn enc(&self, m: &BigInt) -> Option<BigInt> {
         if &one::<BigInt>() < m && m < &(&self.1 - 1) {
             Some(m.modpow(&self.0, &self.1))
@@ -122,21 +220,180 @@ impl PublicRsaKey {
     }
 }
 
+/// A failed key validation check.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RsaKeyError {
+    EvenModulus,
+    SmallPrimeFactor,
+    ExponentOutOfRange,
+    ModulusSize,
+    CompositeFactor,
+    ModulusMismatch,
+    PrimesTooClose,
+    SecretExponentOutOfRange,
+    ExponentMismatch,
+    CrtMismatch,
+}
+
+impl std::fmt::Display for RsaKeyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::EvenModulus => "the RSA modulus is even",
+            Self::SmallPrimeFactor => "the RSA modulus has a small prime factor",
+            Self::ExponentOutOfRange => "the RSA public exponent isn't odd and in (2^16, 2^256)",
+            Self::ModulusSize => "the RSA modulus has a wrong size",
+            Self::CompositeFactor => "an RSA prime factor is composite",
+            Self::ModulusMismatch => "the RSA modulus isn't the product of the primes",
+            Self::PrimesTooClose => "the RSA prime factors are too close",
+            Self::SecretExponentOutOfRange => "the RSA secret exponent isn't in (2^(nlen/2), n)",
+            Self::ExponentMismatch => "the RSA exponents don't match",
+            Self::CrtMismatch => "the RSA CRT values don't match",
+        })
+    }
+}
+
+impl std::error::Error for RsaKeyError {}
+
+/// Blinding options for the private key operation.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Blinding {
+    /// Base blinding: the ciphertext is multiplied by `r^e` before the exponentiation,
+    /// and the result by `r^-1` after it, for a random `r`.
+    pub base: bool,
+    /// Exponent blinding: the secret exponent is replaced by `d + k(ed - 1)` for a random 64 bit `k`.
+    /// `ed - 1` is a multiple of λ(n) so the result doesn't change.
+    pub exponent: bool,
+}
+
+impl Blinding {
+    pub const NONE: Self = Self {
+        base: false,
+        exponent: false,
+    };
+    pub const FULL: Self = Self {
+        base: true,
+        exponent: true,
+    };
+}
+
+impl Default for Blinding {
+    fn default() -> Self {
+        Self {
+            base: true,
+            exponent: false,
+        }
+    }
+}
+
 impl SecretRsaKey {
-    pub fn dec(&self, c: &BigInt) -> Option<BigInt> {
-        if &one::<BigInt>() < c && c < &(&self.1 .1 - 1) {
-            Some(c.modpow(&self.0, &self.1 .1))
+    /// The private key operation, blinded with the default `Blinding`.
+    pub fn dec<CR: RandBigInt + CryptoRng>(&self, c: &BigInt, crng: &mut CR) -> Option<BigInt> {
+        self.dec_with(c, Blinding::default(), crng)
+    }
+
+    /// The private key operation with explicit blinding options.
+    pub fn dec_with<CR: RandBigInt + CryptoRng>(
+        &self,
+        c: &BigInt,
+        blinding: Blinding,
+        crng: &mut CR,
+    ) -> Option<BigInt> {
+        let PublicRsaKey(e, n) = &self.1;
+        if !(&one::<BigInt>() < c && c < &(n - 1)) {
+            return None;
+        }
+
+        let d = if blinding.exponent {
+            let k = BigInt::from(crng.gen_biguint(64));
+            &self.0 + k * (e * &self.0 - 1)
         } else {
-            None
+            self.0.clone()
+        };
+        if blinding.base {
+            let (r, r_inv) = loop {
+                let r = crng.gen_bigint_range(&2.into(), &(n - 1));
+                if r.gcd(n) == one() {
+                    let r_inv = mod_inv(&r, n);
+                    break (r, r_inv);
+                }
+            };
+            let c = (c * r.modpow(e, n)) % n;
+            Some((c.modpow(&d, n) * r_inv) % n)
+        } else {
+            Some(c.modpow(&d, n))
         }
     }
 
+    /// Builds a key from the modulus, the exponents and the primes, computing the CRT values.
+    pub fn from_components(n: BigInt, e: BigInt, d: BigInt, p: BigInt, q: BigInt) -> Self {
+        let crt = CrtValues::new(&d, p, q);
+        Self(d, PublicRsaKey(e, n), crt)
+    }
+
     pub fn pub_key(&self) -> PublicRsaKey {
         self.1.clone()
     }
 
-    pub fn sign<D: Digest + FixedOutputReset>(&self, h: &mut D, m: &BigInt) -> Option<BigInt> {
-        self.dec(&hash_bigint(m, h))
+    /// Key pair consistency validation (SP 800-56B 6.4.1): checks the public key, that the
+    /// modulus is a product of two distinct probable primes of half its size (and at least
+    /// `MIN_BITS` long), that e·d ≡ 1 mod λ(n) with 2^(nlen/2) < d, and the CRT values.
+    /// Keys whose d was computed mod φ(n) are accepted, so d is only bounded by n.
+    pub fn validate<R: RandBigInt>(&self, rng: &mut R) -> Result<(), RsaKeyError> {
+        let SecretRsaKey(d, PublicRsaKey(e, n), crt) = self;
+        let CrtValues { p, q, dp, dq, qinv } = crt;
+        self.1.validate()?;
+
+        let bits = n.bits();
+        if bits < MIN_BITS || bits % 2 != 0 || p.bits() != bits / 2 || q.bits() != bits / 2 {
+            return Err(RsaKeyError::ModulusSize);
+        }
+        if &(p * q) != n {
+            return Err(RsaKeyError::ModulusMismatch);
+        }
+        if (p - q).bits() <= bits / 2 - 100 {
+            return Err(RsaKeyError::PrimesTooClose);
+        }
+        let rounds = mr_rounds(bits / 2);
+        if !is_probable_prime(p, rounds, rng) || !is_probable_prime(q, rounds, rng) {
+            return Err(RsaKeyError::CompositeFactor);
+        }
+
+        let p1: BigInt = p - 1;
+        let q1: BigInt = q - 1;
+        let lam = p1.lcm(&q1);
+        if d.bits() <= bits / 2 || n <= d {
+            return Err(RsaKeyError::SecretExponentOutOfRange);
+        }
+        if (e * d).mod_floor(&lam) != one() {
+            return Err(RsaKeyError::ExponentMismatch);
+        }
+        if *dp != d.mod_floor(&p1) || *dq != d.mod_floor(&q1) || (q * qinv).mod_floor(p) != one() {
+            return Err(RsaKeyError::CrtMismatch);
+        }
+        Ok(())
+    }
+
+    /// The secret exponent.
+    pub fn d(&self) -> &BigInt {
+        &self.0
+    }
+
+    pub fn primes(&self) -> (&BigInt, &BigInt) {
+        (&self.2.p, &self.2.q)
+    }
+
+    /// q^-1 mod p
+    pub fn qinv(&self) -> &BigInt {
+        &self.2.qinv
+    }
+
+    pub fn sign<D: Digest + FixedOutputReset, CR: RandBigInt + CryptoRng>(
+        &self,
+        h: &mut D,
+        m: &BigInt,
+        crng: &mut CR,
+    ) -> Option<BigInt> {
+        self.dec(&hash_bigint(m, h), crng)
     }
 }
 // (define (sign-rsa hash m sk)
@@ -144,3 +401,75 @@ impl SecretRsaKey {
 //
 // (define (verify-rsa hash m ds pk)
 //   (= (rsa ds pk) (hash m)))
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::{hex, i2osp};
+    use rand::rngs::StdRng;
+    use sha2::Sha256;
+
+    /// The SHA-256 of the moduli derived from the seed [7; 32], from an independent
+    /// implementation of the derivation described on `gen_rsa_key_from_seed`.
+    const SEEDED_MODULI: [(PrimeKind, &str); 3] = [
+        (
+            PrimeKind::Probable,
+            "a0104bfefcc9dda803a24a1631f6ce91a1b9ee56deaac6f2c24d82cf329e4b37",
+        ),
+        (
+            PrimeKind::Strong { factor_bits: 256 },
+            "bce08fcc3dce46f15b003a8ea780ffb0b51119517bf4bcd7013aedf0fc664aea",
+        ),
+        (
+            PrimeKind::Provable,
+            "de72b9d1b95794ca4381af348d6f6762672bc311427adcb674f8b3e433948075",
+        ),
+    ];
+
+    #[test]
+    fn seeded_keys() {
+        let mut rng = StdRng::seed_from_u64(0);
+        for (primes, modulus_hash) in SEEDED_MODULI {
+            let opts = KeyGenOptions { bits: 2048, primes };
+            let key = gen_rsa_key_from_seed([7; 32], &opts);
+            let n = i2osp(&key.pub_key().1, 256).unwrap();
+            assert_eq!(
+                Sha256::digest(n).to_vec(),
+                hex(modulus_hash),
+                "{:?}",
+                primes
+            );
+            assert_eq!(key.validate(&mut rng), Ok(()));
+        }
+    }
+
+    #[test]
+    fn blinding_doesnt_change_the_result() {
+        let opts = KeyGenOptions {
+            bits: 2048,
+            ..Default::default()
+        };
+        let key = gen_rsa_key_from_seed([7; 32], &opts);
+        let mut crng = StdRng::seed_from_u64(1);
+        let n = &key.pub_key().1;
+        let c = crng.gen_bigint_range(&2.into(), &(n - 1));
+        let expected = key.dec_with(&c, Blinding::NONE, &mut crng).unwrap();
+        assert_eq!(expected.modpow(&key.pub_key().0, n), c);
+        for blinding in [
+            Blinding {
+                base: true,
+                exponent: false,
+            },
+            Blinding {
+                base: false,
+                exponent: true,
+            },
+            Blinding::FULL,
+        ] {
+            assert_eq!(
+                key.dec_with(&c, blinding, &mut crng),
+                Some(expected.clone())
+            );
+        }
+    }
+}
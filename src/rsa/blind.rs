@@ -0,0 +1,311 @@
+//! RSA blind signatures (RFC 9474), the RSABSSA-SHA384 variants.
+//!
+//! The client prepares the message with `prepare` and blinds it with `blind`, the server signs
+//! the blinded message with `blind_sign`, and the client unblinds the result with `finalize`.
+//! The final signature is an RSASSA-PSS signature of the prepared message with SHA-384.
+use super::pss::emsa_pss_encode;
+use super::{PublicRsaKey, SecretRsaKey};
+use crate::common::{i2osp, mod_inv, os2ip};
+use digest::Digest;
+use num::{bigint::RandBigInt, one, BigInt, Integer, One};
+use rand::prelude::*;
+use sha2::Sha384;
+
+/// The length of the random prefix of the randomized variants.
+const PREFIX_LEN: usize = 32;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Variant {
+    /// RSABSSA-SHA384-PSS-Randomized
+    PssRandomized,
+    /// RSABSSA-SHA384-PSSZERO-Randomized
+    PssZeroRandomized,
+    /// RSABSSA-SHA384-PSS-Deterministic
+    PssDeterministic,
+    /// RSABSSA-SHA384-PSSZERO-Deterministic
+    PssZeroDeterministic,
+}
+
+impl Variant {
+    /// The PSS salt length, the hash length or zero.
+    pub fn salt_len(self) -> usize {
+        match self {
+            Self::PssRandomized | Self::PssDeterministic => <Sha384 as Digest>::output_size(),
+            Self::PssZeroRandomized | Self::PssZeroDeterministic => 0,
+        }
+    }
+
+    pub fn is_randomized(self) -> bool {
+        matches!(self, Self::PssRandomized | Self::PssZeroRandomized)
+    }
+}
+
+/// Prepares the message to be signed: the randomized variants prefix it with 32 random bytes.
+/// The prepared message is the one that is blinded, finalized and verified.
+pub fn prepare<CR: RngCore + CryptoRng>(variant: Variant, msg: &[u8], crng: &mut CR) -> Vec<u8> {
+    if variant.is_randomized() {
+        let mut prepared = vec![0; PREFIX_LEN];
+        crng.fill_bytes(&mut prepared);
+        prepared.extend_from_slice(msg);
+        prepared
+    } else {
+        msg.to_vec()
+    }
+}
+
+/// Blinds the prepared message, returns the blinded message and the inverse of the blind.
+/// Returns `None` if the key is too small for the encoding or the encoded message isn't
+/// invertible mod n.
+pub fn blind<CR: RandBigInt + RngCore + CryptoRng>(
+    pk: &PublicRsaKey,
+    variant: Variant,
+    msg: &[u8],
+    crng: &mut CR,
+) -> Option<(Vec<u8>, BigInt)> {
+    let n = &pk.1;
+    let mut salt = vec![0; variant.salt_len()];
+    crng.fill_bytes(&mut salt);
+    let r = loop {
+        let r = crng.gen_bigint_range(&one(), n);
+        if r.gcd(n).is_one() {
+            break r;
+        }
+    };
+    blind_with(pk, variant, msg, &salt, &r)
+}
+
+/// `blind` with the PSS salt and the blind r given, as in the RFC 9474 test vectors.
+/// Returns `None` if the salt length doesn't match the variant or r isn't invertible mod n.
+pub fn blind_with(
+    pk: &PublicRsaKey,
+    variant: Variant,
+    msg: &[u8],
+    salt: &[u8],
+    r: &BigInt,
+) -> Option<(Vec<u8>, BigInt)> {
+    let PublicRsaKey(e, n) = pk;
+    if salt.len() != variant.salt_len() || !r.gcd(n).is_one() {
+        return None;
+    }
+    let mut h = Sha384::new();
+    Digest::update(&mut h, msg);
+    let m_hash = h.finalize_reset();
+    let em = emsa_pss_encode(&mut h, &m_hash, salt, n.bits() as usize - 1)?;
+    let m = os2ip(&em);
+    if !m.gcd(n).is_one() {
+        return None;
+    }
+    let z = (m * r.modpow(e, n)) % n;
+    Some((i2osp(&z, pk.size())?, mod_inv(r, n)))
+}
+
+/// Signs the blinded message with the private key, and checks the signature before returning it.
+pub fn blind_sign<CR: RandBigInt + CryptoRng>(
+    sk: &SecretRsaKey,
+    blinded_msg: &[u8],
+    crng: &mut CR,
+) -> Option<Vec<u8>> {
+    let PublicRsaKey(e, n) = &sk.1;
+    if blinded_msg.len() != sk.1.size() {
+        return None;
+    }
+    let m = os2ip(blinded_msg);
+    let s = sk.dec(&m, crng)?;
+    if s.modpow(e, n) != m {
+        return None;
+    }
+    i2osp(&s, sk.1.size())
+}
+
+/// Unblinds the blind signature and verifies it on the prepared message.
+/// Returns `None` if the result isn't a valid signature.
+pub fn finalize(
+    pk: &PublicRsaKey,
+    variant: Variant,
+    msg: &[u8],
+    blind_sig: &[u8],
+    inv: &BigInt,
+) -> Option<Vec<u8>> {
+    if blind_sig.len() != pk.size() {
+        return None;
+    }
+    let s = (os2ip(blind_sig) * inv) % &pk.1;
+    let sig = i2osp(&s, pk.size())?;
+    if verify(pk, variant, msg, &sig) {
+        Some(sig)
+    } else {
+        None
+    }
+}
+
+/// Verifies a final signature on the prepared message.
+pub fn verify(pk: &PublicRsaKey, variant: Variant, msg: &[u8], sig: &[u8]) -> bool {
+    pk.verify_pss(&mut Sha384::new(), msg, sig, variant.salt_len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::hex;
+    use crate::rsa::{gen_rsa_key_from_seed, KeyGenOptions};
+    use rand::rngs::StdRng;
+
+    const MSG: &str =
+        "8f3dc6fb8c4a02f4d6352edf0907822c1210a9b32f9bdda4c45a698c80023aa6b59f8cfec5fdbb36331372eb\
+        efedae7d";
+    const PREFIX: &str = "e7a2e8b216e5aec3facf743962d3997f2e7d70088ef257de472d6a258049832e";
+    const SALT: &str =
+        "1b142cd342d1922d01e14c612493a8f8046502f204e71bf7c152011000cb4cf5567a34484cb3a4235856b3a1\
+        6c646a76";
+    const R: &str =
+        "24bd356ad773a9057648a214d72fefd99de6f30648411bd243e08e0635cb94cc987e79eec505d2ad0f812eb6\
+        e36068667b97c13ecd605d5cd8644872ae6eeda524bd356ad773a9057648a214d72fefd99de6f30648411bd2\
+        43e08e0635cb94cc987e79eec505d2ad0f812eb6e36068667b97c13ecd605d5cd8644872ae6eeda524bd356a\
+        d773a9057648a214d72fefd99de6f30648411bd243e08e0635cb94cc987e79eec505d2ad0f812eb6e3606866\
+        7b97c13ecd605d5cd8644872ae6eeda524bd356ad773a9057648a214d72fefd99de6f30648411bd243e08e06\
+        35cb94cc987e79eec505d2ad0f812eb6e36068667b97c13ecd605d5cd8644872ae6eeda5";
+
+    /// (variant, blinded_msg, blind_sig, sig) for the key from the seed [9; 32], computed
+    /// independently of this module.
+    const VECTORS: [(Variant, &str, &str, &str); 4] = [
+        (
+            Variant::PssRandomized,
+            "bff2480ac20c111e78383922d8df467330de08613da1fffbe7b216923912f46ec1bf187b221475789d944cc8\
+            eedf2d0bcc6653aafa07f76e03fa724be33e6d6dae1b3056283311024f771eef415e2d29821e09e1268b1ab2\
+            9a5263719ee0d6b17820461418481dc3f3991afa274c6c399b67a6dda4e5df6275e51e5afa148a13b8a1e982\
+            a5720ca7ff58858e80356d4a7a211dae4405f78f4bc3cd1723c3c24842a767d744d7d87456bff9347d5b7da4\
+            583adc1e79f02e69af4094761f5bb44dc63a9914b121975025eed9d4a06bc63b440cf5dae4629bff461b4877\
+            f1bca1c818c677888c3957495b1f3bd4fb62916cddf8c4e348618071466f407f1befbd87",
+            "b578fa925c799502d78b8cf6b1b7093e0ad7147f0efea03ce64295da02fa66385e585fc0d851844e77a67db9\
+            c87c9e9dd931df98c302c6e946731eae792aba3e6db5644dd4a4f66e038cbf255b37b83cdc59a2fbd5e555f3\
+            8f6dd03d0a787142a29719f12294d410896f0f4354203a39220e6e20e73e3dbf4b97fbc8bbe3a3cae9cf6ca4\
+            6ab29c8c13b1e1807ba981ae4ff2497c0fef16d33f89cf135b564b34788e53058959abb30c0f843f0b42fc75\
+            bcbd3ad25fb1922fbd7fac64a4aad44b20fe167ac3f9ecf135d3a85cfe0f5fce7e9a1c3a11cbc07386c5917b\
+            40390eaba6ca3db64f372202db9e8a45034780f2d2e1c89446262b33dc7687e9d8903a6c",
+            "a3a719176e306396494ec0a9a21f74acfb114c9f35b954e2ea1bb6d5d1e76f1eeda8bcf0ab0c7e7aff6f3f2f\
+            e33186384c2e617be272f392bb6518254b51c22a2394a7b20c39ce84b8165f0d37be317d6c0e88b5681a1f6a\
+            608645553d3b42b66a525e2a6f9be10bb7c8652b77cdf2ffcb46eaadafc0cdd5dec7448ee27016ddc936b791\
+            ea1fdb2184a62a1ff3f754e2c3c3ffeb3dec8ebff42eacdcb042a3da32a8846f3116f5fffc524fd87403b2a2\
+            e9c2f9a83ad04190f59350fb9e02a20eb0cf025b34327154a58605303877f46004c0dc24d694d1d1a6217ef3\
+            301d94be4d51f8dbfe58e5f817cf2c642e9f338b378c6d2f16928f264b0cb132652e294c",
+        ),
+        (
+            Variant::PssZeroRandomized,
+            "c1902066cfcfdc73d68fdda8f620064c988b3061ab8425aaee95ac008e7b1037a68861fb7eafc26e68ab70b5\
+            dedfc8f5c85436d26def82f33447b3bf1b6d3916728c2cc3893edafd6e0075480efbf366c2694845f8282a00\
+            62dce5d16d9a019541d7aa2a8f821e999df0dd47338091a8a49bfffcab844a87083504c9292cae5a4cac8d4a\
+            41a98a9508f7428bab81e68d22454e9ef1ce6fc3dab40de28044e7fc3a9927a9378436fcbc93886174f332a3\
+            dfb3f3ee0f9202d863207939b41eb54eaa8e06c0126d256f75c13a46422cd8b7dc891e418b61b3fb98573aae\
+            b9a9d9f075a7db85546280ec58feb40858ea1e786fdedf4da67f0013be991131562f021c",
+            "a84e85a2e3d18e4951f7364f48d7c08df6bb80b17e971a5eda4f5de2061ce0d007e3c54cabe03876cdaa61bb\
+            19c0d6519d9611f3dcc389bd266e20b1600a80f4bd2b6b36e4ecf3a0d99ac4c3ba659b05a0cb0d8b1b368da9\
+            ef9225c22925319731b9cd38c06c78de7edd24b4084796b8c285939f6e0b137e4067c87b835473a977a4f723\
+            234856dcc13aea29b87ac650bd2e69022c180ce5b4e63a4729016b22f06bab8755053b449c830a4ade324e1a\
+            63ff6b2e228d78980a030d7fd843347f6feef8d9dcc586e15002a191956eab38b6983c3ce137c303a36f8db4\
+            e0d08f8ea9f34e6535d792543f81c0ab68d35c38d9adb0bfbd204dbaf870c93edc768971",
+            "388bfea0305a9c0baff47bc51e1a14dd03ecfca447017eef14abece6226e7561c7535ae85cd8493070026299\
+            07e76db878b21db3feef362ce4f4b96a5a3ea73ae3e00d97323d736c569ebef0e5d8fdb2003e65853bc1d518\
+            1cd3bca28024230682448560c1494e79ba544f5517fe74452b9967574ff472cd553bc33258d1247fe68ff2b0\
+            2f9875fd0a901e9a0c3c5d324d494c1a688686b5ad32ffb6f4dd45b185b2e612a553f8f3a17c47ca71aa3e32\
+            838749e8c500fc137faffc80f20b51850a959e7e2dd278c1358aabe53c7905c4289db8bc892182d745bcc19a\
+            f52511768fea01cf582608a84fc285876ace2d35d2110e6446cc92284efd4bd0cd995923",
+        ),
+        (
+            Variant::PssDeterministic,
+            "466fb02a27f62c2214de00c69e317915c9b5f9e77edfca2829248a3e73a06f480e6af434b589b32e9c59bc3c\
+            3591bcbf0d036c25f8f229db7a15fc5d5da17fc36194136cc6a3c0efe832ba48fda8a35787fbbc9ac3307d90\
+            84290fcb24a32e39d6fc1f79bc6950ac79c5be580b4b76acd9c0ba44c3bb3d99465a40a16e803e1b60175edc\
+            9ec150c9726b985df732886a83167ec447e4de5eac315beeb6798d51cfa61bae0160921c5bcf5bdf637497c1\
+            cd768acc4c55d80ffaa88aee28aecceaf28aa5f9706733cb508f59313be1407bee578ee29bc23852763a0ab2\
+            b366523a22887ef2215b20747be7b98502cc9b095ab6a3097d6b6d2df5d157af8aca91f0",
+            "770b650e1ce566ba5e8c7ddef326bac71591f8833eea28cd20fa18813c590200e7c30c85b08d90dbf4df3fe1\
+            9d8fe317510a15baced0f5e090c9d18523e4a38349412a081fb403186171cb582564e8e821a66422b1a5dc45\
+            25e06f6ef2545eb3e7b53aa804b2afb98338f4309dbec8355cc5fd405b88ee8ecacb2d2d6d0c5e7f07619dcb\
+            8a3425f989db54d9ee46444de9e2dc0e5c691a9c71c4614639ddb12c3339775441544d036638ed4f26396114\
+            04efe27ab1d6bd572881d04f81a5a242baf356313e5bcc757da6802f19e072879ea4ea9838e3f954dcc77d81\
+            0cfeff542d87b5774a66e47b2ca32591a7743979a2ef0adbaf987c4f5d2b85c8186a608b",
+            "9b1fcd2d8947a67bc7f9ea1cde9c6de016f3af1f96a25b1187e28280a6568b45edaae7e8bc4b28cc31851056\
+            92a1563c9c00c571d28ef392f39d5b1d629e99a05bdcdb0d7063dc180399b41a3065b0f7723e567a502dbf6d\
+            a49f41b8a0d2d11a9e8aebd89c9a790030c2f47e5459311858f57bad389f85e84793c5388303ffa7d6593e3f\
+            7f446d3003eb415e49a40d48406b267fbc363d3ea51273e489ffdba6482e4fb82d85c23375af3956b909f7bb\
+            bbcc484f47f8fce8a3234f50903879959753ef3cb600faa0a3e1451c12b7eab71eb0b31c218ef79df7b33573\
+            aa812c5ad70cc6026c9ccb41988f65ce4225bb1e4ae34a4c4a5ca605a271704adc8b5351",
+        ),
+        (
+            Variant::PssZeroDeterministic,
+            "65054703f293ea7b798cc38190ad408a69dcf97a8cfb265874fb4fc022c2955ec32739460d108c4d5e835308\
+            c93172fba9b4873dca37aed72e6de76509cf43e81a5f6a411948d7db0a463a1a0f4cb59a1eea07bd8baf5503\
+            7b0262864c996642dc24fdda0630c19959b42b9bbeea747107f3e254239d01c41112b34e5b915779d23709bf\
+            9fb4e022a46c2d415ea7c5b592bdd3d771f6dfc5851b0f2bac45828a82adde08c89742417fc1eb9a0271597f\
+            a62b0b3d480750a67f630c3235786a639de7b12cf0780976363dcecf6061257b7bf7d8b04c7c200cc9518e00\
+            7711bca34dae4a952fec40edfdebe16c047a73b3da93c2d2b7fd8c183cc5f585b8af55a9",
+            "5240de51d043d51707b005e8f520f9970951ea4888aca89117e42ba8f74c936e0e11371b18f7c7979ad4b945\
+            65f681a1ac6d79be9d68dd2e9d671e761b5d929b62df98826a39ee972e1f2fe9af85ef51811d6d260f2eaa58\
+            9e410843b1d5000cae8f717945670d894fd35e4fec35ff54a8ca77385b7bdb7af30f7021b5a145bdabf8e403\
+            ebec6f135800f091b2662b2929c95a5813c89bf33595102ae64bf9bdfe289f0d48d6e1ca8cb5075ef83b7423\
+            ba2079c5e30b6044fa2a569922ef2400c14c20ef953d8fe943e12c63dc89ef694baf0066f5b01c83ff14c265\
+            1cac54d183d8a156496fedf939c7b85684166a9dca6bd600b1812c4c890d85934be428f3",
+            "b7ba2f33b803e1632ef241467155ba37eae6db2c101aead988a73cd0e841d79206122d52a9bf49d9b85af60b\
+            7b601cd3df05085169cd0cb900352e9e7e6ea98a007f79b1e57c018b62e24d3efbc6dc5605c94770c2ecb091\
+            6332da36b39f6a1e3a5ca2a331fdc4c7dbb0b10cd09de105fdc3ff925b98d1ecc529ab8a4b93a41155dff230\
+            bad4622a92a4d483ebb60076ccc6dcf45d3ad2203a3f80c3ba6687432e2ac879033dee0f86a37112083c4da2\
+            97beadcb2e372e3c0edc0b86c39e0792850f252ca31ef92c8768d156844946cf1dac957f7b8a971400e33a68\
+            37f54355a858ca14d8fc48473140a20b1fd39b2ffc649a6f860a61b200f1e98341298520",
+        ),
+    ];
+
+    fn key() -> SecretRsaKey {
+        let opts = KeyGenOptions {
+            bits: 2048,
+            ..Default::default()
+        };
+        gen_rsa_key_from_seed([9; 32], &opts)
+    }
+
+    #[test]
+    fn known_answers() {
+        let sk = key();
+        let pk = sk.pub_key();
+        let mut crng = StdRng::seed_from_u64(0);
+        let r = BigInt::parse_bytes(R.as_bytes(), 16).unwrap();
+        for (variant, blinded_msg, blind_sig, sig) in VECTORS {
+            let mut msg = if variant.is_randomized() {
+                hex(PREFIX)
+            } else {
+                vec![]
+            };
+            msg.extend_from_slice(&hex(MSG));
+            let salt = &hex(SALT)[..variant.salt_len()];
+
+            let (blinded, inv) = blind_with(&pk, variant, &msg, salt, &r).unwrap();
+            assert_eq!(blinded, hex(blinded_msg), "{variant:?}");
+            let signed = blind_sign(&sk, &blinded, &mut crng).unwrap();
+            assert_eq!(signed, hex(blind_sig), "{variant:?}");
+            let final_sig = finalize(&pk, variant, &msg, &signed, &inv).unwrap();
+            assert_eq!(final_sig, hex(sig), "{variant:?}");
+            assert!(verify(&pk, variant, &msg, &final_sig));
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let sk = key();
+        let pk = sk.pub_key();
+        let mut crng = StdRng::seed_from_u64(1);
+        for (variant, ..) in VECTORS {
+            let msg = prepare(variant, b"blind me", &mut crng);
+            assert_eq!(
+                msg.len(),
+                b"blind me".len() + 32 * variant.is_randomized() as usize
+            );
+            let (blinded, inv) = blind(&pk, variant, &msg, &mut crng).unwrap();
+            let signed = blind_sign(&sk, &blinded, &mut crng).unwrap();
+            let sig = finalize(&pk, variant, &msg, &signed, &inv).unwrap();
+            assert!(verify(&pk, variant, &msg, &sig));
+            assert!(!verify(&pk, variant, b"other", &sig));
+            let mut bad = signed.clone();
+            bad[10] ^= 1;
+            assert_eq!(finalize(&pk, variant, &msg, &bad, &inv), None);
+        }
+    }
+}
@@ -0,0 +1,256 @@
+use super::{CrtValues, PublicRsaKey, SecretRsaKey};
+use crate::der::{pem_decode, pem_encode, DerError, DerReader, DerWriter, TAG_NULL};
+
+/// rsaEncryption (1.2.840.113549.1.1.1)
+const RSA_ENCRYPTION_OID: [u64; 7] = [1, 2, 840, 113549, 1, 1, 1];
+
+const PKCS1_PUBLIC_LABEL: &str = "RSA PUBLIC KEY";
+const PKCS1_SECRET_LABEL: &str = "RSA PRIVATE KEY";
+const SPKI_LABEL: &str = "PUBLIC KEY";
+const PKCS8_LABEL: &str = "PRIVATE KEY";
+
+fn write_algorithm_identifier(w: &mut DerWriter) {
+    w.sequence(|w| {
+        w.oid(&RSA_ENCRYPTION_OID).null();
+    });
+}
+
+fn read_algorithm_identifier(r: &mut DerReader) -> Result<(), DerError> {
+    let mut alg = r.sequence()?;
+    if alg.oid()? != RSA_ENCRYPTION_OID {
+        return Err(DerError::UnknownAlgorithm);
+    }
+    // The parameters must be NULL, but some encoders omit them.
+    if alg.peek_tag() == Some(TAG_NULL) {
+        alg.null()?;
+    }
+    alg.finish()
+}
+
+impl PublicRsaKey {
+    /// PKCS#1 `RSAPublicKey` DER encoding.
+    pub fn to_pkcs1_der(&self) -> Vec<u8> {
+        let mut w = DerWriter::new();
+        w.sequence(|w| {
+            w.integer(&self.1).integer(&self.0);
+        });
+        w.finish()
+    }
+
+    pub fn from_pkcs1_der(der: &[u8]) -> Result<Self, DerError> {
+        let mut r = DerReader::new(der);
+        let mut seq = r.sequence()?;
+        let n = seq.unsigned_integer()?;
+        let e = seq.unsigned_integer()?;
+        seq.finish()?;
+        r.finish()?;
+        Ok(Self(e, n))
+    }
+
+    /// X.509 `SubjectPublicKeyInfo` DER encoding.
+    pub fn to_spki_der(&self) -> Vec<u8> {
+        let mut w = DerWriter::new();
+        w.sequence(|w| {
+            write_algorithm_identifier(w);
+            w.bit_string(&self.to_pkcs1_der());
+        });
+        w.finish()
+    }
+
+    pub fn from_spki_der(der: &[u8]) -> Result<Self, DerError> {
+        let mut r = DerReader::new(der);
+        let mut seq = r.sequence()?;
+        read_algorithm_identifier(&mut seq)?;
+        let key = Self::from_pkcs1_der(seq.bit_string()?)?;
+        seq.finish()?;
+        r.finish()?;
+        Ok(key)
+    }
+
+    pub fn to_pkcs1_pem(&self) -> String {
+        pem_encode(PKCS1_PUBLIC_LABEL, &self.to_pkcs1_der())
+    }
+
+    pub fn to_spki_pem(&self) -> String {
+        pem_encode(SPKI_LABEL, &self.to_spki_der())
+    }
+
+    /// Decodes a PEM encoded PKCS#1 or `SubjectPublicKeyInfo` public key.
+    pub fn from_pem(pem: &str) -> Result<Self, DerError> {
+        match pem_decode(pem)? {
+            (label, der) if label == PKCS1_PUBLIC_LABEL => Self::from_pkcs1_der(&der),
+            (label, der) if label == SPKI_LABEL => Self::from_spki_der(&der),
+            (label, _) => Err(DerError::UnexpectedLabel(label)),
+        }
+    }
+}
+
+impl SecretRsaKey {
+    /// PKCS#1 `RSAPrivateKey` DER encoding.
+    pub fn to_pkcs1_der(&self) -> Vec<u8> {
+        let SecretRsaKey(d, PublicRsaKey(e, n), crt) = self;
+        let mut w = DerWriter::new();
+        w.sequence(|w| {
+            w.small_integer(0)
+                .integer(n)
+                .integer(e)
+                .integer(d)
+                .integer(&crt.p)
+                .integer(&crt.q)
+                .integer(&crt.dp)
+                .integer(&crt.dq)
+                .integer(&crt.qinv);
+        });
+        w.finish()
+    }
+
+    /// Decodes a PKCS#1 `RSAPrivateKey`, multi-prime keys aren't supported.
+    pub fn from_pkcs1_der(der: &[u8]) -> Result<Self, DerError> {
+        let mut r = DerReader::new(der);
+        let mut seq = r.sequence()?;
+        if seq.integer()? != 0.into() {
+            return Err(DerError::UnsupportedVersion);
+        }
+        let n = seq.unsigned_integer()?;
+        let e = seq.unsigned_integer()?;
+        let d = seq.unsigned_integer()?;
+        let crt = CrtValues {
+            p: seq.unsigned_integer()?,
+            q: seq.unsigned_integer()?,
+            dp: seq.unsigned_integer()?,
+            dq: seq.unsigned_integer()?,
+            qinv: seq.unsigned_integer()?,
+        };
+        seq.finish()?;
+        r.finish()?;
+        Ok(Self(d, PublicRsaKey(e, n), crt))
+    }
+
+    /// PKCS#8 `PrivateKeyInfo` DER encoding.
+    pub fn to_pkcs8_der(&self) -> Vec<u8> {
+        let mut w = DerWriter::new();
+        w.sequence(|w| {
+            w.small_integer(0);
+            write_algorithm_identifier(w);
+            w.octet_string(&self.to_pkcs1_der());
+        });
+        w.finish()
+    }
+
+    /// Decodes a PKCS#8 `PrivateKeyInfo` (or v2 `OneAsymmetricKey`), the optional attributes
+    /// and public key are ignored.
+    pub fn from_pkcs8_der(der: &[u8]) -> Result<Self, DerError> {
+        let mut r = DerReader::new(der);
+        let mut seq = r.sequence()?;
+        let version = seq.integer()?;
+        if version != 0.into() && version != 1.into() {
+            return Err(DerError::UnsupportedVersion);
+        }
+        read_algorithm_identifier(&mut seq)?;
+        let key = Self::from_pkcs1_der(seq.octet_string()?)?;
+        r.finish()?;
+        Ok(key)
+    }
+
+    pub fn to_pkcs1_pem(&self) -> String {
+        pem_encode(PKCS1_SECRET_LABEL, &self.to_pkcs1_der())
+    }
+
+    pub fn to_pkcs8_pem(&self) -> String {
+        pem_encode(PKCS8_LABEL, &self.to_pkcs8_der())
+    }
+
+    /// Decodes a PEM encoded PKCS#1 or PKCS#8 secret key.
+    pub fn from_pem(pem: &str) -> Result<Self, DerError> {
+        match pem_decode(pem)? {
+            (label, der) if label == PKCS1_SECRET_LABEL => Self::from_pkcs1_der(&der),
+            (label, der) if label == PKCS8_LABEL => Self::from_pkcs8_der(&der),
+            (label, _) => Err(DerError::UnexpectedLabel(label)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    /// The same 2048-bit key in the formats written by OpenSSL 3.5.
+    const PKCS8: &str = include_str!("../../testdata/rsa-2048.pem");
+    const PKCS1: &str = include_str!("../../testdata/rsa-2048-pkcs1.pem");
+    const SPKI: &str = include_str!("../../testdata/rsa-2048-pub.pem");
+    const PKCS1_PUBLIC: &str = include_str!("../../testdata/rsa-2048-pkcs1-pub.pem");
+
+    #[test]
+    fn openssl_secret_key() {
+        for pem in [PKCS8, PKCS1] {
+            let key = SecretRsaKey::from_pem(pem).unwrap();
+            key.validate(&mut StdRng::seed_from_u64(0)).unwrap();
+            assert_eq!(key.to_pkcs8_pem(), PKCS8);
+            assert_eq!(key.to_pkcs1_pem(), PKCS1);
+            assert_eq!(key.pub_key().to_spki_pem(), SPKI);
+            assert_eq!(key.pub_key().to_pkcs1_pem(), PKCS1_PUBLIC);
+        }
+    }
+
+    #[test]
+    fn openssl_public_key() {
+        for pem in [SPKI, PKCS1_PUBLIC] {
+            let key = PublicRsaKey::from_pem(pem).unwrap();
+            key.validate().unwrap();
+            assert_eq!(key.to_spki_pem(), SPKI);
+            assert_eq!(key.to_pkcs1_pem(), PKCS1_PUBLIC);
+        }
+    }
+
+    #[test]
+    fn der_round_trips() {
+        let key = SecretRsaKey::from_pem(PKCS8).unwrap();
+        let der = key.to_pkcs1_der();
+        let decoded = SecretRsaKey::from_pkcs1_der(&der).unwrap();
+        assert_eq!(decoded.to_pkcs1_der(), der);
+        let decoded = SecretRsaKey::from_pkcs8_der(&key.to_pkcs8_der()).unwrap();
+        assert_eq!(decoded.to_pkcs1_der(), der);
+
+        let pk = key.pub_key();
+        let der = pk.to_pkcs1_der();
+        let decoded = PublicRsaKey::from_pkcs1_der(&der).unwrap();
+        assert_eq!(decoded.to_pkcs1_der(), der);
+        let decoded = PublicRsaKey::from_spki_der(&pk.to_spki_der()).unwrap();
+        assert_eq!(decoded.to_pkcs1_der(), der);
+    }
+
+    #[test]
+    fn rejects_malformed_keys() {
+        assert_eq!(
+            SecretRsaKey::from_pem(SPKI).err(),
+            Some(DerError::UnexpectedLabel("PUBLIC KEY".into()))
+        );
+        assert_eq!(
+            PublicRsaKey::from_pem(PKCS8).err(),
+            Some(DerError::UnexpectedLabel("PRIVATE KEY".into()))
+        );
+
+        let der = pem_decode(PKCS1).unwrap().1;
+        let mut trailing = der.clone();
+        trailing.push(0);
+        assert_eq!(
+            SecretRsaKey::from_pkcs1_der(&trailing).err(),
+            Some(DerError::TrailingData)
+        );
+        assert_eq!(
+            SecretRsaKey::from_pkcs1_der(&der[..der.len() - 1]).err(),
+            Some(DerError::UnexpectedEnd)
+        );
+        // The version follows the 4 byte sequence header, 1 is for multi-prime keys.
+        let mut multi_prime = der.clone();
+        multi_prime[6] = 1;
+        assert_eq!(
+            SecretRsaKey::from_pkcs1_der(&multi_prime).err(),
+            Some(DerError::UnsupportedVersion)
+        );
+        let public = pem_decode(PKCS1_PUBLIC).unwrap().1;
+        assert!(SecretRsaKey::from_pkcs1_der(&public).is_err());
+        assert!(PublicRsaKey::from_spki_der(&public).is_err());
+    }
+}
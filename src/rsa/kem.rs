@@ -0,0 +1,97 @@
+//! Hybrid encryption: RSA-KEM (RFC 5990) with KDF2 over SHA-256, and ChaCha20-Poly1305 for the
+//! message.
+use super::{PublicRsaKey, SecretRsaKey};
+use crate::chacha::{chacha20_poly1305_open, chacha20_poly1305_seal};
+use crate::common::{i2osp, os2ip};
+use digest::Digest;
+use num::bigint::RandBigInt;
+use rand::prelude::*;
+use sha2::Sha256;
+
+/// The ChaCha20-Poly1305 key length.
+const KEY_LEN: usize = 32;
+/// Every key is derived from a fresh secret and used once, so the nonce is fixed.
+const NONCE: [u8; 12] = [0; 12];
+
+/// KDF2 (ISO 18033-2) with SHA-256 and no other info. The key is as long as one hash, so it's
+/// just the hash of z and the counter 1.
+fn kdf2(z: &[u8]) -> [u8; KEY_LEN] {
+    Sha256::new()
+        .chain_update(z)
+        .chain_update(1u32.to_be_bytes())
+        .finalize()
+        .into()
+}
+
+impl PublicRsaKey {
+    /// Encrypts a message of any length. A random z in (1, n - 1) is encapsulated as z^e mod n,
+    /// and the message is encrypted with ChaCha20-Poly1305 under KDF2(z). The output is the
+    /// encapsulation in `size()` bytes followed by the cipher text and the tag.
+    pub fn seal<CR: RandBigInt + CryptoRng>(&self, m: &[u8], crng: &mut CR) -> Vec<u8> {
+        let k = self.size();
+        let z = crng.gen_bigint_range(&2.into(), &(&self.1 - 1));
+        let c = self.enc(&z).unwrap();
+        let key = kdf2(&i2osp(&z, k).unwrap());
+        let mut out = i2osp(&c, k).unwrap();
+        out.extend(chacha20_poly1305_seal(&key, &NONCE, &[], m));
+        out
+    }
+}
+
+impl SecretRsaKey {
+    /// Decrypts the output of `PublicRsaKey::seal`.
+    /// Returns `None` if the encapsulation is invalid or the tag doesn't match.
+    pub fn open<CR: RandBigInt + CryptoRng>(&self, c: &[u8], crng: &mut CR) -> Option<Vec<u8>> {
+        let k = self.1.size();
+        if c.len() < k {
+            return None;
+        }
+        let (encapsulation, c) = c.split_at(k);
+        let z = self.dec(&os2ip(encapsulation), crng)?;
+        let key = kdf2(&i2osp(&z, k)?);
+        chacha20_poly1305_open(&key, &NONCE, &[], c)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn seal_open() {
+        let mut crng = StdRng::seed_from_u64(0);
+        let sk = SecretRsaKey::from_pem(include_str!("../../testdata/rsa-2048.pem")).unwrap();
+        let pk = sk.pub_key();
+        for len in [0, 1, 64, 1000] {
+            let m: Vec<u8> = (0..len).map(|i| i as u8).collect();
+            let c = pk.seal(&m, &mut crng);
+            assert_eq!(c.len(), pk.size() + len + 16);
+            assert_eq!(sk.open(&c, &mut crng), Some(m.clone()));
+            // a fresh secret every time
+            assert_ne!(pk.seal(&m, &mut crng), c);
+        }
+    }
+
+    #[test]
+    fn rejects_flipped_bits() {
+        let mut crng = StdRng::seed_from_u64(0);
+        let sk = SecretRsaKey::from_pem(include_str!("../../testdata/rsa-2048.pem")).unwrap();
+        let pk = sk.pub_key();
+        let c = pk.seal(b"attack at dawn", &mut crng);
+        // the encapsulation, the cipher text and the tag
+        for i in [
+            0,
+            100,
+            pk.size() - 1,
+            pk.size(),
+            pk.size() + 13,
+            c.len() - 1,
+        ] {
+            let mut tampered = c.clone();
+            tampered[i] ^= 1;
+            assert_eq!(sk.open(&tampered, &mut crng), None, "{}", i);
+        }
+        assert_eq!(sk.open(&c[..c.len() - 1], &mut crng), None);
+        assert_eq!(sk.open(&c[..pk.size() - 1], &mut crng), None);
+    }
+}
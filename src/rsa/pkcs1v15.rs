@@ -0,0 +1,376 @@
+use super::{PublicRsaKey, SecretRsaKey};
+use crate::common::{i2osp, os2ip};
+use digest::{Digest, FixedOutputReset};
+use hmac::{Hmac, Mac};
+use num::bigint::RandBigInt;
+use rand::prelude::*;
+use sha2::Sha256;
+
+/// Hash functions that can be used with EMSA-PKCS1-v1_5.
+/// `PREFIX` is the DER encoding of the `DigestInfo` up to (excluding) the digest itself.
+pub trait DigestInfo {
+    const PREFIX: &'static [u8];
+}
+
+macro_rules! impl_digest_info {
+    ($hash:ty, [$($byte:expr),* $(,)?] $(,)?) => {
+        impl DigestInfo for $hash {
+            const PREFIX: &'static [u8] = &[$($byte),*];
+        }
+    };
+}
+
+#[rustfmt::skip]
+impl_digest_info!(sha1::Sha1, [
+    0x30, 0x21, 0x30, 0x09, 0x06, 0x05, 0x2b, 0x0e, 0x03, 0x02, 0x1a, 0x05, 0x00, 0x04, 0x14,
+]);
+#[rustfmt::skip]
+impl_digest_info!(sha2::Sha224, [
+    0x30, 0x2d, 0x30, 0x0d, 0x06, 0x09, 0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02, 0x04,
+    0x05, 0x00, 0x04, 0x1c,
+]);
+#[rustfmt::skip]
+impl_digest_info!(sha2::Sha256, [
+    0x30, 0x31, 0x30, 0x0d, 0x06, 0x09, 0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02, 0x01,
+    0x05, 0x00, 0x04, 0x20,
+]);
+#[rustfmt::skip]
+impl_digest_info!(sha2::Sha384, [
+    0x30, 0x41, 0x30, 0x0d, 0x06, 0x09, 0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02, 0x02,
+    0x05, 0x00, 0x04, 0x30,
+]);
+#[rustfmt::skip]
+impl_digest_info!(sha2::Sha512, [
+    0x30, 0x51, 0x30, 0x0d, 0x06, 0x09, 0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02, 0x03,
+    0x05, 0x00, 0x04, 0x40,
+]);
+#[rustfmt::skip]
+impl_digest_info!(sha2::Sha512_224, [
+    0x30, 0x2d, 0x30, 0x0d, 0x06, 0x09, 0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02, 0x05,
+    0x05, 0x00, 0x04, 0x1c,
+]);
+#[rustfmt::skip]
+impl_digest_info!(sha2::Sha512_256, [
+    0x30, 0x31, 0x30, 0x0d, 0x06, 0x09, 0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02, 0x06,
+    0x05, 0x00, 0x04, 0x20,
+]);
+
+/// EMSA-PKCS1-v1_5 encoding (RFC 8017 9.2) of the message into `k` bytes.
+fn emsa_encode<D: Digest + FixedOutputReset + DigestInfo>(
+    h: &mut D,
+    m: &[u8],
+    k: usize,
+) -> Option<Vec<u8>> {
+    Digest::update(h, m);
+    let hash = h.finalize_reset();
+    let t_len = D::PREFIX.len() + hash.len();
+    if k < t_len + 11 {
+        return None;
+    }
+    let mut em = vec![0xff; k];
+    em[0] = 0x00;
+    em[1] = 0x01;
+    em[k - t_len - 1] = 0x00;
+    em[k - t_len..k - hash.len()].copy_from_slice(D::PREFIX);
+    em[k - hash.len()..].copy_from_slice(&hash);
+    Some(em)
+}
+
+/// The maximal number of tries to pick a synthetic message length.
+const MAX_LEN_GEN_TRIES: usize = 128;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// The PRF used to generate the synthetic messages of the implicit rejection,
+/// returns `bits / 8` bytes derived from `key` and `label`.
+fn implicit_rejection_prf(key: &[u8], label: &[u8], bits: usize) -> Vec<u8> {
+    let len = bits / 8;
+    let mut out = Vec::with_capacity(len + 32);
+    let mut i: u16 = 0;
+    while out.len() < len {
+        let mut mac = HmacSha256::new_from_slice(key).unwrap();
+        mac.update(&i.to_be_bytes());
+        mac.update(label);
+        mac.update(&(bits as u16).to_be_bytes());
+        out.extend_from_slice(&mac.finalize().into_bytes());
+        i += 1;
+    }
+    out.truncate(len);
+    out
+}
+
+/// Returns `0xff` when `x == 0` and `0x00` otherwise.
+#[inline]
+fn ct_is_zero(x: u8) -> u8 {
+    ((x as u16).wrapping_sub(1) >> 8) as u8
+}
+
+/// Returns `usize::MAX` when `x < y` and `0` otherwise, for `x, y < 2^(usize::BITS - 1)`.
+#[inline]
+fn ct_lt(x: usize, y: usize) -> usize {
+    (x.wrapping_sub(y) >> (usize::BITS - 1)).wrapping_neg()
+}
+
+impl PublicRsaKey {
+    /// RSASSA-PKCS1-v1_5 signature verification (RFC 8017 8.2.2).
+    pub fn verify_pkcs1v15<D: Digest + FixedOutputReset + DigestInfo>(
+        &self,
+        h: &mut D,
+        m: &[u8],
+        ds: &[u8],
+    ) -> bool {
+        let k = self.size();
+        if ds.len() != k {
+            return false;
+        }
+        let Some(em) = emsa_encode(h, m, k) else {
+            return false;
+        };
+        let s = os2ip(ds);
+        if self.1 <= s {
+            return false;
+        }
+        match self.enc(&s).and_then(|m| i2osp(&m, k)) {
+            Some(em2) => em == em2,
+            None => false,
+        }
+    }
+
+    /// RSAES-PKCS1-v1_5 encryption (RFC 8017 7.2.1).
+    /// Returns `None` if the message is longer than `k - 11` bytes.
+    pub fn enc_pkcs1v15<CR: Rng + CryptoRng>(&self, m: &[u8], crng: &mut CR) -> Option<Vec<u8>> {
+        let k = self.size();
+        if k < m.len() + 11 {
+            return None;
+        }
+        let mut em = vec![0; k];
+        em[1] = 0x02;
+        for b in &mut em[2..k - m.len() - 1] {
+            *b = crng.gen_range(1..=0xff);
+        }
+        em[k - m.len()..].copy_from_slice(m);
+        i2osp(&self.enc(&os2ip(&em))?, k)
+    }
+}
+
+impl SecretRsaKey {
+    /// RSASSA-PKCS1-v1_5 signature generation (RFC 8017 8.2.1).
+    pub fn sign_pkcs1v15<D: Digest + FixedOutputReset + DigestInfo, CR: RandBigInt + CryptoRng>(
+        &self,
+        h: &mut D,
+        m: &[u8],
+        crng: &mut CR,
+    ) -> Option<Vec<u8>> {
+        let k = self.1.size();
+        let em = emsa_encode(h, m, k)?;
+        i2osp(&self.dec(&os2ip(&em), crng)?, k)
+    }
+
+    /// RSAES-PKCS1-v1_5 decryption (RFC 8017 7.2.2) with implicit rejection.
+    ///
+    /// A ciphertext with an invalid padding doesn't produce an error, instead a synthetic message
+    /// is deterministically derived from the secret key and the ciphertext, so that an attacker
+    /// can't use the decryption as a padding oracle (Bleichenbacher's attack).
+    /// Returns `None` only when the ciphertext itself is malformed (wrong length or out of range).
+    pub fn dec_pkcs1v15<CR: RandBigInt + CryptoRng>(
+        &self,
+        c: &[u8],
+        crng: &mut CR,
+    ) -> Option<Vec<u8>> {
+        let k = self.1.size();
+        if c.len() != k || k < 11 {
+            return None;
+        }
+        let em = i2osp(&self.dec(&os2ip(c), crng)?, k)?;
+
+        // Key derivation key for the synthetic message.
+        let kdk = {
+            let dh = Sha256::digest(i2osp(&self.0, k)?);
+            let mut mac = HmacSha256::new_from_slice(&dh).unwrap();
+            mac.update(c);
+            mac.finalize().into_bytes()
+        };
+        let candidate_lengths = implicit_rejection_prf(&kdk, b"length", MAX_LEN_GEN_TRIES * 16);
+        let synthetic = implicit_rejection_prf(&kdk, b"message", k * 8);
+
+        let max_sep_offset = k - 2 - 8;
+        let len_mask = usize::MAX >> max_sep_offset.leading_zeros();
+        let mut synthetic_length = 0;
+        for pair in candidate_lengths.chunks_exact(2) {
+            let candidate = ((pair[0] as usize) << 8) | pair[1] as usize;
+            let candidate = candidate & len_mask;
+            let mask = ct_lt(candidate, max_sep_offset);
+            synthetic_length = (candidate & mask) | (synthetic_length & !mask);
+        }
+        let synthetic_index = k - synthetic_length;
+
+        // Check the padding in constant time: 0x00 || 0x02 || PS || 0x00 || M
+        let mut good = ct_is_zero(em[0]) & ct_is_zero(em[1] ^ 0x02);
+        let mut found_zero = 0u8;
+        let mut zero_index = 0usize;
+        for (i, &b) in em.iter().enumerate().skip(2) {
+            let is_zero = ct_is_zero(b);
+            let first = !found_zero & is_zero;
+            zero_index |= i & (first as i8 as isize as usize);
+            found_zero |= is_zero;
+        }
+        good &= found_zero;
+        // PS must be at least 8 bytes long.
+        good &= !(ct_lt(zero_index, 10) as u8);
+        let good = good as i8 as isize as usize;
+        let msg_index = ((zero_index + 1) & good) | (synthetic_index & !good);
+
+        Some(
+            em.iter()
+                .zip(&synthetic)
+                .skip(msg_index)
+                .map(|(&e, &s)| (e & good as u8) | (s & !good as u8))
+                .collect(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::hex;
+    use rand::rngs::StdRng;
+    use sha1::Sha1;
+    use sha2::Sha512;
+
+    /// A 2048-bit key generated by OpenSSL. The expected signatures and synthetic messages were
+    /// computed with OpenSSL 3.5, which implements the implicit rejection of Kario's draft.
+    const KEY: &str = include_str!("../../testdata/rsa-2048.pem");
+    const MSG: &[u8] = b"Hello, PKCS#1 v1.5!";
+
+    /// The signatures of MSG with SHA-1, SHA-256 and SHA-512.
+    const SIG_SHA1: &str = "7f79907f8bd752b5e3398e9cb83dd3b214c7cafb37daf0562f64e661dabf4a3f\
+        2ea762ded253a073ab626a8caf25bc77ad2a24d7cb5d52571dde08a2b961f245\
+        7bdcee8c44c6bc714195b35872ec3040600b801bf1d80d0b1d0418e8f1213203\
+        c6647c7648c2e68e2e4d82d5878491ddc8f906cbfe29231a5d3badb3b871fcc3\
+        6db4f791997d390598e20c5bf99b09f0c8dd2e14ddacffa683f395d94f519b3e\
+        0ef9a4cb25f86d28cfc2676eb5734122a161964eb43ca2533c1fa32b73da3193\
+        419362c3b9c16fc96312ab6f9c9db135a0186e4d84e36396e9d52eef4b15f74b\
+        1d8f5bb63deff9b2d3fa7a9fb170b5626410f9492585dc6638e460b65e2685b4";
+    const SIG_SHA256: &str = "a025f871967ab640d1f14e0fb682cacfe107d7ff056cfb3647a0207567808c33\
+        6deba901a8fc6a9c468cffcc1e3797977e72a9ac897f9155eb0a1493d7dc9c8a\
+        edc4bfccc3e92cb315d14a173f69d6d94fe7d28e18db4a2618995583905414d7\
+        55b46b4cdc81d9122dfdaf88d994d87f0ed2a8782b780bb6d097d098d9b605cb\
+        52c543bea23d74a1b956a0c1698e37a3ff5f0f11259d172bee40910c28c4fdfd\
+        1a873c904e1f5785aa07a1c56510cb8211c73432035346777c41354c050bfddd\
+        a98f8e8846175547bdbb993c8e2098486f49c91944834e38152a7eaca6f30ab5\
+        31316bd8551054369cfd3493df3bb3245c7ee9899afb8a242f109e9dd3776c6b";
+    const SIG_SHA512: &str = "0f092f4ff6f1a28bb7f0b212d07e1efd4e9470c94e97f02e769ce219facbf944\
+        73b236e69978d98ba4be67798f3736378b381455f03ca029524e262d4ca809ab\
+        c5b8e677447f0ff7d34aef1b1f2040b5682e11576aa1d17f84b73a36d631b5f8\
+        5190c3c16cd9b08f88d216f78882de533a231e829240473dc0ef2fa74d70da38\
+        ea3dbd5f6b50b5757b666611005a310b41aa6dab7dbe4aa84171727a51b35597\
+        0c3015d661f56ae223bf72abb12ddea43ccfd5589898960a9709fb6c412032af\
+        9d9e9413359d308be3ac95939fd987fa67955ca3d8da546cb984c33a359a3be0\
+        54d83a11a4bab816fea83ca09a00ac030e16850488b35d67295f2ada03d9c5a9";
+
+    fn check_signature<D: Digest + FixedOutputReset + DigestInfo>(key: &SecretRsaKey, sig: &str) {
+        let mut crng = StdRng::seed_from_u64(0);
+        let sig = hex(sig);
+        assert_eq!(
+            key.sign_pkcs1v15(&mut D::new(), MSG, &mut crng),
+            Some(sig.clone())
+        );
+        let pk = key.pub_key();
+        assert!(pk.verify_pkcs1v15(&mut D::new(), MSG, &sig));
+        assert!(!pk.verify_pkcs1v15(&mut D::new(), b"Hello, PKCS#1 v2.2!", &sig));
+        let mut flipped = sig.clone();
+        flipped[100] ^= 0x04;
+        assert!(!pk.verify_pkcs1v15(&mut D::new(), MSG, &flipped));
+        assert!(!pk.verify_pkcs1v15(&mut D::new(), MSG, &sig[1..]));
+    }
+
+    #[test]
+    fn signatures() {
+        let key = SecretRsaKey::from_pem(KEY).unwrap();
+        check_signature::<Sha1>(&key, SIG_SHA1);
+        check_signature::<Sha256>(&key, SIG_SHA256);
+        check_signature::<Sha512>(&key, SIG_SHA512);
+
+        // Signature representatives must be below n.
+        let pk = key.pub_key();
+        let n = i2osp(pk.n(), pk.size()).unwrap();
+        assert!(!pk.verify_pkcs1v15(&mut Sha256::new(), MSG, &n));
+    }
+
+    /// Encrypts an encoded message without padding.
+    fn raw_enc(key: &SecretRsaKey, em: &[u8]) -> Vec<u8> {
+        i2osp(
+            &key.pub_key().enc(&os2ip(em)).unwrap(),
+            key.pub_key().size(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn decryption() {
+        let key = SecretRsaKey::from_pem(KEY).unwrap();
+        let mut crng = StdRng::seed_from_u64(0);
+        for (em, m) in [
+            (
+                [&[0, 2][..], &[0x11; 234], &[0], MSG].concat(),
+                MSG.to_vec(),
+            ),
+            ([&[0, 2][..], &[0x11; 253], &[0]].concat(), vec![]),
+            // The shortest PS allowed.
+            (
+                [&[0, 2][..], &[0x11; 8], &[0], &[0x22; 245]].concat(),
+                vec![0x22; 245],
+            ),
+        ] {
+            let c = raw_enc(&key, &em);
+            assert_eq!(key.dec_pkcs1v15(&c, &mut crng), Some(m));
+        }
+
+        let c = key.pub_key().enc_pkcs1v15(MSG, &mut crng).unwrap();
+        assert_eq!(key.dec_pkcs1v15(&c, &mut crng).as_deref(), Some(MSG));
+        assert_eq!(key.dec_pkcs1v15(&c[1..], &mut crng), None);
+        assert_eq!(key.pub_key().enc_pkcs1v15(&[0; 246], &mut crng), None);
+    }
+
+    #[test]
+    fn implicit_rejection() {
+        let key = SecretRsaKey::from_pem(KEY).unwrap();
+        let mut crng = StdRng::seed_from_u64(0);
+        for (em, synthetic) in [
+            // Block type 1
+            (
+                [&[0, 1][..], &[0xff; 234], &[0], MSG].concat(),
+                "450e86a06ae6b99e91f9d1ea1e6f924480d3b395723ab983f06855904c6c8920\
+                f9671e785cc41db9aff704bcfe7b853322005fe72081d8fa5a1deb19e4d0c020\
+                c6daa2490a17a04b8df497a25f6b569abb70f65844d8b17bcc96960504a0da93\
+                224f40fadd0f48bba2e39ea4dad3aae2a04dd28ed6553ebac05a4d175b85ff04\
+                57d2e7a80c23e34d36fb9c6de9a5f7d4521e4e20e8ca805c30f0ed0224f4bb56\
+                b921c4082c5e6e4c5348ef2928d246fda63782823f1d58b01f1ef5f463aee44b\
+                7851ceecc9f8996438d5a8884744003c41f1825deab83dd987ce",
+            ),
+            // Non-zero first byte
+            (
+                [&[1, 2][..], &[0x11; 234], &[0], MSG].concat(),
+                "46476519712f4deb42964cd0332423f73c743c5060835fb91e8c1bb223128276\
+                7ae28e51169ba7c885",
+            ),
+            // No separator
+            (
+                [&[0, 2][..], &[0x11; 254]].concat(),
+                "594a1210932e0d766ce0f398725719be65555e95e44b2bbdbdae556447ec3642\
+                d001b6b61e884e7b41d5d6cc85deba211b4a0347dc533e73b31b03325292d3fd\
+                1f1fd9062e86ad9b2f12f7c3caeb6c7dac6473f283350db66b8f",
+            ),
+            // PS shorter than 8 bytes
+            (
+                [&[0, 2][..], &[0x11; 7], &[0], &[0x22; 246]].concat(),
+                "7a78c3ecbc12e08e2a8c0757bac37698d69ab9a8d7f11203d41d79d872869e3b\
+                0e3cb5a99de37ff8514ef3d784b4e9c1489bb818",
+            ),
+        ] {
+            let c = raw_enc(&key, &em);
+            assert_eq!(key.dec_pkcs1v15(&c, &mut crng), Some(hex(synthetic)));
+        }
+    }
+}
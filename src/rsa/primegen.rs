@@ -0,0 +1,436 @@
+//! Prime generation for RSA keys: probable primes from a sieve, safe and strong primes, and
+//! provable primes with the Shawe-Taylor method.
+use crate::common::{i2osp, mod_inv, os2ip};
+use crate::primes::{baillie_psw, is_prime_u64, is_probable_prime, miller_rabin_base, Sieve};
+use digest::Digest;
+use num::{bigint::RandBigInt, BigInt, Integer, One, ToPrimitive};
+use rand::{prelude::*, rngs::SmallRng};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+
+/// The bit length of the Shawe-Taylor hash, SHA-256.
+const ST_OUTLEN: u64 = 256;
+/// The seed length of `gen_provable_prime` in bytes.
+const ST_SEED_LEN: usize = 32;
+
+/// The Miller-Rabin rounds for a prime of an RSA key (FIPS 186-5 table B.1), these are on top
+/// of the Baillie-PSW test of `is_probable_prime`.
+pub fn mr_rounds(prime_bits: u64) -> usize {
+    match prime_bits {
+        1536.. => 4,
+        1024.. => 5,
+        _ => 40,
+    }
+}
+
+/// A random odd starting point of `bits` bits with the two top bits set, so that the product of
+/// two such primes has exactly `2 * bits` bits.
+fn random_start<CR: RandBigInt + CryptoRng>(bits: u64, crng: &mut CR) -> BigInt {
+    let start = BigInt::from_biguint(num::bigint::Sign::Plus, crng.gen_biguint(bits));
+    start | (BigInt::from(3) << (bits - 2)) | BigInt::from(1)
+}
+
+/// Runs `search` on every available thread, each from its own random starting point, until one
+/// of them finds a prime. The searches should give up once `stop` is set.
+fn parallel_search<R, CR, F>(bits: u64, rng: &mut R, crng: &mut CR, search: F) -> BigInt
+where
+    R: RandBigInt,
+    CR: RandBigInt + CryptoRng,
+    F: Fn(&BigInt, &mut SmallRng, &AtomicBool) -> Option<BigInt> + Sync,
+{
+    let threads = thread::available_parallelism().map_or(1, |n| n.get());
+    loop {
+        let stop = AtomicBool::new(false);
+        let found = thread::scope(|s| {
+            let handles: Vec<_> = (0..threads)
+                .map(|_| {
+                    let start = random_start(bits, crng);
+                    let mut rng = SmallRng::seed_from_u64(rng.gen_biguint(64).to_u64().unwrap());
+                    let (stop, search) = (&stop, &search);
+                    s.spawn(move || {
+                        let prime = search(&start, &mut rng, stop);
+                        if prime.is_some() {
+                            stop.store(true, Ordering::Relaxed);
+                        }
+                        prime
+                    })
+                })
+                .collect();
+            handles
+                .into_iter()
+                .find_map(|handle| handle.join().unwrap())
+        });
+        if let Some(prime) = found {
+            break prime;
+        }
+    }
+}
+
+/// Generates a prime of `bits` bits with the two top bits set. Every available thread searches
+/// the sieve from its own random starting point, and the first prime found wins.
+pub fn gen_prime<R: RandBigInt, CR: RandBigInt + CryptoRng>(
+    bits: u64,
+    rng: &mut R,
+    crng: &mut CR,
+) -> BigInt {
+    gen_prime_congruent(bits, 1, 2, rng, crng)
+}
+
+/// `gen_prime` for a prime ≡ `residue` mod `modulus`, an odd residue mod a power of two. Only
+/// the candidates in the residue class are sieved.
+pub fn gen_prime_congruent<R: RandBigInt, CR: RandBigInt + CryptoRng>(
+    bits: u64,
+    residue: u32,
+    modulus: u32,
+    rng: &mut R,
+    crng: &mut CR,
+) -> BigInt {
+    let rounds = mr_rounds(bits);
+    parallel_search(bits, rng, crng, |start, rng, stop| {
+        Sieve::new_congruent(start, residue, modulus)
+            .take_while(|n| n.bits() == bits && !stop.load(Ordering::Relaxed))
+            .find(|n| is_probable_prime(n, rounds, rng))
+    })
+}
+
+/// Generates a safe prime p = 2q + 1 of `bits` bits, where q is a prime too.
+/// The two top bits of p are set.
+pub fn gen_safe_prime<R: RandBigInt, CR: RandBigInt + CryptoRng>(
+    bits: u64,
+    rng: &mut R,
+    crng: &mut CR,
+) -> BigInt {
+    assert!(32 <= bits, "safe primes must have at least 32 bits");
+    let rounds = mr_rounds(bits);
+    parallel_search(bits - 1, rng, crng, |start, rng, stop| {
+        Sieve::new_safe(start)
+            .take_while(|q| q.bits() == bits - 1 && !stop.load(Ordering::Relaxed))
+            .map(|q| (&q << 1u8) + 1u8)
+            .find(|p| {
+                let q: BigInt = p >> 1u8;
+                miller_rabin_base(&q, &2.into())
+                    && miller_rabin_base(p, &2.into())
+                    && is_probable_prime(&q, rounds, rng)
+                    && is_probable_prime(p, rounds, rng)
+            })
+    })
+}
+
+/// The residue and modulus of the candidates for a strong prime: p ≡ 1 mod 2r, p ≡ -1 mod s.
+fn strong_residue(r: &BigInt, s: &BigInt) -> (BigInt, BigInt) {
+    let r2: BigInt = r << 1u8;
+    let step = &r2 * s;
+    let base = (mod_inv(s, &r2) * s - mod_inv(&r2, s) * &r2).mod_floor(&step);
+    (base, step)
+}
+
+/// Generates a strong prime p of `bits` bits with the two top bits set, where p - 1 and p + 1
+/// have prime factors of `factor_bits` bits. The factors come from `gen_prime` and are combined
+/// as in FIPS 186-5 B.3.6: p ≡ 1 mod 2r and p ≡ -1 mod s.
+pub fn gen_strong_prime<R: RandBigInt, CR: RandBigInt + CryptoRng>(
+    bits: u64,
+    factor_bits: u64,
+    rng: &mut R,
+    crng: &mut CR,
+) -> BigInt {
+    assert!(
+        32 <= factor_bits && 2 * factor_bits + 8 <= bits,
+        "the factors of a strong prime must have between 32 and (bits - 8) / 2 bits"
+    );
+    let r = gen_prime(factor_bits, rng, crng);
+    let s = gen_prime(factor_bits, rng, crng);
+    let (base, step) = strong_residue(&r, &s);
+    let rounds = mr_rounds(bits);
+    parallel_search(bits, rng, crng, |start, rng, stop| {
+        let mut p = start + (&base - start).mod_floor(&step);
+        while p.bits() == bits && !stop.load(Ordering::Relaxed) {
+            if is_probable_prime(&p, rounds, rng) {
+                return Some(p);
+            }
+            p += &step;
+        }
+        None
+    })
+}
+
+/// A starting point of the seeded search: the next ⌈bits / 8⌉ bytes of the stream as a big
+/// endian integer, reduced to `bits` bits, with the two top bits and the low bit set.
+fn seeded_start<R: RngCore>(bits: u64, stream: &mut R) -> BigInt {
+    let mut bytes = vec![0; bits.div_ceil(8) as usize];
+    stream.fill_bytes(&mut bytes);
+    let start = os2ip(&bytes).mod_floor(&(BigInt::one() << bits));
+    start | (BigInt::from(3) << (bits - 2)) | BigInt::from(1)
+}
+
+/// The seeded counterpart of `gen_prime`: the first odd number from a starting point that passes
+/// Baillie-PSW, with a new starting point whenever the candidates outgrow `bits` bits.
+pub(super) fn seeded_prime<R: RngCore>(bits: u64, stream: &mut R) -> BigInt {
+    loop {
+        let start = seeded_start(bits, stream);
+        if let Some(prime) = Sieve::new(&start)
+            .take_while(|n| n.bits() == bits)
+            .find(baillie_psw)
+        {
+            break prime;
+        }
+    }
+}
+
+/// The seeded counterpart of `gen_strong_prime`: the factors r and s are `seeded_prime`s, then p
+/// is the first candidate ≡ 1 mod 2r and ≡ -1 mod s from a starting point that passes
+/// Baillie-PSW.
+pub(super) fn seeded_strong_prime<R: RngCore>(
+    bits: u64,
+    factor_bits: u64,
+    stream: &mut R,
+) -> BigInt {
+    let r = seeded_prime(factor_bits, stream);
+    let s = seeded_prime(factor_bits, stream);
+    let (base, step) = strong_residue(&r, &s);
+    loop {
+        let start = seeded_start(bits, stream);
+        let mut p = &start + (&base - &start).mod_floor(&step);
+        while p.bits() == bits {
+            if baillie_psw(&p) {
+                return p;
+            }
+            p += &step;
+        }
+    }
+}
+
+/// The seeded counterpart of `gen_provable_prime`: `shawe_taylor` with the next 32 bytes of the
+/// stream as its seed.
+pub(super) fn seeded_provable_prime<R: RngCore>(bits: u64, stream: &mut R) -> BigInt {
+    loop {
+        let mut seed = [0; ST_SEED_LEN];
+        stream.fill_bytes(&mut seed);
+        if let Some(cert) = shawe_taylor(bits, &seed) {
+            break cert.prime();
+        }
+    }
+}
+
+/// A Pocklington step of a prime certificate: `prime - 1 = 2t * c0` where c0 is the previous
+/// prime of the chain, c0^2 > prime, witness^(prime - 1) ≡ 1 and gcd(witness^2t - 1, prime) = 1.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PocklingtonStep {
+    pub prime: BigInt,
+    pub witness: BigInt,
+}
+
+/// A certificate of primality: a small prime that is checked deterministically, and a chain of
+/// Pocklington steps, each proving a prime from the one before it.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PrimeCertificate {
+    pub base: u64,
+    pub steps: Vec<PocklingtonStep>,
+}
+
+impl PrimeCertificate {
+    /// The certified prime.
+    pub fn prime(&self) -> BigInt {
+        self.steps
+            .last()
+            .map_or_else(|| self.base.into(), |step| step.prime.clone())
+    }
+
+    /// Checks the whole chain, returns whether it proves that `prime()` is a prime.
+    pub fn verify(&self) -> bool {
+        if !is_prime_u64(self.base) {
+            return false;
+        }
+        let mut c0 = BigInt::from(self.base);
+        for PocklingtonStep {
+            prime: c,
+            witness: a,
+        } in &self.steps
+        {
+            let c1: BigInt = c - 1;
+            let (t2, rem) = c1.div_rem(&c0);
+            if rem != BigInt::from(0) || t2.is_odd() || &c0 * &c0 <= *c || *a < 2.into() || c1 <= *a
+            {
+                return false;
+            }
+            let z = a.modpow(&t2, c);
+            if !(&z - 1u8).gcd(c).is_one() || !z.modpow(&c0, c).is_one() {
+                return false;
+            }
+            c0 = c.clone();
+        }
+        true
+    }
+}
+
+/// Hashes the seed as a byte string of the seed's length, like FIPS 186-5 does.
+fn st_hash(seed: &BigInt, seed_len: usize) -> BigInt {
+    let seed = seed.mod_floor(&(BigInt::one() << (8 * seed_len)));
+    os2ip(&Sha256::digest(i2osp(&seed, seed_len).unwrap()))
+}
+
+/// Concatenates the hashes of `iterations + 1` consecutive seeds and advances the seed past them.
+fn st_hashes(prime_seed: &mut BigInt, seed_len: usize, iterations: u64) -> BigInt {
+    let mut x: BigInt = 0.into();
+    for i in 0..=iterations {
+        x += st_hash(&(&*prime_seed + i), seed_len) << (i * ST_OUTLEN);
+    }
+    *prime_seed += iterations + 1;
+    x
+}
+
+/// ST_Random_Prime of FIPS 186-5 A.1.2.1.2 with SHA-256, for a prime in [low, 2^bits).
+/// FIPS 186-5 uses low = 2^(bits - 1), the intermediate primes of the chain are built so.
+fn st_random_prime(
+    bits: u64,
+    low: &BigInt,
+    prime_seed: &mut BigInt,
+    seed_len: usize,
+) -> Option<PrimeCertificate> {
+    let range = (BigInt::one() << bits) - low;
+    if bits < 33 {
+        for _ in 0..4 * bits + 1 {
+            let c = st_hash(prime_seed, seed_len) ^ st_hash(&(&*prime_seed + 1), seed_len);
+            let c = (low + c.mod_floor(&range)) | BigInt::one();
+            *prime_seed += 2;
+            let c = c.to_u64().unwrap();
+            if is_prime_u64(c) {
+                return Some(PrimeCertificate {
+                    base: c,
+                    steps: vec![],
+                });
+            }
+        }
+        return None;
+    }
+
+    let c0_bits = bits.div_ceil(2) + 1;
+    let mut cert = st_random_prime(
+        c0_bits,
+        &(BigInt::one() << (c0_bits - 1)),
+        prime_seed,
+        seed_len,
+    )?;
+    let c0 = cert.prime();
+    let c02: BigInt = &c0 << 1u8;
+    let iterations = bits.div_ceil(ST_OUTLEN) - 1;
+    let x = low + st_hashes(prime_seed, seed_len, iterations).mod_floor(&range);
+    let mut t = x.div_ceil(&c02);
+    for _ in 0..4 * bits {
+        if (BigInt::one() << bits) < &t * &c02 + 1 {
+            t = low.div_ceil(&c02);
+        }
+        let c = &t * &c02 + 1;
+        let a = st_hashes(prime_seed, seed_len, iterations).mod_floor(&(&c - 3u8)) + 2u8;
+        let z = a.modpow(&(&t << 1u8), &c);
+        if (&z - 1u8).gcd(&c).is_one() && z.modpow(&c0, &c).is_one() {
+            cert.steps.push(PocklingtonStep {
+                prime: c,
+                witness: a,
+            });
+            return Some(cert);
+        }
+        t += 1;
+    }
+    None
+}
+
+/// Constructs a provable prime of `bits` bits from the seed with the Shawe-Taylor method,
+/// along with its certificate. The same seed always gives the same prime.
+/// The two top bits of the prime are set, like the other RSA primes (FIPS 186-4 B.3.2 asks
+/// for p ≥ √2 · 2^(bits - 1)). Returns `None` if the construction fails for this seed.
+pub fn shawe_taylor(bits: u64, seed: &[u8]) -> Option<PrimeCertificate> {
+    if bits < 2 {
+        return None;
+    }
+    let low = BigInt::from(3) << (bits - 2);
+    st_random_prime(bits, &low, &mut os2ip(seed), seed.len())
+}
+
+/// Generates a provable prime of `bits` bits from random seeds with the Shawe-Taylor method.
+pub fn gen_provable_prime<CR: RandBigInt + CryptoRng>(
+    bits: u64,
+    crng: &mut CR,
+) -> PrimeCertificate {
+    loop {
+        let seed = crng.gen_biguint(8 * ST_SEED_LEN as u64);
+        let seed = i2osp(&BigInt::from(seed), ST_SEED_LEN).unwrap();
+        if let Some(cert) = shawe_taylor(bits, &seed) {
+            break cert;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn top_bits_set(p: &BigInt, bits: u64) -> bool {
+        p.bits() == bits && p.bit(bits - 2)
+    }
+
+    #[test]
+    fn prime_certificates() {
+        for bits in [20, 64, 512] {
+            for seed in 0..4u8 {
+                let cert = shawe_taylor(bits, &[seed; ST_SEED_LEN]).unwrap();
+                assert!(cert.verify());
+                assert!(top_bits_set(&cert.prime(), bits));
+                assert_eq!(shawe_taylor(bits, &[seed; ST_SEED_LEN]), Some(cert.clone()));
+                let json = serde_json::to_string(&cert).unwrap();
+                let decoded: PrimeCertificate = serde_json::from_str(&json).unwrap();
+                assert!(decoded.verify());
+                assert_eq!(decoded.prime(), cert.prime());
+            }
+        }
+        let cert = gen_provable_prime(1024, &mut StdRng::seed_from_u64(0));
+        assert!(cert.verify());
+        assert!(top_bits_set(&cert.prime(), 1024));
+    }
+
+    #[test]
+    fn rejects_tampered_certificates() {
+        let cert = shawe_taylor(512, &[1; ST_SEED_LEN]).unwrap();
+        assert!(cert.steps.len() >= 2);
+
+        let mut composite_base = cert.clone();
+        composite_base.base += 1;
+        assert!(!composite_base.verify());
+        for i in 0..cert.steps.len() {
+            let mut next = cert.clone();
+            next.steps[i].prime += 2;
+            assert!(!next.verify());
+            let mut witness = cert.clone();
+            witness.steps[i].witness = 1.into();
+            assert!(!witness.verify());
+        }
+        // dropping the last step certifies the previous prime instead
+        for i in 0..cert.steps.len() - 1 {
+            let mut skipped = cert.clone();
+            skipped.steps.remove(i);
+            assert!(!skipped.verify());
+        }
+        // the square of the previous prime must exceed the next one
+        let big_step = PrimeCertificate {
+            base: 3,
+            steps: vec![PocklingtonStep {
+                prime: 13.into(),
+                witness: 2.into(),
+            }],
+        };
+        assert!(!big_step.verify());
+    }
+
+    #[test]
+    fn safe_primes() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut crng = StdRng::seed_from_u64(1);
+        for bits in [32, 64, 256] {
+            let p = gen_safe_prime(bits, &mut rng, &mut crng);
+            assert!(top_bits_set(&p, bits));
+            assert!(is_probable_prime(&p, 40, &mut rng));
+            assert!(is_probable_prime(&((&p - 1) / 2), 40, &mut rng));
+        }
+    }
+}
@@ -0,0 +1,130 @@
+use super::{PublicRsaKey, SecretRsaKey};
+use crate::common::{i2osp, os2ip};
+use digest::{Digest, FixedOutputReset};
+use rand::prelude::*;
+
+/// MGF1 mask generation function (RFC 8017 B.2.1).
+pub(crate) fn mgf1<D: Digest + FixedOutputReset>(h: &mut D, seed: &[u8], len: usize) -> Vec<u8> {
+    let mut out = Vec::with_capacity(len + <D as Digest>::output_size());
+    let mut counter: u32 = 0;
+    while out.len() < len {
+        Digest::update(h, seed);
+        Digest::update(h, counter.to_be_bytes());
+        out.extend_from_slice(&h.finalize_reset());
+        counter += 1;
+    }
+    out.truncate(len);
+    out
+}
+
+/// EMSA-PSS encoding (RFC 8017 9.1.1) of an already hashed message into `em_bits` bits.
+pub(crate) fn emsa_pss_encode<D: Digest + FixedOutputReset>(
+    h: &mut D,
+    m_hash: &[u8],
+    salt: &[u8],
+    em_bits: usize,
+) -> Option<Vec<u8>> {
+    let h_len = m_hash.len();
+    let em_len = em_bits.div_ceil(8);
+    if em_len < h_len + salt.len() + 2 {
+        return None;
+    }
+    Digest::update(h, [0; 8]);
+    Digest::update(h, m_hash);
+    Digest::update(h, salt);
+    let hash = h.finalize_reset();
+
+    let db_len = em_len - h_len - 1;
+    let mut db = vec![0; db_len];
+    db[db_len - salt.len() - 1] = 0x01;
+    db[db_len - salt.len()..].copy_from_slice(salt);
+    for (b, mask) in db.iter_mut().zip(mgf1(h, &hash, db_len)) {
+        *b ^= mask;
+    }
+    db[0] &= 0xff >> (8 * em_len - em_bits);
+
+    let mut em = db;
+    em.extend_from_slice(&hash);
+    em.push(0xbc);
+    Some(em)
+}
+
+/// EMSA-PSS verification (RFC 8017 9.1.2) of an already hashed message.
+pub(crate) fn emsa_pss_verify<D: Digest + FixedOutputReset>(
+    h: &mut D,
+    m_hash: &[u8],
+    em: &[u8],
+    salt_len: usize,
+    em_bits: usize,
+) -> bool {
+    let h_len = m_hash.len();
+    let em_len = em_bits.div_ceil(8);
+    if em.len() != em_len || em_len < h_len + salt_len + 2 || em[em_len - 1] != 0xbc {
+        return false;
+    }
+    let (masked_db, hash) = em[..em_len - 1].split_at(em_len - h_len - 1);
+    let top_mask = 0xff >> (8 * em_len - em_bits);
+    if masked_db[0] & !top_mask != 0 {
+        return false;
+    }
+    let mut db: Vec<u8> = masked_db
+        .iter()
+        .zip(mgf1(h, hash, masked_db.len()))
+        .map(|(b, mask)| b ^ mask)
+        .collect();
+    db[0] &= top_mask;
+
+    let ps_len = db.len() - salt_len - 1;
+    if db[..ps_len].iter().any(|&b| b != 0) || db[ps_len] != 0x01 {
+        return false;
+    }
+    Digest::update(h, [0; 8]);
+    Digest::update(h, m_hash);
+    Digest::update(h, &db[ps_len + 1..]);
+    h.finalize_reset()[..] == *hash
+}
+
+impl PublicRsaKey {
+    /// RSASSA-PSS signature verification (RFC 8017 8.1.2) with MGF1 over the same hash.
+    pub fn verify_pss<D: Digest + FixedOutputReset>(
+        &self,
+        h: &mut D,
+        m: &[u8],
+        ds: &[u8],
+        salt_len: usize,
+    ) -> bool {
+        let k = self.size();
+        let em_bits = self.1.bits() as usize - 1;
+        if ds.len() != k {
+            return false;
+        }
+        let s = os2ip(ds);
+        if self.1 <= s {
+            return false;
+        }
+        let Some(em) = self.enc(&s).and_then(|m| i2osp(&m, em_bits.div_ceil(8))) else {
+            return false;
+        };
+        Digest::update(h, m);
+        let m_hash = h.finalize_reset();
+        emsa_pss_verify(h, &m_hash, &em, salt_len, em_bits)
+    }
+}
+
+impl SecretRsaKey {
+    /// RSASSA-PSS signature generation (RFC 8017 8.1.1) with MGF1 over the same hash.
+    pub fn sign_pss<D: Digest + FixedOutputReset, CR: RngCore + CryptoRng>(
+        &self,
+        h: &mut D,
+        m: &[u8],
+        salt_len: usize,
+        crng: &mut CR,
+    ) -> Option<Vec<u8>> {
+        let mut salt = vec![0; salt_len];
+        crng.fill_bytes(&mut salt);
+        Digest::update(h, m);
+        let m_hash = h.finalize_reset();
+        let em = emsa_pss_encode(h, &m_hash, &salt, self.1 .1.bits() as usize - 1)?;
+        i2osp(&self.dec(&os2ip(&em), crng)?, self.1.size())
+    }
+}
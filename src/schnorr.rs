@@ -0,0 +1,258 @@
+//! Prime order subgroups of Z_p^*, written multiplicatively but used through `AddGroup`, so
+//! that the generic schemes of `ecc` work over finite fields: ECDSA becomes DSA, EdDSA becomes
+//! Schnorr signatures and ECDH becomes DH.
+use crate::common::mod_inv;
+use crate::dh::{DhGroup, NamedGroup};
+use crate::dsa::DsaParams;
+use crate::ecc::AddGroup;
+use lazy_static::lazy_static;
+use num::{one, BigInt, One, Signed};
+use serde::{Deserialize, Serialize};
+
+/// The subgroup of order q generated by g mod the prime p. The group operation is
+/// multiplication mod p, "negation" is inversion and "scalar multiplication" is exponentiation.
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct SchnorrGroup {
+    p: BigInt,
+    q: BigInt,
+    g: BigInt,
+}
+
+lazy_static! {
+    /// The RFC 5114 1024-bit MODP group with a 160-bit prime order subgroup.
+    pub static ref MODP1024_160: SchnorrGroup = SchnorrGroup::from_hex(
+        b"B10B8F96A080E01DDE92DE5EAE5D54EC52C99FBCFB06A3C69A6A9DCA52D23B61\
+          6073E28675A23D189838EF1E2EE652C013ECB4AEA906112324975C3CD49B83BF\
+          ACCBDD7D90C4BD7098488E9C219A73724EFFD6FAE5644738FAA31A4FF55BCCC0\
+          A151AF5F0DC8B4BD45BF37DF365C1A65E68CFDA76D4DA708DF1FB2BC2E4A4371",
+        b"F518AA8781A8DF278ABA4E7D64B7CB9D49462353",
+        b"A4D1CBD5C3FD34126765A442EFB99905F8104DD258AC507FD6406CFF14266D31\
+          266FEA1E5C41564B777E690F5504F213160217B4B01B886A5E91547F9E2749F4\
+          D7FBD7D3B9A92EE1909D0D2263F80A76A6A24C087A091F531DBF0A0169B6A28A\
+          D662A4D18E73AFA32D779D5918D08BC8858F4DCEF97C2A24855E6EEB22B3B2E5",
+    );
+    /// The RFC 5114 2048-bit MODP group with a 224-bit prime order subgroup.
+    pub static ref MODP2048_224: SchnorrGroup = SchnorrGroup::from_hex(
+        b"AD107E1E9123A9D0D660FAA79559C51FA20D64E5683B9FD1B54B1597B61D0A75\
+          E6FA141DF95A56DBAF9A3C407BA1DF15EB3D688A309C180E1DE6B85A1274A0A6\
+          6D3F8152AD6AC2129037C9EDEFDA4DF8D91E8FEF55B7394B7AD5B7D0B6C12207\
+          C9F98D11ED34DBF6C6BA0B2C8BBC27BE6A00E0A0B9C49708B3BF8A3170918836\
+          81286130BC8985DB1602E714415D9330278273C7DE31EFDC7310F7121FD5A074\
+          15987D9ADC0A486DCDF93ACC44328387315D75E198C641A480CD86A1B9E587E8\
+          BE60E69CC928B2B9C52172E413042E9B23F10B0E16E79763C9B53DCF4BA80A29\
+          E3FB73C16B8E75B97EF363E2FFA31F71CF9DE5384E71B81C0AC4DFFE0C10E64F",
+        b"801C0D34C58D93FE997177101F80535A4738CEBCBF389A99B36371EB",
+        b"AC4032EF4F2D9AE39DF30B5C8FFDAC506CDEBE7B89998CAF74866A08CFE4FFE3\
+          A6824A4E10B9A6F0DD921F01A70C4AFAAB739D7700C29F52C57DB17C620A8652\
+          BE5E9001A8D66AD7C17669101999024AF4D027275AC1348BB8A762D0521BC98A\
+          E247150422EA1ED409939D54DA7460CDB5F6C6B250717CBEF180EB34118E98D1\
+          19529A45D6F834566E3025E316A330EFBB77A86F0C1AB15B051AE3D428C8F8AC\
+          B70A8137150B8EEB10E183EDD19963DDD9E263E4770589EF6AA21E7F5F2FF381\
+          B539CCE3409D13CD566AFBB48D6C019181E1BCFE94B30269EDFE72FE9B6AA4BD\
+          7B5A0F1C71CFFF4C19C418E1F6EC017981BC087F2A7065B384B890D3191F2BFA",
+    );
+    /// The RFC 5114 2048-bit MODP group with a 256-bit prime order subgroup.
+    pub static ref MODP2048_256: SchnorrGroup = SchnorrGroup::from_hex(
+        b"87A8E61DB4B6663CFFBBD19C651959998CEEF608660DD0F25D2CEED4435E3B00\
+          E00DF8F1D61957D4FAF7DF4561B2AA3016C3D91134096FAA3BF4296D830E9A7C\
+          209E0C6497517ABD5A8A9D306BCF67ED91F9E6725B4758C022E0B1EF4275BF7B\
+          6C5BFC11D45F9088B941F54EB1E59BB8BC39A0BF12307F5C4FDB70C581B23F76\
+          B63ACAE1CAA6B7902D52526735488A0EF13C6D9A51BFA4AB3AD8347796524D8E\
+          F6A167B5A41825D967E144E5140564251CCACB83E6B486F6B3CA3F7971506026\
+          C0B857F689962856DED4010ABD0BE621C3A3960A54E710C375F26375D7014103\
+          A4B54330C198AF126116D2276E11715F693877FAD7EF09CADB094AE91E1A1597",
+        b"8CF83642A709A097B447997640129DA299B1A47D1EB3750BA308B0FE64F5FBD3",
+        b"3FB32C9B73134D0B2E77506660EDBD484CA7B18F21EF205407F4793A1A0BA125\
+          10DBC15077BE463FFF4FED4AAC0BB555BE3A6C1B0C6B47B1BC3773BF7E8C6F62\
+          901228F8C28CBB18A55AE31341000A650196F931C77A57F2DDF463E5E9EC144B\
+          777DE62AAAB8A8628AC376D282D6ED3864E67982428EBC831D14348F6F2F9193\
+          B5045AF2767164E1DFC967C1FB3F2E55A4BD1BFFE83B9C80D052B985D182EA0A\
+          DB2A3B7313D3FE14C8484B1E052588B9B7D2BBD2DF016199ECD06E1557CD0915\
+          B3353BBB64E0EC377FD028370DF92B52C7891428CDC67EB6184B523D1DB246C3\
+          2F63078490F00EF8D647D148D47954515E2327CFEF98C582664B4C0F6CC41659",
+    );
+    /// The RFC 7919 ffdhe2048 group, the subgroup of the quadratic residues.
+    pub static ref FFDHE2048: SchnorrGroup = NamedGroup::Ffdhe2048.group().into();
+    /// The RFC 7919 ffdhe3072 group.
+    pub static ref FFDHE3072: SchnorrGroup = NamedGroup::Ffdhe3072.group().into();
+    /// The RFC 7919 ffdhe4096 group.
+    pub static ref FFDHE4096: SchnorrGroup = NamedGroup::Ffdhe4096.group().into();
+}
+
+impl SchnorrGroup {
+    pub fn new(p: BigInt, q: BigInt, g: BigInt) -> Self {
+        Self { p, q, g }
+    }
+
+    fn from_hex(p: &[u8], q: &[u8], g: &[u8]) -> Self {
+        let parse = |hex| BigInt::parse_bytes(hex, 16).unwrap();
+        Self::new(parse(p), parse(q), parse(g))
+    }
+
+    pub fn p(&self) -> &BigInt {
+        &self.p
+    }
+}
+
+impl From<&DhGroup> for SchnorrGroup {
+    fn from(group: &DhGroup) -> Self {
+        Self::new(group.p().clone(), group.q().clone(), group.g().clone())
+    }
+}
+
+impl From<&DsaParams> for SchnorrGroup {
+    fn from(params: &DsaParams) -> Self {
+        Self::new(params.p().clone(), params.q().clone(), params.g().clone())
+    }
+}
+
+impl AddGroup for SchnorrGroup {
+    type Point = BigInt;
+    fn identity(&self) -> Self::Point {
+        one()
+    }
+    fn generator(&self) -> &Self::Point {
+        &self.g
+    }
+    fn order(&self) -> &BigInt {
+        &self.q
+    }
+    fn add(&self, p: &Self::Point, q: &Self::Point) -> Self::Point {
+        (p * q) % &self.p
+    }
+    fn neg(&self, p: &Self::Point) -> Self::Point {
+        mod_inv(p, &self.p)
+    }
+    fn double(&self, p: &Self::Point) -> Self::Point {
+        (p * p) % &self.p
+    }
+    fn mul(&self, k: &BigInt, p: &Self::Point) -> Self::Point {
+        if k.is_negative() {
+            self.neg(p).modpow(&-k, &self.p)
+        } else {
+            p.modpow(k, &self.p)
+        }
+    }
+    /// Whether the element is in the subgroup: 0 < x < p and x^q ≡ 1 (mod p).
+    fn validate(&self, p: &Self::Point) -> bool {
+        p.is_positive() && *p < self.p && p.modpow(&self.q, &self.p).is_one()
+    }
+    fn to_bigint(p: &Self::Point) -> &BigInt {
+        p
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::hash_bigint;
+    use crate::ecc::{ecdh, ecdsa_sign, ecdsa_verify, eddsa_sign, eddsa_verify, Point};
+    use digest::Digest;
+    use num::{bigint::RandBigInt, Integer};
+    use rand::prelude::*;
+    use sha2::Sha256;
+
+    #[test]
+    fn generators_have_order_q() {
+        for (name, group) in [
+            ("modp1024_160", &*MODP1024_160),
+            ("modp2048_224", &*MODP2048_224),
+            ("modp2048_256", &*MODP2048_256),
+            ("ffdhe2048", &*FFDHE2048),
+            ("ffdhe3072", &*FFDHE3072),
+            ("ffdhe4096", &*FFDHE4096),
+        ] {
+            let g = group.generator();
+            assert!(group.validate(g), "{}", name);
+            assert!(!g.is_one(), "{}", name);
+            assert!((group.p() - 1u8).is_multiple_of(group.order()), "{}", name);
+        }
+    }
+
+    #[test]
+    fn dsa_and_dh() {
+        let mut crng = StdRng::seed_from_u64(0);
+        let mut h = Sha256::new();
+        for group in [&*MODP2048_256, &*FFDHE2048] {
+            let q = group.order();
+            let g = Point {
+                pos: group.generator().clone(),
+                curve: group,
+            };
+            let sk1 = crng.gen_bigint_range(&one(), q);
+            let sk2 = crng.gen_bigint_range(&one(), q);
+            let pk1 = &sk1 * &g;
+            let pk2 = &sk2 * &g;
+            assert_eq!(ecdh(&sk1, &pk2, &mut h), ecdh(&sk2, &pk1, &mut h));
+
+            let m = BigInt::from(0x1234_5678);
+            let ds = ecdsa_sign(&m, &sk1, group, &mut crng, &mut h);
+            assert!(ecdsa_verify(&m, &pk1.pos, &ds, group, &mut h));
+            assert!(!ecdsa_verify(&(&m + 1), &pk1.pos, &ds, group, &mut h));
+            assert!(!ecdsa_verify(&m, &pk2.pos, &ds, group, &mut h));
+            let (r, s) = &ds;
+            assert!(!ecdsa_verify(
+                &m,
+                &pk1.pos,
+                &(r.clone(), s + 1),
+                group,
+                &mut h
+            ));
+            assert!(!ecdsa_verify(
+                &m,
+                &pk1.pos,
+                &(r.clone(), s + q),
+                group,
+                &mut h
+            ));
+        }
+    }
+
+    #[test]
+    fn schnorr_signatures() {
+        let mut crng = StdRng::seed_from_u64(0);
+        let mut h = Sha256::new();
+        let group = &*MODP2048_256;
+        let g = Point {
+            pos: group.generator().clone(),
+            curve: group,
+        };
+        let sk = crng.gen_bigint_range(&one(), group.order());
+        let pk = (&sk * &g).pos;
+        let m = BigInt::from(42);
+        let ds = eddsa_sign(&m, &sk, group, &mut crng, &mut h);
+        assert!(eddsa_verify(&m, &pk, &ds, group, &mut h));
+        assert!(!eddsa_verify(&(&m + 1), &pk, &ds, group, &mut h));
+
+        // with the challenge H(m) anyone could pick s and solve for R = sG - H(m) pk
+        let s = BigInt::from(7);
+        let z = hash_bigint(&m, &mut h).mod_floor(group.order());
+        let forged = (&s * &g
+            - z * Point {
+                pos: pk.clone(),
+                curve: group,
+            })
+        .pos;
+        assert!(!eddsa_verify(&m, &pk, &(forged, s), group, &mut h));
+
+        let (r, s) = &ds;
+        let other_pk = (&crng.gen_bigint_range(&one(), group.order()) * &g).pos;
+        assert!(!eddsa_verify(&m, &other_pk, &ds, group, &mut h));
+        let r2 = group.add(r, group.generator());
+        assert!(!eddsa_verify(&m, &pk, &(r2, s + 1), group, &mut h));
+        assert!(!eddsa_verify(
+            &m,
+            &pk,
+            &(group.identity(), s.clone()),
+            group,
+            &mut h
+        ));
+        assert!(!eddsa_verify(
+            &m,
+            &pk,
+            &(BigInt::from(0), s.clone()),
+            group,
+            &mut h
+        ));
+    }
+}
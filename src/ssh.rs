@@ -0,0 +1,503 @@
+//! OpenSSH key formats: the `authorized_keys` public key line and the `openssh-key-v1`
+//! private key container.
+use crate::common::{base64_decode, base64_encode, BASE64};
+use crate::der::{pem_decode, pem_encode};
+use crate::ecc::{ed25519_expand, ed25519_pub_key, Pos, ED25519};
+use crate::rsa::{PublicRsaKey, SecretRsaKey};
+use aes::cipher::{KeyIvInit, StreamCipher};
+use num::{bigint::Sign, BigInt};
+use rand::prelude::*;
+use std::fmt;
+
+const AUTH_MAGIC: &[u8] = b"openssh-key-v1\0";
+const PEM_LABEL: &str = "OPENSSH PRIVATE KEY";
+const SSH_RSA: &str = "ssh-rsa";
+const SSH_ED25519: &str = "ssh-ed25519";
+const CIPHER_NONE: &str = "none";
+const CIPHER_AES256_CTR: &str = "aes256-ctr";
+const KDF_NONE: &str = "none";
+const KDF_BCRYPT: &str = "bcrypt";
+/// The default number of bcrypt-pbkdf rounds, same as `ssh-keygen`.
+const BCRYPT_ROUNDS: u32 = 16;
+/// The most bcrypt-pbkdf rounds accepted when reading a key, the round count comes from the
+/// key file and each round takes milliseconds.
+const MAX_BCRYPT_ROUNDS: u32 = 1024;
+
+type Aes256Ctr = ctr::Ctr128BE<aes::Aes256>;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SshError {
+    InvalidFormat,
+    InvalidKey,
+    UnsupportedKeyType(String),
+    UnsupportedCipher(String),
+    UnsupportedKdf(String),
+    MissingPassphrase,
+    WrongPassphrase,
+    TooManyRounds(u32),
+}
+
+impl fmt::Display for SshError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidFormat => write!(f, "invalid OpenSSH key format"),
+            Self::InvalidKey => write!(f, "invalid OpenSSH key"),
+            Self::UnsupportedKeyType(t) => write!(f, "unsupported OpenSSH key type \"{}\"", t),
+            Self::UnsupportedCipher(c) => write!(f, "unsupported OpenSSH cipher \"{}\"", c),
+            Self::UnsupportedKdf(k) => write!(f, "unsupported OpenSSH KDF \"{}\"", k),
+            Self::MissingPassphrase => write!(f, "the OpenSSH key is encrypted"),
+            Self::WrongPassphrase => write!(f, "wrong passphrase for the OpenSSH key"),
+            Self::TooManyRounds(n) => write!(
+                f,
+                "the OpenSSH key uses {} bcrypt rounds, more than {}",
+                n, MAX_BCRYPT_ROUNDS
+            ),
+        }
+    }
+}
+
+impl std::error::Error for SshError {}
+
+/// An SSH public key.
+#[derive(Clone)]
+pub enum SshPublicKey {
+    Rsa(PublicRsaKey),
+    Ed25519(Pos),
+}
+
+/// An SSH secret key. Ed25519 keys keep their RFC 8032 seed, the secret scalar is derived from it.
+#[derive(Clone)]
+pub enum SshSecretKey {
+    Rsa(SecretRsaKey),
+    Ed25519 { seed: [u8; 32], pk: Pos },
+}
+
+/// Writer of the SSH wire encoding (RFC 4251 section 5).
+#[derive(Default)]
+struct SshWriter(Vec<u8>);
+
+impl SshWriter {
+    fn u32(&mut self, n: u32) -> &mut Self {
+        self.0.extend_from_slice(&n.to_be_bytes());
+        self
+    }
+
+    fn string(&mut self, s: impl AsRef<[u8]>) -> &mut Self {
+        let s = s.as_ref();
+        self.u32(s.len() as u32);
+        self.0.extend_from_slice(s);
+        self
+    }
+
+    fn mpint(&mut self, n: &BigInt) -> &mut Self {
+        if n.sign() == Sign::NoSign {
+            self.string([])
+        } else {
+            self.string(n.to_signed_bytes_be())
+        }
+    }
+}
+
+/// Reader of the SSH wire encoding (RFC 4251 section 5).
+struct SshReader<'a>(&'a [u8]);
+
+impl<'a> SshReader<'a> {
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], SshError> {
+        if self.0.len() < len {
+            return Err(SshError::InvalidFormat);
+        }
+        let (out, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Ok(out)
+    }
+
+    fn u32(&mut self) -> Result<u32, SshError> {
+        Ok(u32::from_be_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+
+    fn string(&mut self) -> Result<&'a [u8], SshError> {
+        let len = self.u32()? as usize;
+        self.bytes(len)
+    }
+
+    fn str(&mut self) -> Result<&'a str, SshError> {
+        std::str::from_utf8(self.string()?).map_err(|_| SshError::InvalidFormat)
+    }
+
+    fn mpint(&mut self) -> Result<BigInt, SshError> {
+        let n = BigInt::from_signed_bytes_be(self.string()?);
+        if n.sign() == Sign::Minus {
+            Err(SshError::InvalidKey)
+        } else {
+            Ok(n)
+        }
+    }
+
+    fn finish(&self) -> Result<(), SshError> {
+        if self.0.is_empty() {
+            Ok(())
+        } else {
+            Err(SshError::InvalidFormat)
+        }
+    }
+}
+
+impl SshPublicKey {
+    pub fn key_type(&self) -> &'static str {
+        match self {
+            Self::Rsa(_) => SSH_RSA,
+            Self::Ed25519(_) => SSH_ED25519,
+        }
+    }
+
+    /// The SSH wire encoding of the key.
+    pub fn to_blob(&self) -> Vec<u8> {
+        let mut w = SshWriter::default();
+        w.string(self.key_type());
+        match self {
+            Self::Rsa(key) => {
+                w.mpint(key.e()).mpint(key.n());
+            }
+            Self::Ed25519(pk) => {
+                w.string(ED25519.encode_point(pk));
+            }
+        }
+        w.0
+    }
+
+    pub fn from_blob(blob: &[u8]) -> Result<Self, SshError> {
+        let mut r = SshReader(blob);
+        let key = Self::read(&mut r)?;
+        r.finish()?;
+        Ok(key)
+    }
+
+    fn read(r: &mut SshReader) -> Result<Self, SshError> {
+        match r.str()? {
+            SSH_RSA => {
+                let e = r.mpint()?;
+                let n = r.mpint()?;
+                Ok(Self::Rsa(PublicRsaKey::new(e, n)))
+            }
+            SSH_ED25519 => Ok(Self::Ed25519(
+                ED25519
+                    .decode_point(r.string()?)
+                    .ok_or(SshError::InvalidKey)?,
+            )),
+            t => Err(SshError::UnsupportedKeyType(t.to_owned())),
+        }
+    }
+
+    /// The `authorized_keys` line: `<type> <base64 blob> <comment>`.
+    pub fn to_line(&self, comment: &str) -> String {
+        let mut line = format!(
+            "{} {}",
+            self.key_type(),
+            base64_encode(&self.to_blob(), BASE64, true)
+        );
+        if !comment.is_empty() {
+            line.push(' ');
+            line.push_str(comment);
+        }
+        line
+    }
+
+    /// Parses an `authorized_keys` line, returns the key and the comment.
+    pub fn from_line(line: &str) -> Result<(Self, String), SshError> {
+        let mut parts = line.trim().splitn(3, char::is_whitespace);
+        let key_type = parts.next().ok_or(SshError::InvalidFormat)?;
+        let blob = parts.next().ok_or(SshError::InvalidFormat)?;
+        let comment = parts.next().unwrap_or("").trim().to_owned();
+        let blob = base64_decode(blob, BASE64).ok_or(SshError::InvalidFormat)?;
+        let key = Self::from_blob(&blob)?;
+        if key.key_type() != key_type {
+            return Err(SshError::InvalidFormat);
+        }
+        Ok((key, comment))
+    }
+}
+
+impl SshSecretKey {
+    pub fn from_ed25519_seed(seed: [u8; 32]) -> Self {
+        Self::Ed25519 {
+            pk: ed25519_pub_key(&seed),
+            seed,
+        }
+    }
+
+    pub fn pub_key(&self) -> SshPublicKey {
+        match self {
+            Self::Rsa(key) => SshPublicKey::Rsa(key.pub_key()),
+            Self::Ed25519 { pk, .. } => SshPublicKey::Ed25519(pk.clone()),
+        }
+    }
+
+    /// The secret scalar of an Ed25519 key, to be used with the `ecc` signing functions.
+    pub fn ed25519_scalar(&self) -> Option<BigInt> {
+        match self {
+            Self::Ed25519 { seed, .. } => Some(ed25519_expand(seed).0),
+            _ => None,
+        }
+    }
+
+    fn write_private(&self, w: &mut SshWriter) {
+        match self {
+            Self::Rsa(key) => {
+                let pub_key = key.pub_key();
+                let (p, q) = key.primes();
+                w.string(SSH_RSA)
+                    .mpint(pub_key.n())
+                    .mpint(pub_key.e())
+                    .mpint(key.d())
+                    .mpint(key.qinv())
+                    .mpint(p)
+                    .mpint(q);
+            }
+            Self::Ed25519 { seed, pk } => {
+                let pk = ED25519.encode_point(pk);
+                w.string(SSH_ED25519)
+                    .string(&pk)
+                    .string([&seed[..], &pk].concat());
+            }
+        }
+    }
+
+    fn read_private(r: &mut SshReader) -> Result<Self, SshError> {
+        match r.str()? {
+            SSH_RSA => {
+                let n = r.mpint()?;
+                let e = r.mpint()?;
+                let d = r.mpint()?;
+                let qinv = r.mpint()?;
+                let p = r.mpint()?;
+                let q = r.mpint()?;
+                let key = SecretRsaKey::from_components(n, e, d, p, q);
+                if *key.qinv() != qinv {
+                    return Err(SshError::InvalidKey);
+                }
+                Ok(Self::Rsa(key))
+            }
+            SSH_ED25519 => {
+                let pk = r.string()?;
+                let sk = r.string()?;
+                if sk.len() != 64 || &sk[32..] != pk {
+                    return Err(SshError::InvalidKey);
+                }
+                let key = Self::from_ed25519_seed(sk[..32].try_into().unwrap());
+                match &key {
+                    Self::Ed25519 { pk: derived, .. } if ED25519.encode_point(derived) == pk => {
+                        Ok(key)
+                    }
+                    _ => Err(SshError::InvalidKey),
+                }
+            }
+            t => Err(SshError::UnsupportedKeyType(t.to_owned())),
+        }
+    }
+
+    /// Encodes the key in the `openssh-key-v1` format, PEM armored.
+    /// With a passphrase the key is encrypted with aes256-ctr and a bcrypt-pbkdf derived key.
+    pub fn to_openssh<R: Rng + CryptoRng>(
+        &self,
+        comment: &str,
+        passphrase: Option<&str>,
+        crng: &mut R,
+    ) -> String {
+        let check: u32 = crng.gen();
+        let mut private = SshWriter::default();
+        private.u32(check).u32(check);
+        self.write_private(&mut private);
+        private.string(comment);
+        let block_size = if passphrase.is_some() { 16 } else { 8 };
+        let mut pad = 1;
+        while private.0.len() % block_size != 0 {
+            private.0.push(pad);
+            pad += 1;
+        }
+        let mut private = private.0;
+
+        let mut w = SshWriter::default();
+        w.0.extend_from_slice(AUTH_MAGIC);
+        match passphrase {
+            Some(passphrase) => {
+                let mut salt = [0; 16];
+                crng.fill_bytes(&mut salt);
+                let mut kdf_options = SshWriter::default();
+                kdf_options.string(salt).u32(BCRYPT_ROUNDS);
+                let mut key_iv = [0; 48];
+                bcrypt_pbkdf::bcrypt_pbkdf(passphrase, &salt, BCRYPT_ROUNDS, &mut key_iv).unwrap();
+                Aes256Ctr::new(key_iv[..32].into(), key_iv[32..].into())
+                    .apply_keystream(&mut private);
+                w.string(CIPHER_AES256_CTR)
+                    .string(KDF_BCRYPT)
+                    .string(kdf_options.0);
+            }
+            None => {
+                w.string(CIPHER_NONE).string(KDF_NONE).string([]);
+            }
+        }
+        w.u32(1).string(self.pub_key().to_blob()).string(private);
+        pem_encode(PEM_LABEL, &w.0)
+    }
+
+    /// Decodes a PEM armored `openssh-key-v1` key, returns the key and its comment.
+    pub fn from_openssh(pem: &str, passphrase: Option<&str>) -> Result<(Self, String), SshError> {
+        let (label, data) = pem_decode(pem).map_err(|_| SshError::InvalidFormat)?;
+        if label != PEM_LABEL || !data.starts_with(AUTH_MAGIC) {
+            return Err(SshError::InvalidFormat);
+        }
+        let mut r = SshReader(&data[AUTH_MAGIC.len()..]);
+        let cipher = r.str()?;
+        let kdf = r.str()?;
+        let kdf_options = r.string()?;
+        if r.u32()? != 1 {
+            return Err(SshError::InvalidFormat);
+        }
+        let pub_key = SshPublicKey::from_blob(r.string()?)?;
+        let mut private = r.string()?.to_vec();
+        r.finish()?;
+
+        match (cipher, kdf) {
+            (CIPHER_NONE, KDF_NONE) => {}
+            (CIPHER_AES256_CTR, KDF_BCRYPT) => {
+                let passphrase = passphrase.ok_or(SshError::MissingPassphrase)?;
+                let mut r = SshReader(kdf_options);
+                let salt = r.string()?;
+                let rounds = r.u32()?;
+                r.finish()?;
+                if MAX_BCRYPT_ROUNDS < rounds {
+                    return Err(SshError::TooManyRounds(rounds));
+                }
+                if private.len() % 16 != 0 {
+                    return Err(SshError::InvalidFormat);
+                }
+                let mut key_iv = [0; 48];
+                bcrypt_pbkdf::bcrypt_pbkdf(passphrase, salt, rounds, &mut key_iv)
+                    .map_err(|_| SshError::InvalidFormat)?;
+                Aes256Ctr::new(key_iv[..32].into(), key_iv[32..].into())
+                    .apply_keystream(&mut private);
+            }
+            (CIPHER_NONE | CIPHER_AES256_CTR, kdf) => {
+                return Err(SshError::UnsupportedKdf(kdf.to_owned()))
+            }
+            (cipher, _) => return Err(SshError::UnsupportedCipher(cipher.to_owned())),
+        }
+
+        let mut r = SshReader(&private);
+        if r.u32()? != r.u32()? {
+            return Err(if passphrase.is_some() {
+                SshError::WrongPassphrase
+            } else {
+                SshError::InvalidFormat
+            });
+        }
+        let key = Self::read_private(&mut r)?;
+        let comment =
+            String::from_utf8(r.string()?.to_vec()).map_err(|_| SshError::InvalidFormat)?;
+        if r.0.iter().zip(1..).any(|(&b, i)| b != i) {
+            return Err(SshError::InvalidFormat);
+        }
+        if key.pub_key().to_blob() != pub_key.to_blob() {
+            return Err(SshError::InvalidKey);
+        }
+        Ok((key, comment))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ecc::{ed25519_sign, ed25519_verify};
+    use rand::rngs::StdRng;
+
+    /// Keys written by OpenSSH 9.2 `ssh-keygen`, the encrypted ones with the passphrase
+    /// PASSPHRASE, aes256-ctr and 16 bcrypt-pbkdf rounds.
+    const RSA: &str = include_str!("../testdata/ssh-rsa");
+    const RSA_ENCRYPTED: &str = include_str!("../testdata/ssh-rsa-enc");
+    const RSA_PUB: &str = include_str!("../testdata/ssh-rsa.pub");
+    const ED25519_KEY: &str = include_str!("../testdata/ssh-ed25519");
+    const ED25519_ENCRYPTED: &str = include_str!("../testdata/ssh-ed25519-enc");
+    const ED25519_PUB: &str = include_str!("../testdata/ssh-ed25519.pub");
+    const PASSPHRASE: &str = "correct horse";
+
+    #[test]
+    fn ssh_keygen_keys() {
+        for (pem, passphrase, pub_line, comment) in [
+            (RSA, None, RSA_PUB, "rsa@co2"),
+            (RSA_ENCRYPTED, Some(PASSPHRASE), RSA_PUB, "rsa@co2"),
+            (ED25519_KEY, None, ED25519_PUB, "ed25519@co2"),
+            (
+                ED25519_ENCRYPTED,
+                Some(PASSPHRASE),
+                ED25519_PUB,
+                "ed25519@co2",
+            ),
+        ] {
+            let (key, c) = SshSecretKey::from_openssh(pem, passphrase).unwrap();
+            assert_eq!(c, comment);
+            assert_eq!(key.pub_key().to_line(comment), pub_line.trim_end());
+            let (pub_key, c) = SshPublicKey::from_line(pub_line).unwrap();
+            assert_eq!(c, comment);
+            assert_eq!(pub_key.to_blob(), key.pub_key().to_blob());
+
+            match &key {
+                SshSecretKey::Rsa(key) => key.validate(&mut StdRng::seed_from_u64(0)).unwrap(),
+                SshSecretKey::Ed25519 { seed, pk } => {
+                    assert!(ed25519_verify(
+                        pk,
+                        b"message",
+                        &ed25519_sign(seed, b"message")
+                    ))
+                }
+            }
+
+            // Written back and read again, both in the clear and encrypted.
+            let mut crng = StdRng::seed_from_u64(0);
+            for passphrase in [None, Some("another passphrase")] {
+                let pem = key.to_openssh(comment, passphrase, &mut crng);
+                let (decoded, c) = SshSecretKey::from_openssh(&pem, passphrase).unwrap();
+                assert_eq!(c, comment);
+                assert_eq!(decoded.pub_key().to_blob(), key.pub_key().to_blob());
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_wrong_passphrases() {
+        for pem in [RSA_ENCRYPTED, ED25519_ENCRYPTED] {
+            assert_eq!(
+                SshSecretKey::from_openssh(pem, None).err(),
+                Some(SshError::MissingPassphrase)
+            );
+            assert_eq!(
+                SshSecretKey::from_openssh(pem, Some("wrong horse")).err(),
+                Some(SshError::WrongPassphrase)
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_too_many_rounds() {
+        let (_, mut data) = pem_decode(ED25519_ENCRYPTED).unwrap();
+        // The rounds follow the cipher and KDF names, the options length and the 16 byte salt.
+        let offset =
+            AUTH_MAGIC.len() + 4 + CIPHER_AES256_CTR.len() + 4 + KDF_BCRYPT.len() + 4 + 4 + 16;
+        assert_eq!(data[offset..offset + 4], 16u32.to_be_bytes());
+        for (rounds, result) in [
+            (
+                MAX_BCRYPT_ROUNDS + 1,
+                SshError::TooManyRounds(MAX_BCRYPT_ROUNDS + 1),
+            ),
+            (u32::MAX, SshError::TooManyRounds(u32::MAX)),
+            // Other round counts give another key.
+            (17, SshError::WrongPassphrase),
+        ] {
+            data[offset..offset + 4].copy_from_slice(&rounds.to_be_bytes());
+            let pem = pem_encode(PEM_LABEL, &data);
+            assert_eq!(
+                SshSecretKey::from_openssh(&pem, Some(PASSPHRASE)).err(),
+                Some(result)
+            );
+        }
+    }
+}
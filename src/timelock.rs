@@ -0,0 +1,126 @@
+//! Time-lock puzzles (Rivest, Shamir and Wagner), secrets that can only be recovered with t
+//! sequential squarings mod an RSA modulus.
+use crate::chacha::{chacha20_poly1305_open, chacha20_poly1305_seal};
+use crate::common::i2osp;
+use crate::rsa::{gen_prime, MIN_BITS};
+use digest::Digest;
+use num::{bigint::RandBigInt, BigInt, Integer, One};
+use rand::prelude::*;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+
+/// The number of squarings between the calls to the progress callback of `solve`.
+pub const PROGRESS_STEP: u64 = 1 << 16;
+/// The key of every puzzle is used once, so the nonce is fixed.
+const NONCE: [u8; 12] = [0; 12];
+
+/// The secret encrypted under a key derived from a^(2^t) mod n.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Puzzle {
+    pub n: BigInt,
+    pub a: BigInt,
+    pub t: u64,
+    pub c: Vec<u8>,
+}
+
+/// The ChaCha20-Poly1305 key of the puzzle, the SHA-256 hash of b = a^(2^t) mod n.
+fn derive_key(b: &BigInt, n: &BigInt) -> [u8; 32] {
+    let k = n.bits().div_ceil(8) as usize;
+    Sha256::digest(i2osp(b, k).unwrap()).into()
+}
+
+/// Creates a puzzle for the secret that takes t squarings mod a `bits` bit modulus to solve.
+/// The creator knows φ(n), so it computes a^(2^t) as a^(2^t mod φ(n)) quickly, then forgets the
+/// factorization.
+pub fn create_puzzle<R: RandBigInt, CR: RandBigInt + CryptoRng>(
+    bits: u64,
+    t: u64,
+    secret: &[u8],
+    rng: &mut R,
+    crng: &mut CR,
+) -> Puzzle {
+    assert!(
+        MIN_BITS <= bits && bits.is_even(),
+        "time-lock moduli must have an even size of at least {} bits",
+        MIN_BITS
+    );
+    let (p, q) = loop {
+        let p = gen_prime(bits / 2, rng, crng);
+        let q = gen_prime(bits / 2, rng, crng);
+        if p != q {
+            break (p, q);
+        }
+    };
+    let n = &p * &q;
+    let phi = (p - 1u8) * (q - 1u8);
+    let a = loop {
+        let a = crng.gen_bigint_range(&2.into(), &(&n - 1u8));
+        if a.gcd(&n).is_one() {
+            break a;
+        }
+    };
+    let e = BigInt::from(2).modpow(&t.into(), &phi);
+    let b = a.modpow(&e, &n);
+    let c = chacha20_poly1305_seal(&derive_key(&b, &n), &NONCE, &[], secret);
+    Puzzle { n, a, t, c }
+}
+
+/// Solves the puzzle with t sequential squarings, calling `progress` with the number of
+/// squarings done every `PROGRESS_STEP` squarings, and with t at the end unless t is a multiple
+/// of `PROGRESS_STEP`, so every count is reported once.
+/// Returns `None` if the result doesn't decrypt the secret.
+pub fn solve<F: FnMut(u64)>(puzzle: &Puzzle, mut progress: F) -> Option<Vec<u8>> {
+    let Puzzle { n, a, t, c } = puzzle;
+    let mut b = a.mod_floor(n);
+    for i in 1..=*t {
+        b = (&b * &b) % n;
+        if i % PROGRESS_STEP == 0 {
+            progress(i);
+        }
+    }
+    if t % PROGRESS_STEP != 0 {
+        progress(*t);
+    }
+    chacha20_poly1305_open(&derive_key(&b, n), &NONCE, &[], c)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn solve_logged(puzzle: &Puzzle) -> (Option<Vec<u8>>, Vec<u64>) {
+        let mut calls = vec![];
+        let secret = solve(puzzle, |i| calls.push(i));
+        (secret, calls)
+    }
+
+    #[test]
+    fn create_and_solve() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut crng = StdRng::seed_from_u64(1);
+        for (t, expected_calls) in [
+            (0, vec![]),
+            (5, vec![5]),
+            (PROGRESS_STEP, vec![PROGRESS_STEP]),
+            (
+                2 * PROGRESS_STEP + 3,
+                vec![PROGRESS_STEP, 2 * PROGRESS_STEP, 2 * PROGRESS_STEP + 3],
+            ),
+        ] {
+            let puzzle = create_puzzle(2048, t, b"secret", &mut rng, &mut crng);
+            assert_eq!(
+                solve_logged(&puzzle),
+                (Some(b"secret".to_vec()), expected_calls)
+            );
+        }
+
+        let puzzle = create_puzzle(2048, 10, b"secret", &mut rng, &mut crng);
+        let early = Puzzle {
+            t: 9,
+            ..puzzle.clone()
+        };
+        assert_eq!(solve_logged(&early), (None, vec![9]));
+        let late = Puzzle { t: 11, ..puzzle };
+        assert_eq!(solve_logged(&late).0, None);
+    }
+}
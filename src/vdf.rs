@@ -0,0 +1,241 @@
+//! Verifiable delay functions over an RSA group: y = x^(2^t) mod n takes t sequential squarings,
+//! and a Wesolowski or Pietrzak proof lets anyone check y quickly.
+//!
+//! The group elements are the classes {x, -x} of the integers coprime to n, represented by the
+//! smaller one. This removes the element -1 of known order.
+use crate::common::{i2osp, os2ip};
+use crate::primes::baillie_psw;
+use crate::rsa::{gen_rsa_key_with, pss::mgf1, KeyGenOptions, PrimeKind};
+use digest::Digest;
+use num::{bigint::RandBigInt, BigInt, Integer, One};
+use rand::prelude::*;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+
+/// The bit length of the Wesolowski prime and of the Pietrzak challenges.
+const CHALLENGE_BITS: u64 = 128;
+
+/// An RSA modulus whose factorization is unknown to everyone using it.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Vdf {
+    n: BigInt,
+}
+
+impl Vdf {
+    /// A VDF over a fresh `bits` bit RSA modulus, the secret key is dropped right away. Whoever
+    /// runs this is trusted to not keep the factors.
+    pub fn setup<R: RandBigInt, CR: RandBigInt + CryptoRng>(
+        bits: u64,
+        rng: &mut R,
+        crng: &mut CR,
+    ) -> Self {
+        let opts = KeyGenOptions {
+            bits,
+            primes: PrimeKind::Probable,
+        };
+        let n = gen_rsa_key_with(&opts, rng, crng).pub_key().n().clone();
+        Self { n }
+    }
+
+    /// A VDF over a modulus from elsewhere, e.g. the RSA-2048 challenge number.
+    pub fn new(n: BigInt) -> Self {
+        Self { n }
+    }
+
+    pub fn n(&self) -> &BigInt {
+        &self.n
+    }
+
+    fn size(&self) -> usize {
+        self.n.bits().div_ceil(8) as usize
+    }
+
+    /// The representative of the class {x, -x}.
+    fn norm(&self, x: BigInt) -> BigInt {
+        let x = x.mod_floor(&self.n);
+        let neg = &self.n - &x;
+        x.min(neg)
+    }
+
+    fn mul(&self, a: &BigInt, b: &BigInt) -> BigInt {
+        self.norm(a * b)
+    }
+
+    fn pow(&self, a: &BigInt, e: &BigInt) -> BigInt {
+        self.norm(a.modpow(e, &self.n))
+    }
+
+    /// Whether x is the representative of a group element.
+    fn is_element(&self, x: &BigInt) -> bool {
+        &BigInt::from(0) < x && *x == self.norm(x.clone()) && x.gcd(&self.n).is_one()
+    }
+
+    fn square_n(&self, x: &BigInt, t: u64) -> BigInt {
+        let mut y = x.clone();
+        for _ in 0..t {
+            y = (&y * &y) % &self.n;
+        }
+        self.norm(y)
+    }
+
+    /// Hashes the input into the group with MGF1 over SHA-256.
+    pub fn hash_to_group(&self, input: &[u8]) -> BigInt {
+        let mut h = Sha256::new();
+        let seed = Sha256::new().chain_update(input).finalize();
+        let x = self.norm(os2ip(&mgf1(&mut h, &seed, self.size() + 16)));
+        if self.is_element(&x) {
+            x
+        } else {
+            // Only happens if the hash reveals a factor of n.
+            self.hash_to_group(&seed)
+        }
+    }
+
+    /// Computes y = x^(2^t) with t sequential squarings.
+    pub fn eval(&self, x: &BigInt, t: u64) -> BigInt {
+        self.square_n(&self.norm(x.clone()), t)
+    }
+
+    /// The SHA-256 hash of the elements and t, truncated to `CHALLENGE_BITS` bits.
+    fn challenge(&self, elements: &[&BigInt], t: u64, counter: u64) -> BigInt {
+        let mut h = Sha256::new();
+        for x in elements {
+            h.update(i2osp(x, self.size()).unwrap());
+        }
+        h.update(t.to_be_bytes());
+        h.update(counter.to_be_bytes());
+        os2ip(&h.finalize()[..CHALLENGE_BITS as usize / 8])
+    }
+
+    /// The Wesolowski prime ℓ: the first challenge of x, y and t with a counter that has the top
+    /// bit set and passes Baillie-PSW.
+    fn hash_to_prime(&self, x: &BigInt, y: &BigInt, t: u64) -> BigInt {
+        (0..)
+            .map(|counter| {
+                self.challenge(&[x, y], t, counter) | (BigInt::one() << (CHALLENGE_BITS - 1))
+            })
+            .find(baillie_psw)
+            .unwrap()
+    }
+
+    /// The Wesolowski proof π = x^⌊2^t / ℓ⌋ of y = x^(2^t), computed with t more squarings by
+    /// long division of 2^t by ℓ.
+    pub fn prove_wesolowski(&self, x: &BigInt, y: &BigInt, t: u64) -> BigInt {
+        let x = self.norm(x.clone());
+        let l = self.hash_to_prime(&x, y, t);
+        let mut pi = BigInt::one();
+        let mut r = BigInt::one();
+        for _ in 0..t {
+            r <<= 1u8;
+            pi = (&pi * &pi) % &self.n;
+            if l <= r {
+                r -= &l;
+                pi = (pi * &x) % &self.n;
+            }
+        }
+        self.norm(pi)
+    }
+
+    /// Checks π^ℓ x^(2^t mod ℓ) = y, two short exponentiations.
+    pub fn verify_wesolowski(&self, x: &BigInt, y: &BigInt, t: u64, pi: &BigInt) -> bool {
+        if !self.is_element(x) || !self.is_element(y) || !self.is_element(pi) {
+            return false;
+        }
+        let l = self.hash_to_prime(x, y, t);
+        let r = BigInt::from(2).modpow(&t.into(), &l);
+        self.mul(&self.pow(pi, &l), &self.pow(x, &r)) == *y
+    }
+
+    /// The Pietrzak proof of y = x^(2^t): while t > 1, an odd t is made even by squaring y, then
+    /// μ = x^(2^(t/2)) halves the statement to x' = x^r μ, y' = μ^r y with a hashed challenge r.
+    /// The proof is the list of the μ.
+    pub fn prove_pietrzak(&self, x: &BigInt, y: &BigInt, t: u64) -> Vec<BigInt> {
+        let (mut x, mut y, mut t) = (self.norm(x.clone()), y.clone(), t);
+        let mut proof = vec![];
+        while 1 < t {
+            if t.is_odd() {
+                y = self.mul(&y, &y);
+                t += 1;
+            }
+            let mu = self.square_n(&x, t / 2);
+            let r = self.challenge(&[&x, &y, &mu], t, 0);
+            x = self.mul(&self.pow(&x, &r), &mu);
+            y = self.mul(&self.pow(&mu, &r), &y);
+            t /= 2;
+            proof.push(mu);
+        }
+        proof
+    }
+
+    /// Replays the halving with the μ of the proof, about log2(t) short exponentiations.
+    pub fn verify_pietrzak(&self, x: &BigInt, y: &BigInt, t: u64, proof: &[BigInt]) -> bool {
+        if !self.is_element(x) || !self.is_element(y) {
+            return false;
+        }
+        let (mut x, mut y, mut t) = (x.clone(), y.clone(), t);
+        let mut proof = proof.iter();
+        while 1 < t {
+            if t.is_odd() {
+                y = self.mul(&y, &y);
+                t += 1;
+            }
+            let Some(mu) = proof.next() else {
+                return false;
+            };
+            if !self.is_element(mu) {
+                return false;
+            }
+            let r = self.challenge(&[&x, &y, mu], t, 0);
+            x = self.mul(&self.pow(&x, &r), mu);
+            y = self.mul(&self.pow(mu, &r), &y);
+            t /= 2;
+        }
+        proof.next().is_none() && self.square_n(&x, t) == y
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rsa::PublicRsaKey;
+
+    fn test_vdf() -> Vdf {
+        let pk = PublicRsaKey::from_pem(include_str!("../testdata/rsa-2048-pub.pem")).unwrap();
+        Vdf::new(pk.n().clone())
+    }
+
+    #[test]
+    fn proofs() {
+        let vdf = test_vdf();
+        let x = vdf.hash_to_group(b"input");
+        assert!(vdf.is_element(&x));
+        for t in [0, 1, 2, 3, 7, 64, 1001] {
+            let y = vdf.eval(&x, t);
+            assert_eq!(y, vdf.norm(x.modpow(&(BigInt::one() << t), vdf.n())));
+
+            let pi = vdf.prove_wesolowski(&x, &y, t);
+            assert!(vdf.verify_wesolowski(&x, &y, t, &pi), "t = {}", t);
+            let proof = vdf.prove_pietrzak(&x, &y, t);
+            assert!(proof.len() <= 64 - t.leading_zeros() as usize);
+            assert!(vdf.verify_pietrzak(&x, &y, t, &proof), "t = {}", t);
+
+            // a wrong y, t or proof
+            let wrong_y = vdf.mul(&y, &x);
+            assert!(!vdf.verify_wesolowski(&x, &wrong_y, t, &pi));
+            assert!(!vdf.verify_pietrzak(&x, &wrong_y, t, &proof));
+            assert!(!vdf.verify_wesolowski(&x, &y, t + 1, &pi));
+            assert!(!vdf.verify_pietrzak(&x, &y, t + 1, &proof));
+            assert!(!vdf.verify_wesolowski(&x, &y, t, &vdf.mul(&pi, &x)));
+            // the other representative of y isn't an element
+            assert!(!vdf.verify_wesolowski(&x, &(vdf.n() - &y), t, &pi));
+            if let Some((_, truncated)) = proof.split_last() {
+                assert!(!vdf.verify_pietrzak(&x, &y, t, truncated));
+                let mut tampered = proof.clone();
+                tampered[0] = vdf.mul(&tampered[0], &x);
+                assert!(!vdf.verify_pietrzak(&x, &y, t, &tampered));
+            }
+            let extended = [proof, vec![x.clone()]].concat();
+            assert!(!vdf.verify_pietrzak(&x, &y, t, &extended));
+        }
+    }
+}